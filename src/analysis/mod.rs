@@ -0,0 +1,18 @@
+//! # Analysis of Parsed Bridge Pool Assignments
+//!
+//! This module provides pure functions computing reports over already parsed bridge pool assignment
+//! files, independent of any export backend.
+//!
+//! ## Usage
+//!
+//! Parse files with [`parse_bridge_pool_files`](crate::parse::parse_bridge_pool_files) and pass the
+//! result to a report function, e.g. `recurring_assignments` to see how often identical assignment
+//! lines recur across files.
+//!
+//! ## Submodules
+//!
+//! - **recurring**: Counts identical assignment lines across files.
+
+mod recurring;
+
+pub use recurring::recurring_assignments;
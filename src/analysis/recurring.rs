@@ -0,0 +1,69 @@
+use crate::parse::ParsedBridgePoolAssignment;
+use std::collections::BTreeMap;
+
+/// Counts how often each assignment line occurs across the given files.
+///
+/// Assignment digests mix in the file digest, so the same bridge with the same assignment in two
+/// files is exported as two rows. This report instead groups by the line itself
+/// (e.g. "005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4"), rebuilt exactly from the
+/// parsed fingerprint and assignment string. A file lists each fingerprint at most once, so a count
+/// is the number of files containing the line.
+///
+/// # Arguments
+///
+/// * `parsed` - The parsed files.
+///
+/// # Returns
+///
+/// A map of each assignment line to the number of files it occurs in. Lines found in a single file
+/// are included with a count of 1.
+pub fn recurring_assignments(parsed: &[ParsedBridgePoolAssignment]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for file in parsed {
+        for (fingerprint, assignment) in &file.entries {
+            *counts.entry(format!("{} {}", fingerprint, assignment)).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::BridgePoolFile;
+    use crate::parse::parse_bridge_pool_files;
+
+    /// Tests that a line found in two files is counted twice, and a line whose assignment changed
+    /// between the files is counted once per variant.
+    #[test]
+    fn test_recurring_assignments() {
+        let file = |path: &str, content: &str| BridgePoolFile {
+            path: path.to_string(),
+            last_modified: 0,
+            content: content.to_string(),
+            raw_content: content.as_bytes().to_vec(),
+        };
+        let parsed = parse_bridge_pool_files(vec![
+            file(
+                "file1",
+                "bridge-pool-assignment 2022-04-09 00:29:37\n\
+                    005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n\
+                    01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https\n",
+            ),
+            file(
+                "file2",
+                "bridge-pool-assignment 2022-04-09 00:59:37\n\
+                    005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n\
+                    01ea4fb2da2086e71e7ca84c683fcadd2aa9036b moat\n",
+            ),
+        ])
+        .unwrap();
+
+        let counts = recurring_assignments(&parsed);
+
+        assert_eq!(counts["005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4"], 2);
+        assert_eq!(counts["01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https"], 1);
+        assert_eq!(counts["01ea4fb2da2086e71e7ca84c683fcadd2aa9036b moat"], 1);
+        assert_eq!(counts.len(), 3);
+    }
+}
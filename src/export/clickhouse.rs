@@ -0,0 +1,356 @@
+use super::types::{EmptyFile, ExportOptions, ExportSummary, Exporter, FingerprintFilter};
+use crate::parse::{AssignmentParser, DefaultAssignmentParser, ParsedBridgePoolAssignment};
+use crate::utils::datetime_from_millis;
+use anyhow::{Context, Result as AnyhowResult};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// DDL of the ClickHouse tables, partitioned by publication month.
+///
+/// Assignments are ordered by `(fingerprint, published)`, so the history of a single bridge is stored
+/// contiguously, which is the access pattern of most time-series queries.
+const CREATE_TABLES: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS bridge_pool_assignments_file (
+        published DateTime64(3, 'UTC'),
+        header String,
+        digest FixedString(64),
+        freshness LowCardinality(String)
+    ) ENGINE = MergeTree
+    PARTITION BY toYYYYMM(published)
+    ORDER BY (published, digest)",
+    "CREATE TABLE IF NOT EXISTS bridge_pool_assignment (
+        published DateTime64(3, 'UTC'),
+        digest FixedString(64),
+        fingerprint FixedString(40),
+        distribution_method LowCardinality(String),
+        transport LowCardinality(Nullable(String)),
+        ip Nullable(String),
+        blocklist Nullable(String),
+        blocklist_countries Array(LowCardinality(String)),
+        bridge_pool_assignments FixedString(64),
+        distributed Bool,
+        state Nullable(String),
+        bandwidth Nullable(String),
+        bandwidth_bytes Nullable(Int64),
+        ratio Nullable(Float32)
+    ) ENGINE = MergeTree
+    PARTITION BY toYYYYMM(published)
+    ORDER BY (fingerprint, published)",
+];
+
+/// A row of `bridge_pool_assignments_file`, serialized as `JSONEachRow`.
+#[derive(Debug, Serialize)]
+struct FileRow<'a> {
+    published: String,
+    header: &'a str,
+    digest: &'a str,
+    freshness: &'static str,
+}
+
+/// A row of `bridge_pool_assignment`, serialized as `JSONEachRow`.
+#[derive(Debug, Serialize)]
+struct AssignmentRow<'a> {
+    published: &'a str,
+    digest: String,
+    fingerprint: &'a str,
+    distribution_method: String,
+    transport: Option<String>,
+    ip: Option<String>,
+    blocklist: Option<String>,
+    blocklist_countries: Vec<String>,
+    bridge_pool_assignments: &'a str,
+    distributed: bool,
+    state: Option<String>,
+    bandwidth: Option<String>,
+    bandwidth_bytes: Option<i64>,
+    ratio: Option<f32>,
+}
+
+/// An [`Exporter`] writing to ClickHouse, for large-scale analytical queries over assignments.
+///
+/// Talks to ClickHouse over its HTTP interface (`INSERT ... FORMAT JSONEachRow`) using the `reqwest`
+/// client the crate already depends on, so no native ClickHouse client is needed. Tables are created
+/// on first export with the same columns as the PostgreSQL schema, mapped to ClickHouse types.
+///
+/// `MergeTree` tables don't enforce unique keys, so unlike the PostgreSQL backend, re-exporting a file
+/// inserts its rows again; deduplicate at query time (e.g. `SELECT DISTINCT digest ...`) if needed.
+///
+/// Of the [`ExportOptions`], the header override, assignment parser and excluded fingerprints apply;
+/// [`ClickHouseExporter::with_export_options`] rejects the PostgreSQL-specific ones.
+#[derive(Debug, Clone)]
+pub struct ClickHouseExporter {
+    client: reqwest::Client,
+    url: String,
+    database: String,
+    credentials: Option<(String, String)>,
+    assignment_parser: Arc<dyn AssignmentParser>,
+    header: Option<String>,
+    excluded_fingerprints: FingerprintFilter,
+}
+
+impl ClickHouseExporter {
+    /// Creates an exporter for the ClickHouse HTTP endpoint at `url` (e.g. "http://localhost:8123"),
+    /// writing to `database`.
+    pub fn new(url: &str, database: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            database: database.to_string(),
+            credentials: None,
+            assignment_parser: Arc::new(DefaultAssignmentParser),
+            header: None,
+            excluded_fingerprints: FingerprintFilter::default(),
+        }
+    }
+
+    /// Authenticates as `user` with `password` instead of ClickHouse's default user.
+    pub fn with_credentials(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    /// Uses `parser` instead of the [`DefaultAssignmentParser`] for assignment strings.
+    pub fn with_assignment_parser(mut self, parser: Arc<dyn AssignmentParser>) -> Self {
+        self.assignment_parser = parser;
+        self
+    }
+
+    /// Applies the export options that ClickHouse supports: the header override, the assignment parser
+    /// and the excluded fingerprints.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The export options, e.g. as built from the command line.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ClickHouseExporter)` - The exporter using the options.
+    /// * `Err(anyhow::Error)` - An option that only applies to PostgreSQL differs from its default.
+    pub fn with_export_options(mut self, options: ExportOptions) -> AnyhowResult<Self> {
+        let defaults = ExportOptions::default();
+        let unsupported: Vec<&str> = [
+            ("clear", options.clear),
+            ("with_daily_summary", options.with_daily_summary),
+            ("batch_delay", options.batch_delay != defaults.batch_delay),
+            ("store_raw", options.store_raw),
+            ("verify_after_export", options.verify_after_export),
+            ("with_assignment_last_modified", options.with_assignment_last_modified),
+            ("schema", options.schema.is_some()),
+            ("insert_method", options.insert_method != defaults.insert_method),
+            ("column_mapping", !options.column_mapping.is_default()),
+            ("upsert_file_metadata", options.upsert_file_metadata),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect();
+        if !unsupported.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Export options not supported by the ClickHouse backend: {}",
+                unsupported.join(", ")
+            ));
+        }
+        self.header = options.header;
+        self.assignment_parser = options.assignment_parser;
+        self.excluded_fingerprints = options.excluded_fingerprints;
+        Ok(self)
+    }
+
+    /// Runs `query` with `body` appended as its data, returning the response text.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The SQL statement.
+    /// * `body` - Data for `INSERT ... FORMAT` statements; empty otherwise.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(String)` - The response body.
+    /// * `Err(anyhow::Error)` - The request failed or ClickHouse reported an error.
+    async fn execute(&self, query: &str, body: String) -> AnyhowResult<String> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .query(&[("database", self.database.as_str()), ("query", query)])
+            .body(body);
+        if let Some((user, password)) = &self.credentials {
+            request = request
+                .header("X-ClickHouse-User", user)
+                .header("X-ClickHouse-Key", password);
+        }
+        let response = request.send().await.context("Failed to reach ClickHouse")?;
+        let status = response.status();
+        let text = response.text().await.context("Failed to read ClickHouse response")?;
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("ClickHouse returned {}: {}", status, text.trim()));
+        }
+        Ok(text)
+    }
+
+    /// Inserts `rows` into `table` as `JSONEachRow`, doing nothing if there are none.
+    async fn insert<T: Serialize>(&self, table: &str, rows: &[T]) -> AnyhowResult<()> {
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut body = String::new();
+        for row in rows {
+            body.push_str(&serde_json::to_string(row).context("Failed to serialize row")?);
+            body.push('\n');
+        }
+        self.execute(&format!("INSERT INTO {} FORMAT JSONEachRow", table), body)
+            .await
+            .context(format!("Failed to insert into {}", table))?;
+        Ok(())
+    }
+
+    /// Creates the tables if needed and inserts the rows of every parsed file.
+    async fn write(
+        &self,
+        parsed_assignments: Vec<ParsedBridgePoolAssignment>,
+    ) -> AnyhowResult<ExportSummary> {
+        for statement in CREATE_TABLES {
+            self.execute(statement, String::new())
+                .await
+                .context("Failed to create ClickHouse tables")?;
+        }
+
+        let mut summary = ExportSummary::default();
+        for parsed in &parsed_assignments {
+            let published = datetime_from_millis(parsed.published_millis)
+                .context("Invalid published timestamp")?
+                .format("%Y-%m-%d %H:%M:%S%.3f")
+                .to_string();
+            let file_digest = parsed.file_digest.as_str();
+
+            let mut rows = Vec::with_capacity(parsed.entries.len());
+            for (fingerprint, assignment_str) in &parsed.entries {
+                if self.excluded_fingerprints.is_excluded(fingerprint) {
+                    continue;
+                }
+                let digest = parsed
+                    .assignment_digests
+                    .get(fingerprint)
+                    .context(format!("No assignment digest found for fingerprint: {}", fingerprint))?;
+                let assignment = self.assignment_parser.parse(assignment_str);
+                rows.push(AssignmentRow {
+                    published: &published,
+                    digest: digest.to_string(),
+                    fingerprint,
+                    distribution_method: assignment.distribution_method,
+                    transport: assignment.transport,
+                    ip: assignment.ip,
+                    blocklist: assignment.blocklist,
+                    blocklist_countries: assignment.blocklist_countries,
+                    bridge_pool_assignments: file_digest,
+                    distributed: assignment.distributed.unwrap_or(false),
+                    state: assignment.state,
+                    bandwidth: assignment.bandwidth,
+                    bandwidth_bytes: assignment.bandwidth_bytes,
+                    ratio: assignment.ratio,
+                });
+            }
+
+            self.insert(
+                "bridge_pool_assignments_file",
+                &[FileRow {
+                    published: published.clone(),
+                    header: self.header.as_deref().unwrap_or(parsed.header_or_default()),
+                    digest: file_digest,
+                    freshness: parsed.freshness.as_str(),
+                }],
+            )
+            .await?;
+            self.insert("bridge_pool_assignment", &rows).await?;
+
+            summary.files_exported += 1;
+            summary.assignments_exported += rows.len();
+            if parsed.entries.is_empty() {
+                summary.empty_files.push(EmptyFile::of(parsed));
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+impl Exporter for ClickHouseExporter {
+    fn export(
+        &mut self,
+        parsed_assignments: Vec<ParsedBridgePoolAssignment>,
+    ) -> BoxFuture<'_, AnyhowResult<ExportSummary>> {
+        Box::pin(async move { self.write(parsed_assignments).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::BridgePoolFile;
+    use crate::parse::parse_bridge_pool_files;
+
+    /// Tests that the header override and exclusions are applied, and that options only PostgreSQL
+    /// supports are rejected rather than ignored.
+    #[test]
+    fn test_with_export_options() {
+        let exporter = ClickHouseExporter::new("http://localhost:8123", "default")
+            .with_export_options(ExportOptions {
+                header: Some("bridge-pool-assignment".to_string()),
+                excluded_fingerprints: FingerprintFilter::new(["005fd4d7decbb250055b861579e6fdc79ad17bee"]).unwrap(),
+                ..ExportOptions::default()
+            })
+            .unwrap();
+        assert_eq!(exporter.header.as_deref(), Some("bridge-pool-assignment"));
+        assert!(exporter.excluded_fingerprints.is_excluded("005FD4D7DECBB250055B861579E6FDC79AD17BEE"));
+
+        let error = ClickHouseExporter::new("http://localhost:8123", "default")
+            .with_export_options(ExportOptions {
+                clear: true,
+                schema: Some("bpa".to_string()),
+                ..ExportOptions::default()
+            })
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Export options not supported by the ClickHouse backend: clear, schema"
+        );
+    }
+
+    /// Tests inserting a file and querying back the row counts.
+    #[tokio::test]
+    #[ignore = "requires ClickHouse; set TEST_CLICKHOUSE_URL"]
+    async fn test_clickhouse_export_round_trip() {
+        let url = std::env::var("TEST_CLICKHOUSE_URL").unwrap_or_else(|_| "http://localhost:8123".to_string());
+        let admin = ClickHouseExporter::new(&url, "default");
+        admin
+            .execute("DROP DATABASE IF EXISTS bpa_test", String::new())
+            .await
+            .unwrap();
+        admin.execute("CREATE DATABASE bpa_test", String::new()).await.unwrap();
+        let content = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+            005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n\
+            01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https blocklist=cn,ir\n";
+        let parsed = parse_bridge_pool_files(vec![BridgePoolFile {
+            path: "file1".to_string(),
+            last_modified: 0,
+            content: content.to_string(),
+            raw_content: content.as_bytes().to_vec(),
+        }])
+        .unwrap();
+        let mut exporter = ClickHouseExporter::new(&url, "bpa_test");
+
+        let summary = exporter.export(parsed).await.unwrap();
+
+        assert_eq!((summary.files_exported, summary.assignments_exported), (1, 2));
+        assert!(summary.empty_files.is_empty());
+        let count = |query: &'static str| {
+            let exporter = exporter.clone();
+            async move { exporter.execute(query, String::new()).await.unwrap().trim().to_string() }
+        };
+        assert_eq!(count("SELECT count() FROM bridge_pool_assignments_file").await, "1");
+        assert_eq!(count("SELECT count() FROM bridge_pool_assignment").await, "2");
+        assert_eq!(
+            count("SELECT arrayStringConcat(blocklist_countries, ',') FROM bridge_pool_assignment WHERE blocklist IS NOT NULL").await,
+            "cn,ir"
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use super::types::{EmptyFile, ExportSummary, Exporter};
+use crate::fetch::Freshness;
+use crate::parse::{AssignmentParser, BridgeAssignment, DefaultAssignmentParser, ParsedBridgePoolAssignment};
+use crate::utils::Digest;
+use anyhow::{Context, Result as AnyhowResult};
+use futures::future::BoxFuture;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// A file row held by the [`MemoryExporter`], mirroring `bridge_pool_assignments_file`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryFileRow {
+    /// SHA-256 digest of the file's raw content.
+    pub digest: Digest,
+    /// Publication timestamp in milliseconds since the Unix epoch.
+    pub published_millis: i64,
+    /// File header (e.g., "bridge-pool-assignment 2022-04-09 00:29:37").
+    pub header: String,
+    /// CollecTor freshness category of the source file.
+    pub freshness: Freshness,
+}
+
+/// An assignment row held by the [`MemoryExporter`], mirroring `bridge_pool_assignment`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryAssignmentRow {
+    /// SHA-256 digest of the raw line combined with the file digest.
+    pub digest: Digest,
+    /// Digest of the file this assignment belongs to.
+    pub file_digest: Digest,
+    /// Publication timestamp in milliseconds since the Unix epoch.
+    pub published_millis: i64,
+    /// Bridge fingerprint (40-character hex string).
+    pub fingerprint: String,
+    /// Structured assignment fields.
+    pub assignment: BridgeAssignment,
+}
+
+/// An [`Exporter`] keeping exported rows in memory.
+///
+/// Rows are keyed by digest and, like the PostgreSQL backend's `ON CONFLICT (digest) DO NOTHING`,
+/// rows whose digest is already present are skipped. Useful for dry runs and for testing the
+/// pipeline without a database.
+#[derive(Debug)]
+pub struct MemoryExporter {
+    /// Exported file rows, keyed by file digest.
+    pub files: BTreeMap<Digest, MemoryFileRow>,
+    /// Exported assignment rows, keyed by assignment digest.
+    pub assignments: BTreeMap<Digest, MemoryAssignmentRow>,
+    assignment_parser: Arc<dyn AssignmentParser>,
+}
+
+impl Default for MemoryExporter {
+    fn default() -> Self {
+        Self {
+            files: BTreeMap::new(),
+            assignments: BTreeMap::new(),
+            assignment_parser: Arc::new(DefaultAssignmentParser),
+        }
+    }
+}
+
+impl MemoryExporter {
+    /// Creates an empty in-memory exporter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses `parser` instead of the [`DefaultAssignmentParser`] for assignment strings.
+    pub fn with_assignment_parser(mut self, parser: Arc<dyn AssignmentParser>) -> Self {
+        self.assignment_parser = parser;
+        self
+    }
+
+    /// Stores the rows of the given assignments, skipping digests that are already present.
+    fn store(
+        &mut self,
+        parsed_assignments: Vec<ParsedBridgePoolAssignment>,
+    ) -> AnyhowResult<ExportSummary> {
+        let mut summary = ExportSummary::default();
+
+        for parsed in parsed_assignments {
+            let file_digest = parsed.file_digest.clone();
+            if !self.files.contains_key(&file_digest) {
+                self.files.insert(
+                    file_digest.clone(),
+                    MemoryFileRow {
+                        digest: file_digest.clone(),
+                        published_millis: parsed.published_millis,
+                        header: parsed.header_or_default().to_string(),
+                        freshness: parsed.freshness,
+                    },
+                );
+                summary.files_exported += 1;
+                if parsed.entries.is_empty() {
+                    summary.empty_files.push(EmptyFile::of(&parsed));
+                }
+            }
+
+            for (fingerprint, assignment_str) in &parsed.entries {
+                let digest = parsed
+                    .assignment_digests
+                    .get(fingerprint)
+                    .context(format!("No assignment digest found for fingerprint: {}", fingerprint))?
+                    .clone();
+                if self.assignments.contains_key(&digest) {
+                    continue;
+                }
+                self.assignments.insert(
+                    digest.clone(),
+                    MemoryAssignmentRow {
+                        digest,
+                        file_digest: file_digest.clone(),
+                        published_millis: parsed.published_millis,
+                        fingerprint: fingerprint.clone(),
+                        assignment: self.assignment_parser.parse(assignment_str),
+                    },
+                );
+                summary.assignments_exported += 1;
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+impl Exporter for MemoryExporter {
+    fn export(
+        &mut self,
+        parsed_assignments: Vec<ParsedBridgePoolAssignment>,
+    ) -> BoxFuture<'_, AnyhowResult<ExportSummary>> {
+        Box::pin(async move { self.store(parsed_assignments) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::compute_file_digest;
+    use crate::fetch::BridgePoolFile;
+    use crate::parse::parse_bridge_pool_files;
+
+    /// Tests that exporting the same file twice only writes its rows once.
+    #[tokio::test]
+    async fn test_memory_exporter_skips_existing_digests() {
+        let content = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+            005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n\
+            01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https\n";
+        let parse = || {
+            parse_bridge_pool_files(vec![BridgePoolFile {
+                path: "file1".to_string(),
+                last_modified: 0,
+                content: content.to_string(),
+                raw_content: content.as_bytes().to_vec(),
+            }])
+            .unwrap()
+        };
+        let mut exporter = MemoryExporter::new();
+
+        let first = exporter.export(parse()).await.unwrap();
+        let second = exporter.export(parse()).await.unwrap();
+
+        assert_eq!(
+            first,
+            ExportSummary {
+                files_exported: 1,
+                assignments_exported: 2,
+                empty_files: Vec::new(),
+            }
+        );
+        assert_eq!(second, ExportSummary::default());
+        assert_eq!(exporter.files.len(), 1);
+        assert_eq!(exporter.assignments.len(), 2);
+        let row = exporter
+            .assignments
+            .values()
+            .find(|row| row.fingerprint == "005fd4d7decbb250055b861579e6fdc79ad17bee")
+            .unwrap();
+        assert_eq!(row.assignment.transport.as_deref(), Some("obfs4"));
+        assert_eq!(row.file_digest, compute_file_digest(content.as_bytes()));
+    }
+
+    /// Tests that a header-only file is exported as a file row without assignment rows and reported
+    /// in `empty_files`.
+    #[tokio::test]
+    async fn test_memory_exporter_reports_empty_files() {
+        let content = "bridge-pool-assignment 2022-04-09 00:29:37\n";
+        let parsed = parse_bridge_pool_files(vec![BridgePoolFile {
+            path: "file1".to_string(),
+            last_modified: 0,
+            content: content.to_string(),
+            raw_content: content.as_bytes().to_vec(),
+        }])
+        .unwrap();
+        let mut exporter = MemoryExporter::new();
+
+        let summary = exporter.export(parsed).await.unwrap();
+
+        let digest = compute_file_digest(content.as_bytes());
+        assert_eq!(summary.files_exported, 1);
+        assert_eq!(summary.assignments_exported, 0);
+        assert_eq!(
+            summary.empty_files,
+            [EmptyFile {
+                digest: digest.to_string(),
+                published_millis: 1_649_464_177_000,
+            }]
+        );
+        assert!(exporter.files.contains_key(&digest));
+        assert!(exporter.assignments.is_empty());
+    }
+
+    /// A parser for an older format that named the transport `pt` instead of `transport`.
+    #[derive(Debug)]
+    struct LegacyTransportParser;
+
+    impl AssignmentParser for LegacyTransportParser {
+        fn parse(&self, assignment_str: &str) -> BridgeAssignment {
+            let mut assignment = DefaultAssignmentParser.parse(assignment_str);
+            assignment.transport = assignment_str
+                .split_whitespace()
+                .find_map(|pair| pair.strip_prefix("pt="))
+                .map(str::to_string);
+            assignment
+        }
+    }
+
+    /// Tests that a custom assignment parser is used for exported rows.
+    #[tokio::test]
+    async fn test_memory_exporter_uses_custom_assignment_parser() {
+        let content = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+            005fd4d7decbb250055b861579e6fdc79ad17bee email pt=obfs4\n";
+        let parse = || {
+            parse_bridge_pool_files(vec![BridgePoolFile {
+                path: "file1".to_string(),
+                last_modified: 0,
+                content: content.to_string(),
+                raw_content: content.as_bytes().to_vec(),
+            }])
+            .unwrap()
+        };
+        let mut default_exporter = MemoryExporter::new();
+        let mut legacy_exporter = MemoryExporter::new().with_assignment_parser(Arc::new(LegacyTransportParser));
+
+        default_exporter.export(parse()).await.unwrap();
+        legacy_exporter.export(parse()).await.unwrap();
+
+        let transport = |exporter: &MemoryExporter| exporter.assignments.values().next().unwrap().assignment.transport.clone();
+        assert_eq!(transport(&default_exporter), None);
+        assert_eq!(transport(&legacy_exporter).as_deref(), Some("obfs4"));
+    }
+}
@@ -7,13 +7,30 @@
 //! ## Usage
 //!
 //! The main entry point is the [`export_to_postgres`] function, which takes a vector of parsed assignments,
-//! a database connection string, and a flag to clear existing data. It establishes a connection, sets up tables,
-//! and inserts data in a single transaction.
+//! a database connection string, and an [`ExportOptions`] value (e.g. whether to clear existing data). It
+//! establishes a connection, sets up tables, and inserts data in a single transaction.
+//!
+//! Each backend implements the [`Exporter`] trait, which lets the pipeline export without knowing which
+//! backend was selected.
 //!
 //! ## Submodules
 //!
 //! - **postgres**: Contains PostgreSQL-specific export functionality.
+//! - **clickhouse**: Contains a ClickHouse backend for analytical workloads (`clickhouse` feature only).
+//! - **memory**: Contains an in-memory backend, useful for dry runs and tests.
+//! - **ndjson**: Contains a backend writing one NDJSON file per publication date.
+//! - **types**: Defines the exporter trait, its options, and the export summary.
 
+#[cfg(feature = "clickhouse")]
+mod clickhouse;
+mod memory;
+mod ndjson;
 mod postgres;
+mod types;
 
-pub use postgres::export_to_postgres; 
\ No newline at end of file
+#[cfg(feature = "clickhouse")]
+pub use clickhouse::ClickHouseExporter;
+pub use memory::{MemoryAssignmentRow, MemoryExporter, MemoryFileRow};
+pub use ndjson::NdjsonExporter;
+pub use postgres::{export_to_postgres, schema_ddl, PostgresExporter};
+pub use types::{ColumnMapping, EmptyFile, ExportOptions, ExportSummary, Exporter, FingerprintFilter, InsertMethod}; 
\ No newline at end of file
@@ -0,0 +1,475 @@
+use super::types::{EmptyFile, ExportSummary, Exporter};
+use crate::parse::{AssignmentParser, BridgeAssignment, DefaultAssignmentParser, ParsedBridgePoolAssignment};
+use crate::utils::{datetime_from_millis, Digest};
+use anyhow::{Context, Result as AnyhowResult};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+/// One line of an NDJSON output file: a single assignment with its file metadata.
+#[derive(Debug, Serialize)]
+struct NdjsonAssignmentLine<'a> {
+    /// Publication time of the file, formatted as "YYYY-MM-DD HH:MM:SS" (UTC).
+    published: &'a str,
+    /// SHA-256 digest of the file's raw content.
+    file_digest: &'a Digest,
+    /// SHA-256 digest of the raw line combined with the file digest.
+    digest: Digest,
+    /// Bridge fingerprint (40-character hex string).
+    fingerprint: &'a str,
+    /// Structured assignment fields.
+    #[serde(flatten)]
+    assignment: BridgeAssignment,
+}
+
+/// An [`Exporter`] writing assignments to one NDJSON file per publication date.
+///
+/// Each parsed file is appended to `<output_dir>/<YYYY-MM-DD>.ndjson`, one JSON object per assignment,
+/// so several files published on the same day end up in the same output file. This mirrors
+/// CollecTor's own one-file-per-document layout for tooling that expects per-date files.
+///
+/// Unlike the database backends, nothing is deduplicated: exporting the same file twice appends its
+/// assignments twice.
+///
+/// Output files are never left half-written: the lines of an export are grouped by date, and each
+/// date's lines are written to a `.tmp` sibling holding the previous content plus the new lines, which
+/// replaces the output file only once it has been fully written and synced. Each output file is thus
+/// copied and synced once per export, however many parsed files share its date. If writing fails (e.g.
+/// on a full disk), the temporary file is removed and the output file keeps its previous content.
+///
+/// Each object holds all [`NdjsonExporter::COLUMNS`] by default; [`NdjsonExporter::with_columns`]
+/// narrows and reorders them.
+#[derive(Debug, Clone)]
+pub struct NdjsonExporter {
+    output_dir: PathBuf,
+    assignment_parser: Arc<dyn AssignmentParser>,
+    columns: Option<Vec<&'static str>>,
+}
+
+impl NdjsonExporter {
+    /// Keys of each output object, in their default order.
+    pub const COLUMNS: [&'static str; 14] = [
+        "published",
+        "file_digest",
+        "digest",
+        "fingerprint",
+        "distribution_method",
+        "transport",
+        "ip",
+        "blocklist",
+        "blocklist_countries",
+        "distributed",
+        "state",
+        "bandwidth",
+        "bandwidth_bytes",
+        "ratio",
+    ];
+
+    /// Creates an exporter writing below `output_dir`, which is created on first export if missing.
+    pub fn new(output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            output_dir: output_dir.into(),
+            assignment_parser: Arc::new(DefaultAssignmentParser),
+            columns: None,
+        }
+    }
+
+    /// Writes only `columns`, in the given order, instead of all [`Self::COLUMNS`].
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - Names from [`Self::COLUMNS`] (e.g. `["fingerprint", "distribution_method"]`).
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Self)` - The exporter writing the selected columns.
+    /// * `Err(anyhow::Error)` - `columns` is empty, or names an unknown column or one column twice.
+    pub fn with_columns<S: AsRef<str>>(mut self, columns: &[S]) -> AnyhowResult<Self> {
+        if columns.is_empty() {
+            return Err(anyhow::anyhow!("No columns selected"));
+        }
+        let mut selected: Vec<&'static str> = Vec::with_capacity(columns.len());
+        for column in columns {
+            let column = column.as_ref().trim();
+            let known = Self::COLUMNS.iter().find(|known| **known == column).with_context(|| {
+                format!("Unknown column {:?}; expected one of {}", column, Self::COLUMNS.join(", "))
+            })?;
+            if selected.contains(known) {
+                return Err(anyhow::anyhow!("Column {:?} selected more than once", column));
+            }
+            selected.push(known);
+        }
+        self.columns = Some(selected);
+        Ok(self)
+    }
+
+    /// Uses `parser` instead of the [`DefaultAssignmentParser`] for assignment strings.
+    pub fn with_assignment_parser(mut self, parser: Arc<dyn AssignmentParser>) -> Self {
+        self.assignment_parser = parser;
+        self
+    }
+
+    /// Returns the directory the per-date files are written to.
+    pub fn output_dir(&self) -> &Path {
+        &self.output_dir
+    }
+
+    /// Appends the assignments of each parsed file to the output file of its publication date.
+    async fn write(
+        &self,
+        parsed_assignments: Vec<ParsedBridgePoolAssignment>,
+    ) -> AnyhowResult<ExportSummary> {
+        let mut summary = ExportSummary::default();
+        tokio::fs::create_dir_all(&self.output_dir)
+            .await
+            .context(format!("Failed to create output directory {}", self.output_dir.display()))?;
+
+        // Lines per output file name, in the order of the parsed files
+        let mut lines_by_date: BTreeMap<String, String> = BTreeMap::new();
+        for parsed in parsed_assignments {
+            let published = datetime_from_millis(parsed.published_millis)
+                .context("Invalid published timestamp")?;
+            let published_str = published.format("%Y-%m-%d %H:%M:%S").to_string();
+            let file_digest = parsed.file_digest.clone();
+
+            let lines = lines_by_date
+                .entry(format!("{}.ndjson", published.format("%Y-%m-%d")))
+                .or_default();
+            for (fingerprint, assignment_str) in &parsed.entries {
+                let digest = parsed
+                    .assignment_digests
+                    .get(fingerprint)
+                    .context(format!("No assignment digest found for fingerprint: {}", fingerprint))?;
+                let line = NdjsonAssignmentLine {
+                    published: &published_str,
+                    file_digest: &file_digest,
+                    digest: digest.clone(),
+                    fingerprint,
+                    assignment: self.assignment_parser.parse(assignment_str),
+                };
+                lines.push_str(&self.serialize_line(&line).context("Failed to serialize assignment")?);
+                lines.push('\n');
+            }
+
+            summary.files_exported += 1;
+            summary.assignments_exported += parsed.entries.len();
+            if parsed.entries.is_empty() {
+                summary.empty_files.push(EmptyFile::of(&parsed));
+            }
+        }
+
+        for (file_name, lines) in lines_by_date {
+            let path = self.output_dir.join(file_name);
+            replace_via_temp_file(&path, |temp_path| append_copy(&path, temp_path, lines.as_bytes())).await?;
+        }
+
+        Ok(summary)
+    }
+}
+
+impl NdjsonAssignmentLine<'_> {
+    /// Serializes the value of one of [`NdjsonExporter::COLUMNS`].
+    ///
+    /// Each field is serialized directly with its own type, as in the full line; going through a
+    /// `serde_json::Value` would widen `ratio` to `f64` and write `0.1` as `0.10000000149011612`.
+    fn serialize_column(&self, column: &str) -> serde_json::Result<String> {
+        let assignment = &self.assignment;
+        match column {
+            "published" => serde_json::to_string(self.published),
+            "file_digest" => serde_json::to_string(self.file_digest),
+            "digest" => serde_json::to_string(&self.digest),
+            "fingerprint" => serde_json::to_string(self.fingerprint),
+            "distribution_method" => serde_json::to_string(&assignment.distribution_method),
+            "transport" => serde_json::to_string(&assignment.transport),
+            "ip" => serde_json::to_string(&assignment.ip),
+            "blocklist" => serde_json::to_string(&assignment.blocklist),
+            "blocklist_countries" => serde_json::to_string(&assignment.blocklist_countries),
+            "distributed" => serde_json::to_string(&assignment.distributed),
+            "state" => serde_json::to_string(&assignment.state),
+            "bandwidth" => serde_json::to_string(&assignment.bandwidth),
+            "bandwidth_bytes" => serde_json::to_string(&assignment.bandwidth_bytes),
+            "ratio" => serde_json::to_string(&assignment.ratio),
+            _ => Err(serde::ser::Error::custom(format!("Unknown column {:?}", column))),
+        }
+    }
+}
+
+impl NdjsonExporter {
+    /// Serializes `line` as a JSON object holding the selected columns in their selected order.
+    fn serialize_line(&self, line: &NdjsonAssignmentLine<'_>) -> serde_json::Result<String> {
+        let Some(columns) = &self.columns else {
+            return serde_json::to_string(line);
+        };
+        // A `serde_json::Map` sorts its keys, so the object is assembled by hand to keep the order
+        let fields = columns
+            .iter()
+            .map(|column| Ok(format!("{}:{}", serde_json::to_string(column)?, line.serialize_column(column)?)))
+            .collect::<serde_json::Result<Vec<String>>>()?;
+        Ok(format!("{{{}}}", fields.join(",")))
+    }
+}
+
+/// Writes `path` by way of a `.tmp` sibling, so a failed write never leaves a partial `path` behind.
+///
+/// `write` receives the temporary path and must fully write the new content there. On success the
+/// temporary file is renamed over `path` (atomic on the same file system); on failure it is removed
+/// and `path` is left untouched.
+///
+/// # Arguments
+///
+/// * `path` - The final output path.
+/// * `write` - Writes the complete new content to the temporary path it is given.
+///
+/// # Returns
+///
+/// * `Ok(())` - `path` holds the new content.
+/// * `Err(anyhow::Error)` - Writing or renaming failed; `path` is unchanged.
+async fn replace_via_temp_file<F, Fut>(path: &Path, write: F) -> AnyhowResult<()>
+where
+    F: FnOnce(PathBuf) -> Fut,
+    Fut: std::future::Future<Output = AnyhowResult<()>>,
+{
+    let mut temp_name = path.file_name().context("Output path has no file name")?.to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let result = match write(temp_path.clone()).await {
+        Ok(()) => tokio::fs::rename(&temp_path, path)
+            .await
+            .context(format!("Failed to move {} into place", temp_path.display())),
+        Err(e) => Err(e),
+    };
+    if result.is_err() {
+        // Best effort: the temporary file may not even have been created
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    result
+}
+
+/// Writes the current content of `path` (if any) followed by `data` to `temp_path`, then syncs it.
+async fn append_copy(path: &Path, temp_path: PathBuf, data: &[u8]) -> AnyhowResult<()> {
+    match tokio::fs::copy(path, &temp_path).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).context(format!("Failed to copy {}", path.display())),
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&temp_path)
+        .await
+        .context(format!("Failed to open {}", temp_path.display()))?;
+    file.write_all(data)
+        .await
+        .context(format!("Failed to write {}", temp_path.display()))?;
+    // tokio completes file writes in the background; flush so they are done before syncing
+    file.flush()
+        .await
+        .context(format!("Failed to flush {}", temp_path.display()))?;
+    file.sync_all()
+        .await
+        .context(format!("Failed to sync {}", temp_path.display()))
+}
+
+impl Exporter for NdjsonExporter {
+    fn export(
+        &mut self,
+        parsed_assignments: Vec<ParsedBridgePoolAssignment>,
+    ) -> BoxFuture<'_, AnyhowResult<ExportSummary>> {
+        Box::pin(async move { self.write(parsed_assignments).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::compute_file_digest;
+    use crate::fetch::BridgePoolFile;
+    use crate::parse::parse_bridge_pool_files;
+    use serde_json::Value;
+
+    /// Reads an NDJSON file into its JSON values.
+    fn read_lines(path: &Path) -> Vec<Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    /// Tests that an out-of-range publication timestamp fails the export with a clear error.
+    #[tokio::test]
+    async fn test_ndjson_exporter_rejects_extreme_timestamp() {
+        let parsed = ParsedBridgePoolAssignment {
+            published_millis: i64::MAX,
+            entries: Default::default(),
+            file_digest: compute_file_digest(b""),
+            raw_content: None,
+            assignment_digests: Default::default(),
+            header: None,
+            freshness: Default::default(),
+            last_modified_millis: 0,
+            duplicate_fingerprints: Vec::new(),
+        };
+        let output_dir = std::env::temp_dir().join(format!("bpa_ndjson_extreme_{}", std::process::id()));
+        let mut exporter = NdjsonExporter::new(&output_dir);
+
+        let err = exporter.export(vec![parsed]).await.unwrap_err();
+
+        assert!(format!("{:#}", err).contains("outside the supported range"), "{:#}", err);
+        let _ = std::fs::remove_dir_all(&output_dir);
+    }
+
+    /// Tests that files are written to one output file per publication date, with files from the
+    /// same date appended to each other.
+    #[tokio::test]
+    async fn test_ndjson_exporter_writes_one_file_per_date() {
+        let files = [
+            "bridge-pool-assignment 2022-04-09 00:29:37\n\
+             005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n",
+            "bridge-pool-assignment 2022-04-09 12:00:00\n\
+             01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https\n",
+            "bridge-pool-assignment 2022-04-10 00:29:37\n\
+             0227e4fe2ba6cb7e1bda6c94e1bdc3a0d8a4f1c6 moat blocklist=cn,ir\n",
+        ];
+        let parsed = parse_bridge_pool_files(
+            files
+                .iter()
+                .enumerate()
+                .map(|(i, content)| BridgePoolFile {
+                    path: format!("file{}", i),
+                    last_modified: 0,
+                    content: content.to_string(),
+                    raw_content: content.as_bytes().to_vec(),
+                })
+                .collect(),
+        )
+        .unwrap();
+        let output_dir = std::env::temp_dir().join(format!("bpa_ndjson_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&output_dir);
+        let mut exporter = NdjsonExporter::new(&output_dir);
+
+        let summary = exporter.export(parsed).await.unwrap();
+
+        assert_eq!((summary.files_exported, summary.assignments_exported), (3, 3));
+        assert!(summary.empty_files.is_empty());
+        let mut names: Vec<String> = std::fs::read_dir(&output_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["2022-04-09.ndjson", "2022-04-10.ndjson"]);
+
+        let first_day = read_lines(&output_dir.join("2022-04-09.ndjson"));
+        assert_eq!(first_day.len(), 2);
+        assert_eq!(first_day[0]["published"], "2022-04-09 00:29:37");
+        assert_eq!(first_day[0]["fingerprint"], "005fd4d7decbb250055b861579e6fdc79ad17bee");
+        assert_eq!(first_day[0]["transport"], "obfs4");
+        assert_eq!(
+            first_day[0]["file_digest"],
+            compute_file_digest(files[0].as_bytes()).as_str()
+        );
+        assert_eq!(first_day[1]["published"], "2022-04-09 12:00:00");
+        assert_eq!(first_day[1]["distribution_method"], "https");
+
+        let second_day = read_lines(&output_dir.join("2022-04-10.ndjson"));
+        assert_eq!(second_day.len(), 1);
+        assert_eq!(second_day[0]["blocklist_countries"], serde_json::json!(["cn", "ir"]));
+
+        std::fs::remove_dir_all(&output_dir).unwrap();
+    }
+
+    /// Tests that selected columns are written in the selected order, and that invalid selections are
+    /// rejected.
+    #[tokio::test]
+    async fn test_ndjson_exporter_selected_columns() {
+        let content = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+            005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n";
+        let parsed = parse_bridge_pool_files(vec![BridgePoolFile {
+            path: "file".to_string(),
+            last_modified: 0,
+            content: content.to_string(),
+            raw_content: content.as_bytes().to_vec(),
+        }])
+        .unwrap();
+        let output_dir = std::env::temp_dir().join(format!("bpa_ndjson_columns_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&output_dir);
+        let mut exporter = NdjsonExporter::new(&output_dir)
+            .with_columns(&["fingerprint", "distribution_method", "published"])
+            .unwrap();
+
+        exporter.export(parsed).await.unwrap();
+
+        let written = std::fs::read_to_string(output_dir.join("2022-04-09.ndjson")).unwrap();
+        std::fs::remove_dir_all(&output_dir).unwrap();
+        assert_eq!(
+            written,
+            "{\"fingerprint\":\"005fd4d7decbb250055b861579e6fdc79ad17bee\",\
+            \"distribution_method\":\"email\",\"published\":\"2022-04-09 00:29:37\"}\n"
+        );
+
+        let selection_error = |columns: &[&str]| NdjsonExporter::new(&output_dir).with_columns(columns).unwrap_err();
+        assert!(selection_error(&[]).to_string().contains("No columns"));
+        assert!(selection_error(&["fingerprint", "color"]).to_string().contains("Unknown column \"color\""));
+        assert!(selection_error(&["ip", "ip"]).to_string().contains("more than once"));
+    }
+
+    /// Tests that selecting all default columns writes exactly the serialized line, with the same keys,
+    /// order and values (including a non-integral `f32` ratio).
+    #[test]
+    fn test_ndjson_columns_match_line_fields() {
+        let digest = compute_file_digest(b"");
+        let line = NdjsonAssignmentLine {
+            published: "2022-04-09 00:29:37",
+            file_digest: &digest,
+            digest: digest.clone(),
+            fingerprint: "005fd4d7decbb250055b861579e6fdc79ad17bee",
+            assignment: DefaultAssignmentParser
+                .parse("email transport=obfs4 blocklist=cn,ir distributed=true bandwidth=1024KB ratio=0.1"),
+        };
+        let default = NdjsonExporter::new("unused").serialize_line(&line).unwrap();
+        let all = NdjsonExporter::new("unused")
+            .with_columns(&NdjsonExporter::COLUMNS)
+            .unwrap()
+            .serialize_line(&line)
+            .unwrap();
+
+        assert_eq!(all, default);
+        assert!(all.ends_with(",\"ratio\":0.1}"), "{}", all);
+    }
+
+    /// Tests that a write failing halfway leaves neither a truncated output file nor the temporary file,
+    /// and keeps an existing output file's content.
+    #[tokio::test]
+    async fn test_failed_write_leaves_no_partial_file() {
+        let output_dir = std::env::temp_dir().join(format!("bpa_ndjson_partial_{}", std::process::id()));
+        std::fs::create_dir_all(&output_dir).unwrap();
+        let path = output_dir.join("2022-04-09.ndjson");
+        // Writes part of the content, then fails the way a full disk would.
+        let failing_write = |temp_path: PathBuf| async move {
+            tokio::fs::write(&temp_path, b"{\"published\":").await.unwrap();
+            Err(anyhow::Error::from(std::io::Error::new(
+                std::io::ErrorKind::StorageFull,
+                "No space left on device",
+            )))
+        };
+
+        let err = replace_via_temp_file(&path, failing_write).await.unwrap_err();
+        assert!(err.to_string().contains("No space left"), "{}", err);
+        assert_eq!(std::fs::read_dir(&output_dir).unwrap().count(), 0);
+
+        std::fs::write(&path, "{}\n").unwrap();
+        replace_via_temp_file(&path, failing_write).await.unwrap_err();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}\n");
+        assert_eq!(std::fs::read_dir(&output_dir).unwrap().count(), 1);
+
+        replace_via_temp_file(&path, |temp_path| append_copy(&path, temp_path, b"{}\n")).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}\n{}\n");
+        assert_eq!(std::fs::read_dir(&output_dir).unwrap().count(), 1);
+
+        std::fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
@@ -1,34 +1,66 @@
+use super::types::{ColumnMapping, EmptyFile, ExportOptions, ExportSummary, Exporter, InsertMethod};
 use crate::parse::ParsedBridgePoolAssignment;
-use crate::utils::{compute_file_digest, compute_assignment_digest};
+use crate::utils::{datetime_from_millis, Digest};
 use anyhow::{Context, Result as AnyhowResult};
-use chrono::{DateTime, Utc};
-use tokio_postgres::{NoTls, Transaction};
+use chrono::{NaiveDate, NaiveDateTime};
+use futures::future::BoxFuture;
+use std::collections::{BTreeSet, HashSet};
+use std::time::Duration;
+use tokio_postgres::binary_copy::BinaryCopyInWriter;
+use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::{Client, NoTls, Transaction};
 
 // Global constant to limit the number of files to export during testing
 const MAX_FILES_TO_EXPORT: usize = 100;
 
+/// A single row destined for the `bridge_pool_assignment` table, in column order:
+/// (published, digest, fingerprint, distribution_method, transport, ip, blocklist,
+/// bridge_pool_assignments, distributed, state, bandwidth, bandwidth_bytes, ratio, last_modified).
+/// The trailing `last_modified` is only written with `ExportOptions::with_assignment_last_modified`.
+type AssignmentRow = (
+  NaiveDateTime,
+  String,
+  String,
+  String,
+  Option<String>,
+  Option<String>,
+  Option<String>,
+  String,
+  bool,
+  Option<String>,
+  Option<String>,
+  Option<i64>,
+  Option<f32>,
+  Option<NaiveDateTime>,
+);
+
+
 /// Exports parsed bridge pool assignment data to a PostgreSQL database.
 ///
 /// Connects to a PostgreSQL database, creates necessary tables if they don't exist, and inserts the provided
 /// parsed data. Uses a transaction to ensure atomicity across table operations. Optionally truncates existing
-/// tables if the `clear` flag is set.
+/// tables if `options.clear` is set, and refreshes the daily summary table if `options.with_daily_summary`
+/// is set.
 ///
 /// # Arguments
 ///
 /// * `parsed_assignments` - Vector of parsed bridge pool assignments to export.
 /// * `db_params` - PostgreSQL connection string (e.g., "host=localhost user=postgres password=example").
-/// * `clear` - If `true`, truncates existing tables before inserting new data.
+/// * `options` - Export options (clearing, daily summary, ...).
 ///
 /// # Returns
 ///
-/// * `Ok(())` - Data successfully exported.
-/// * `Err(anyhow::Error)` - Connection, transaction, or query execution failed.
+/// * `Ok(ExportSummary)` - Data successfully exported; the summary counts newly written rows.
+/// * `Err(anyhow::Error)` - Connection, transaction, or query execution failed, or `options` combines
+///   `store_raw` with `excluded_fingerprints` (the raw content would still hold the excluded lines).
 ///
 /// # Examples
 ///
 /// ```rust,no_run
+/// use bridge_pool_assignments::fetch::Freshness;
 /// use bridge_pool_assignments::parse::ParsedBridgePoolAssignment;
-/// use bridge_pool_assignments::export::export_to_postgres;
+/// use bridge_pool_assignments::export::{export_to_postgres, ExportOptions};
+/// use bridge_pool_assignments::utils::compute_file_digest;
 /// use std::collections::BTreeMap;
 ///
 /// #[tokio::main]
@@ -37,14 +69,19 @@ const MAX_FILES_TO_EXPORT: usize = 100;
 ///     let assignment = ParsedBridgePoolAssignment {
 ///         published_millis: 1638316800000, // Example timestamp
 ///         entries: BTreeMap::new(),        // Empty entries for simplicity
-///         raw_content: Vec::new(),         // Empty raw content for simplicity
-///         raw_lines: BTreeMap::new(),      // Empty raw lines for simplicity
+///         file_digest: compute_file_digest(b""), // Digest of the (empty) raw content
+///         raw_content: None,               // Raw bytes are only needed with `store_raw`
+///         assignment_digests: BTreeMap::new(), // No entries, so no assignment digests
+///         header: None,                    // Stored as "bridge-pool-assignment"
+///         freshness: Freshness::Recent,    // Fetched from below `recent/`
+///         last_modified_millis: 0,         // Unknown
+///         duplicate_fingerprints: Vec::new(), // No entries, so no duplicates
 ///     };
 ///     let assignments = vec![assignment];
 ///     export_to_postgres(
 ///         assignments,
 ///         "host=localhost user=postgres password=your_password dbname=your_db",
-///         false,
+///         &ExportOptions::default(),
 ///     ).await?;
 ///     Ok(())
 /// }
@@ -52,8 +89,14 @@ const MAX_FILES_TO_EXPORT: usize = 100;
 pub async fn export_to_postgres(
   parsed_assignments: Vec<ParsedBridgePoolAssignment>,
   db_params: &str,
-  clear: bool,
-) -> AnyhowResult<()> {
+  options: &ExportOptions,
+) -> AnyhowResult<ExportSummary> {
+  if options.store_raw && !options.excluded_fingerprints.is_empty() {
+    return Err(anyhow::anyhow!(
+      "Storing raw content cannot be combined with excluded fingerprints, whose lines it would keep"
+    ));
+  }
+
   let (mut client, connection) = tokio_postgres::connect(db_params, NoTls)
     .await
     .context("Failed to connect to PostgreSQL")?;
@@ -68,11 +111,11 @@ pub async fn export_to_postgres(
     .await
     .context("Failed to start transaction")?;
 
-  create_tables(&transaction)
+  create_tables(&transaction, options)
     .await
     .context("Failed to create tables")?;
 
-  if clear {
+  if options.clear {
     transaction
       .execute("TRUNCATE TABLE bridge_pool_assignment CASCADE", &[])
       .await
@@ -81,6 +124,12 @@ pub async fn export_to_postgres(
       .execute("TRUNCATE TABLE bridge_pool_assignments_file CASCADE", &[])
       .await
       .context("Failed to truncate bridge_pool_assignments_file")?;
+    if options.with_daily_summary {
+      transaction
+        .execute("TRUNCATE TABLE bridge_pool_assignment_daily", &[])
+        .await
+        .context("Failed to truncate bridge_pool_assignment_daily")?;
+    }
   }
 
   let assignments_to_export = parsed_assignments
@@ -88,17 +137,48 @@ pub async fn export_to_postgres(
     .take(MAX_FILES_TO_EXPORT)
     .collect::<Vec<_>>();
 
+  let mut summary = ExportSummary::default();
+  let mut exported_dates = BTreeSet::new();
+  let mut expected_file_digests = BTreeSet::new();
+  let mut expected_assignment_digests = BTreeSet::new();
+  let mut throttle = BatchThrottle::new(options.batch_delay);
+
   for assignment in assignments_to_export {
-    // Use raw content to compute the file digest
-    let file_digest = compute_file_digest(&assignment.raw_content);
+    let file_digest = assignment.file_digest.clone();
+    if options.verify_after_export {
+      expected_file_digests.insert(file_digest.to_string());
+      for (fingerprint, digest) in &assignment.assignment_digests {
+        if !options.excluded_fingerprints.is_excluded(fingerprint) {
+          expected_assignment_digests.insert(digest.to_string());
+        }
+      }
+    }
     
-    insert_file_data(&transaction, &assignment, &file_digest)
+    let header = options.header.as_deref().unwrap_or(assignment.header_or_default());
+    let files_written = insert_file_data(&transaction, &assignment, file_digest.as_str(), header, options)
       .await
       .context("Failed to insert file data")?;
+    summary.files_exported += files_written;
+    if files_written > 0 && assignment.entries.is_empty() {
+      summary.empty_files.push(EmptyFile::of(&assignment));
+    }
     
-    insert_assignment_data(&transaction, &assignment, &file_digest)
+    summary.assignments_exported += insert_assignment_data(&transaction, &assignment, &file_digest, options, &mut throttle)
       .await
       .context("Failed to insert assignment data")?;
+
+    if options.with_daily_summary {
+      let published = datetime_from_millis(assignment.published_millis)
+        .context("Invalid published timestamp")?;
+      exported_dates.insert(published.date_naive());
+    }
+  }
+
+  if options.with_daily_summary && !exported_dates.is_empty() {
+    let dates: Vec<NaiveDate> = exported_dates.into_iter().collect();
+    update_daily_summary(&transaction, &dates, &options.column_mapping)
+      .await
+      .context("Failed to update daily summary")?;
   }
 
   transaction
@@ -106,203 +186,771 @@ pub async fn export_to_postgres(
     .await
     .context("Failed to commit transaction")?;
 
-  Ok(())
+  if options.verify_after_export {
+    let file_digests: Vec<String> = expected_file_digests.into_iter().collect();
+    let assignment_digests: Vec<String> = expected_assignment_digests.into_iter().collect();
+    verify_export(&client, &file_digests, &assignment_digests, &options.column_mapping)
+      .await
+      .context("Export verification failed")?;
+  }
+
+  Ok(summary)
 }
 
-/// Creates tables and indexes in the database if they don't already exist.
+/// Checks that all given file and assignment digests are present in the database.
+///
+/// Counts the rows matching the digests in both tables and compares them with the number of digests.
+/// Rows skipped during the export because they already existed still count as present, so only rows
+/// that went missing make the check fail.
+///
+/// # Arguments
+///
+/// * `client` - Database client.
+/// * `file_digests` - Distinct digests of the exported files.
+/// * `assignment_digests` - Distinct digests of the exported assignments.
+/// * `mapping` - Column names of the assignment table.
 ///
-/// Sets up the schema for `bridge_pool_assignments_file` and `bridge_pool_assignment` tables, including
-/// primary keys, foreign key references, and performance-enhancing indexes.
+/// # Returns
+///
+/// * `Ok(())` - Every digest was found.
+/// * `Err(anyhow::Error)` - A count diverged or a query failed.
+async fn verify_export(
+  client: &Client,
+  file_digests: &[String],
+  assignment_digests: &[String],
+  mapping: &ColumnMapping,
+) -> AnyhowResult<()> {
+  let tables = [
+    ("bridge_pool_assignments_file", "digest".to_string(), file_digests),
+    ("bridge_pool_assignment", mapping.column("digest"), assignment_digests),
+  ];
+  for (table, digest_column, digests) in tables {
+    let found: i64 = client
+      .query_one(&format!("SELECT COUNT(*) FROM {} WHERE {} = ANY($1)", table, digest_column), &[&digests])
+      .await
+      .context(format!("Failed to count rows in {}", table))?
+      .get(0);
+    if found as usize != digests.len() {
+      return Err(anyhow::anyhow!(
+        "Expected {} rows in {}, found {}",
+        digests.len(),
+        table,
+        found
+      ));
+    }
+  }
+  Ok(())
+}
+
+/// An [`Exporter`] writing to PostgreSQL via [`export_to_postgres`].
+#[derive(Debug, Clone)]
+pub struct PostgresExporter {
+  db_params: String,
+  options: ExportOptions,
+}
+
+impl PostgresExporter {
+  /// Creates an exporter for the database described by `db_params`.
+  pub fn new(db_params: &str, options: ExportOptions) -> Self {
+    Self {
+      db_params: db_params.to_string(),
+      options,
+    }
+  }
+}
+
+impl Exporter for PostgresExporter {
+  fn export(
+    &mut self,
+    parsed_assignments: Vec<ParsedBridgePoolAssignment>,
+  ) -> BoxFuture<'_, AnyhowResult<ExportSummary>> {
+    Box::pin(export_to_postgres(parsed_assignments, &self.db_params, &self.options))
+  }
+}
+
+/// Returns the DDL statements that set up the export schema, in execution order.
 ///
 /// The schema follows the maintainer's recommendations:
 /// - `bridge_pool_assignments_file` uses the SHA-256 digest of the raw file content as its primary key
 /// - `bridge_pool_assignment` uses the SHA-256 digest of the raw line bytes combined with the file digest as its primary key
 /// - A foreign key relationship connects the two tables through the file digest
+/// - `bridge_pool_assignment_blocklist` holds one row per blocklisted country of an assignment
+///
+/// When the daily summary is enabled, the `bridge_pool_assignment_daily` table is created as well, keyed
+/// by `(date, distribution_method)`. When raw content storage is enabled, the nullable `raw_content`
+/// column is added to `bridge_pool_assignments_file` if it is missing, and likewise the nullable
+/// `last_modified` column to `bridge_pool_assignment` when assignment last-modified times are enabled.
+///
+/// Assignment columns are named according to [`ExportOptions::column_mapping`].
+///
+/// When a schema is configured, the list starts by creating it and pointing `search_path` at it, so
+/// the unqualified names of all following statements (and of the export's queries) resolve there.
+///
+/// Every statement is idempotent, so the same list serves both `create_tables` and `--print-schema`,
+/// which lets the schema be reviewed and applied by hand without connecting to a database.
+///
+/// # Arguments
+///
+/// * `options` - Export options deciding the schema and which optional tables and columns are needed.
+///
+/// # Returns
+///
+/// * `Ok(Vec<String>)` - The `CREATE SCHEMA`, `SET`, `CREATE TABLE`, `ALTER TABLE` and `CREATE INDEX`
+///   statements, without trailing semicolons.
+/// * `Err(anyhow::Error)` - The configured schema name is not a valid identifier.
+pub fn schema_ddl(options: &ExportOptions) -> AnyhowResult<Vec<String>> {
+  let mut schema_statements = Vec::new();
+  if let Some(schema) = &options.schema {
+    let schema = quote_identifier(schema).context("Invalid schema name")?;
+    schema_statements.push(format!("CREATE SCHEMA IF NOT EXISTS {}", schema));
+    schema_statements.push(format!("SET search_path TO {}", schema));
+  }
+
+  let mut statements: Vec<String> = vec![
+    "CREATE TABLE IF NOT EXISTS bridge_pool_assignments_file (
+  published TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+  header TEXT NOT NULL,
+  digest TEXT NOT NULL,
+  freshness TEXT,
+  last_modified TIMESTAMP WITHOUT TIME ZONE,
+  PRIMARY KEY(digest)
+)",
+    // Tables created before the freshness and last_modified columns existed get them added, with NULL
+    // for older rows
+    "ALTER TABLE bridge_pool_assignments_file ADD COLUMN IF NOT EXISTS freshness TEXT",
+    "ALTER TABLE bridge_pool_assignments_file ADD COLUMN IF NOT EXISTS last_modified TIMESTAMP WITHOUT TIME ZONE",
+    "CREATE INDEX IF NOT EXISTS bridge_pool_assignment_file_published
+  ON bridge_pool_assignments_file (published)",
+  ]
+  .into_iter()
+  .map(String::from)
+  .collect();
+  if options.store_raw {
+    statements.push("ALTER TABLE bridge_pool_assignments_file ADD COLUMN IF NOT EXISTS raw_content BYTEA".to_string());
+  }
+  let column = |field| options.column_mapping.column(field);
+  statements.extend([
+    format!(
+      "CREATE TABLE IF NOT EXISTS bridge_pool_assignment (
+  {} TIMESTAMP WITHOUT TIME ZONE NOT NULL,
+  {} TEXT NOT NULL,
+  {} TEXT NOT NULL,
+  {} TEXT NOT NULL,
+  {} TEXT,
+  {} TEXT,
+  {} TEXT,
+  {} TEXT REFERENCES bridge_pool_assignments_file(digest),
+  {} BOOLEAN,
+  {} TEXT,
+  {} TEXT,
+  {} BIGINT,
+  {} REAL,
+  PRIMARY KEY({})
+)",
+      column("published"),
+      column("digest"),
+      column("fingerprint"),
+      column("distribution_method"),
+      column("transport"),
+      column("ip"),
+      column("blocklist"),
+      column("bridge_pool_assignments"),
+      column("distributed"),
+      column("state"),
+      column("bandwidth"),
+      column("bandwidth_bytes"),
+      column("ratio"),
+      column("digest"),
+    ),
+    // Tables created before bandwidth was parsed get the numeric column added, with NULL for older rows
+    format!(
+      "ALTER TABLE bridge_pool_assignment ADD COLUMN IF NOT EXISTS {} BIGINT",
+      column("bandwidth_bytes")
+    ),
+  ]);
+  if options.with_assignment_last_modified {
+    statements.push(format!(
+      "ALTER TABLE bridge_pool_assignment ADD COLUMN IF NOT EXISTS {} TIMESTAMP WITHOUT TIME ZONE",
+      column("last_modified")
+    ));
+  }
+  statements.extend([
+    format!(
+      "CREATE INDEX IF NOT EXISTS bridge_pool_assignment_published
+  ON bridge_pool_assignment ({})",
+      column("published")
+    ),
+    format!(
+      "CREATE INDEX IF NOT EXISTS bridge_pool_assignment_fingerprint
+  ON bridge_pool_assignment ({})",
+      column("fingerprint")
+    ),
+    format!(
+      "CREATE INDEX IF NOT EXISTS bridge_pool_assignment_fingerprint_published_desc_index
+  ON bridge_pool_assignment ({}, {} DESC)",
+      column("fingerprint"),
+      column("published")
+    ),
+    format!(
+      "CREATE TABLE IF NOT EXISTS bridge_pool_assignment_blocklist (
+  assignment_digest TEXT NOT NULL REFERENCES bridge_pool_assignment({}),
+  country TEXT NOT NULL,
+  PRIMARY KEY(assignment_digest, country)
+)",
+      column("digest")
+    ),
+    "CREATE INDEX IF NOT EXISTS bridge_pool_assignment_blocklist_country
+  ON bridge_pool_assignment_blocklist (country)"
+      .to_string(),
+  ]);
+  if options.with_daily_summary {
+    statements.push(
+      "CREATE TABLE IF NOT EXISTS bridge_pool_assignment_daily (
+  date DATE NOT NULL,
+  distribution_method TEXT NOT NULL,
+  bridge_count BIGINT NOT NULL,
+  PRIMARY KEY(date, distribution_method)
+)"
+      .to_string(),
+    );
+  }
+  schema_statements.extend(statements);
+  Ok(schema_statements)
+}
+
+/// Quotes a PostgreSQL identifier, such as a schema name, for use in SQL.
+///
+/// The name is wrapped in double quotes with embedded double quotes doubled, so it is taken verbatim
+/// (including its case) and cannot inject SQL.
+///
+/// # Arguments
+///
+/// * `name` - The identifier to quote.
+///
+/// # Returns
+///
+/// * `Ok(String)` - The quoted identifier.
+/// * `Err(anyhow::Error)` - The name is empty, longer than PostgreSQL's 63-byte limit, or contains a
+///   NUL character.
+pub(crate) fn quote_identifier(name: &str) -> AnyhowResult<String> {
+  if name.is_empty() {
+    return Err(anyhow::anyhow!("Identifier is empty"));
+  }
+  if name.len() > 63 {
+    return Err(anyhow::anyhow!("Identifier {:?} is longer than 63 bytes", name));
+  }
+  if name.contains('\0') {
+    return Err(anyhow::anyhow!("Identifier {:?} contains a NUL character", name));
+  }
+  Ok(format!("\"{}\"", name.replace('"', "\"\"")))
+}
+
+/// Creates the export schema by running the statements of [`schema_ddl`].
 ///
 /// # Arguments
 ///
 /// * `transaction` - Active database transaction to execute schema creation queries.
+/// * `options` - Export options deciding which optional tables are needed.
 ///
 /// # Returns
 ///
 /// * `Ok(())` - Tables and indexes created successfully.
 /// * `Err(anyhow::Error)` - Query execution failed.
-async fn create_tables(transaction: &Transaction<'_>) -> AnyhowResult<()> {
-  transaction
-    .execute(
-      "CREATE TABLE IF NOT EXISTS bridge_pool_assignments_file (
-        published TIMESTAMP WITHOUT TIME ZONE NOT NULL,
-        header TEXT NOT NULL,
-        digest TEXT NOT NULL,
-        PRIMARY KEY(digest)
-      )",
-      &[],
-    )
-    .await
-    .context("Failed to create bridge_pool_assignments_file table")?;
-
-  transaction
-    .execute(
-      "CREATE INDEX IF NOT EXISTS bridge_pool_assignment_file_published 
-      ON bridge_pool_assignments_file (published)",
-      &[],
-    )
-    .await
-    .context("Failed to create index on bridge_pool_assignments_file")?;
-
-  transaction
-    .execute(
-      "CREATE TABLE IF NOT EXISTS bridge_pool_assignment (
-        published TIMESTAMP WITHOUT TIME ZONE NOT NULL,
-        digest TEXT NOT NULL,
-        fingerprint TEXT NOT NULL,
-        distribution_method TEXT NOT NULL,
-        transport TEXT,
-        ip TEXT,
-        blocklist TEXT,
-        bridge_pool_assignments TEXT REFERENCES bridge_pool_assignments_file(digest),
-        distributed BOOLEAN,
-        state TEXT,
-        bandwidth TEXT,
-        ratio REAL,
-        PRIMARY KEY(digest)
-      )",
-      &[],
-    )
-    .await
-    .context("Failed to create bridge_pool_assignment table")?;
+async fn create_tables(transaction: &Transaction<'_>, options: &ExportOptions) -> AnyhowResult<()> {
+  check_legacy_schema(transaction, options).await?;
+  check_column_mapping(transaction, options).await?;
+  for statement in schema_ddl(options)? {
+    transaction
+      .execute(statement.as_str(), &[])
+      .await
+      .with_context(|| format!("Failed to run schema statement: {}", statement.lines().next().unwrap_or_default()))?;
+  }
+  Ok(())
+}
 
-  transaction
-    .execute(
-      "CREATE INDEX IF NOT EXISTS bridge_pool_assignment_published 
-      ON bridge_pool_assignment (published)",
-      &[],
+/// Rejects a `bridge_pool_assignment` table created by the legacy exporter.
+///
+/// The legacy layout used an `id SERIAL PRIMARY KEY` and no unique key on `digest`. `CREATE TABLE IF NOT
+/// EXISTS` leaves such a table in place, after which every `ON CONFLICT (digest)` insert fails with an
+/// obscure error, so the layout is detected up front and reported with migration steps instead.
+///
+/// # Arguments
+///
+/// * `transaction` - Active database transaction.
+/// * `options` - Export options providing the target schema (the connection's current schema if unset).
+///
+/// # Returns
+///
+/// * `Ok(())` - The table does not exist yet or has the current layout.
+/// * `Err(anyhow::Error)` - The table has the legacy layout, or the catalog query failed.
+async fn check_legacy_schema(transaction: &Transaction<'_>, options: &ExportOptions) -> AnyhowResult<()> {
+  let row = transaction
+    .query_opt(
+      "SELECT
+        EXISTS (SELECT 1 FROM pg_attribute
+          WHERE attrelid = c.oid AND attname = 'id' AND NOT attisdropped),
+        EXISTS (SELECT 1 FROM pg_index i
+          JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = i.indkey[0]
+          WHERE i.indrelid = c.oid AND i.indisunique AND i.indnkeyatts = 1 AND a.attname = $2),
+        EXISTS (SELECT 1 FROM pg_attribute
+          WHERE attrelid = c.oid AND attname = $2 AND NOT attisdropped)
+      FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+      WHERE c.relname = 'bridge_pool_assignment' AND n.nspname = COALESCE($1::text, current_schema())",
+      &[&options.schema, &options.column_mapping.name("digest")],
     )
     .await
-    .context("Failed to create published index on bridge_pool_assignment")?;
+    .context("Failed to inspect the existing bridge_pool_assignment table")?;
+  // Without a digest column the table is not a legacy one but lacks a mapping, which
+  // `check_column_mapping` reports
+  let Some(row) = row.filter(|row| row.get::<_, bool>(2)) else {
+    return Ok(());
+  };
+  let (has_id, has_digest_key): (bool, bool) = (row.get(0), row.get(1));
+  // A mapped table is an existing warehouse table, which may well have an `id` column of its own
+  let has_id = has_id && options.column_mapping.is_default();
+  if has_id || !has_digest_key {
+    return Err(anyhow::anyhow!(
+      "bridge_pool_assignment has the legacy layout ({}). Migrate it before exporting, e.g. with \
+      `DELETE FROM bridge_pool_assignment a USING bridge_pool_assignment b WHERE a.digest = b.digest AND a.ctid > b.ctid; \
+      ALTER TABLE bridge_pool_assignment DROP COLUMN IF EXISTS id; \
+      ALTER TABLE bridge_pool_assignment ADD PRIMARY KEY (digest);`, or export into a fresh schema with --db-schema",
+      if has_id { "an `id` column" } else { "no primary key on `digest`" }
+    ));
+  }
+  Ok(())
+}
 
-  transaction
-    .execute(
-      "CREATE INDEX IF NOT EXISTS bridge_pool_assignment_fingerprint 
-      ON bridge_pool_assignment (fingerprint)",
-      &[],
+/// Checks that an existing `bridge_pool_assignment` table has a column for every required field.
+///
+/// Matters mostly for tables created outside the crate and described by
+/// [`ExportOptions::column_mapping`]. The `bandwidth_bytes` and `last_modified` columns are not
+/// required, since [`schema_ddl`] adds them if missing.
+///
+/// # Arguments
+///
+/// * `transaction` - Active database transaction.
+/// * `options` - Export options providing the target schema and the column mapping.
+///
+/// # Returns
+///
+/// * `Ok(())` - The table does not exist yet or has every required column.
+/// * `Err(anyhow::Error)` - Columns are missing, or the catalog query failed.
+async fn check_column_mapping(transaction: &Transaction<'_>, options: &ExportOptions) -> AnyhowResult<()> {
+  let existing: HashSet<String> = transaction
+    .query(
+      "SELECT a.attname::text FROM pg_attribute a
+      JOIN pg_class c ON c.oid = a.attrelid JOIN pg_namespace n ON n.oid = c.relnamespace
+      WHERE c.relname = 'bridge_pool_assignment' AND n.nspname = COALESCE($1::text, current_schema())
+        AND a.attnum > 0 AND NOT a.attisdropped",
+      &[&options.schema],
     )
     .await
-    .context("Failed to create fingerprint index on bridge_pool_assignment")?;
+    .context("Failed to inspect the columns of bridge_pool_assignment")?
+    .iter()
+    .map(|row| row.get(0))
+    .collect();
+  if existing.is_empty() {
+    return Ok(());
+  }
+  let missing: Vec<String> = ColumnMapping::FIELDS
+    .iter()
+    .filter(|field| !matches!(**field, "bandwidth_bytes" | "last_modified"))
+    .map(|field| options.column_mapping.name(field))
+    .filter(|column| !existing.contains(*column))
+    .map(|column| format!("{:?}", column))
+    .collect();
+  if !missing.is_empty() {
+    return Err(anyhow::anyhow!(
+      "bridge_pool_assignment has no column {}; map the crate's fields to the table's columns with --column-map",
+      missing.join(", ")
+    ));
+  }
+  Ok(())
+}
 
+/// Recomputes the per-day, per-distribution-method counts for the given dates.
+///
+/// Counts are derived from the `bridge_pool_assignment` table itself rather than from the batch being
+/// exported, so rows skipped by `ON CONFLICT` and rows from earlier runs on the same day are accounted for.
+/// Existing summary rows are overwritten via upsert, which keeps repeated exports idempotent.
+///
+/// # Arguments
+///
+/// * `transaction` - Active database transaction.
+/// * `dates` - Publication dates (UTC) touched by this export.
+/// * `mapping` - Column names of the assignment table.
+///
+/// # Returns
+///
+/// * `Ok(())` - Summary rows upserted successfully.
+/// * `Err(anyhow::Error)` - Query execution failed.
+async fn update_daily_summary(
+  transaction: &Transaction<'_>,
+  dates: &[NaiveDate],
+  mapping: &ColumnMapping,
+) -> AnyhowResult<()> {
   transaction
     .execute(
-      "CREATE INDEX IF NOT EXISTS bridge_pool_assignment_fingerprint_published_desc_index 
-      ON bridge_pool_assignment (fingerprint, published DESC)",
-      &[],
+      &format!(
+        "INSERT INTO bridge_pool_assignment_daily (date, distribution_method, bridge_count)
+        SELECT {0}::date, {1}, COUNT(*)
+        FROM bridge_pool_assignment
+        WHERE {0}::date = ANY($1)
+        GROUP BY {0}::date, {1}
+        ON CONFLICT (date, distribution_method) DO UPDATE SET bridge_count = EXCLUDED.bridge_count",
+        mapping.column("published"),
+        mapping.column("distribution_method")
+      ),
+      &[&dates],
     )
     .await
-    .context("Failed to create fingerprint+published index on bridge_pool_assignment")?;
-
+    .context("Failed to upsert into bridge_pool_assignment_daily")?;
   Ok(())
 }
 
 /// Inserts file metadata into the `bridge_pool_assignments_file` table.
 ///
-/// Adds a record for the assignment file, including its publication timestamp, header, and digest.
+/// Adds a record for the assignment file, including its publication timestamp, header, digest,
+/// freshness category and last-modified time, and optionally the file's raw bytes.
+///
+/// A file whose digest is already present is left alone, unless
+/// [`ExportOptions::upsert_file_metadata`] is set: then its mutable metadata (header, freshness and
+/// last-modified time) is overwritten where it differs. The published time and raw bytes derive from
+/// the content, which the digest pins, so they are never updated.
 ///
 /// # Arguments
 ///
 /// * `transaction` - Active database transaction.
 /// * `assignment` - Parsed bridge pool assignment data.
 /// * `digest` - SHA-256 digest of the assignment file's raw content.
+/// * `header` - Header stored for the file.
+/// * `options` - Export options deciding whether to store raw bytes and to update existing rows.
 ///
 /// # Returns
 ///
-/// * `Ok(())` - Data inserted successfully.
+/// * `Ok(usize)` - Number of rows inserted, or with `upsert_file_metadata` inserted or updated (0 if
+///   the file was already present unchanged).
 /// * `Err(anyhow::Error)` - Timestamp conversion or query execution failed.
 async fn insert_file_data(
   transaction: &Transaction<'_>,
   assignment: &ParsedBridgePoolAssignment,
   digest: &str,
-) -> AnyhowResult<()> {
-  let published_dt = DateTime::<Utc>::from_timestamp_millis(assignment.published_millis)
+  header: &str,
+  options: &ExportOptions,
+) -> AnyhowResult<usize> {
+  let published_dt = datetime_from_millis(assignment.published_millis)
     .context("Invalid published timestamp")?;
   let published_naive = published_dt.naive_utc();
+  let freshness = assignment.freshness.as_str();
+  let last_modified_naive = match assignment.last_modified_millis {
+    0 => None,
+    millis => Some(datetime_from_millis(millis).context("Invalid last modified timestamp")?.naive_utc()),
+  };
 
-  let header = "bridge-pool-assignment";
-  transaction
+  let on_conflict = if options.upsert_file_metadata {
+    "DO UPDATE SET header = EXCLUDED.header, freshness = EXCLUDED.freshness, last_modified = EXCLUDED.last_modified
+    WHERE (bridge_pool_assignments_file.header, bridge_pool_assignments_file.freshness,
+      bridge_pool_assignments_file.last_modified)
+      IS DISTINCT FROM (EXCLUDED.header, EXCLUDED.freshness, EXCLUDED.last_modified)"
+  } else {
+    "DO NOTHING"
+  };
+  let mut params: Vec<&(dyn ToSql + Sync)> = vec![&published_naive, &header, &digest, &freshness, &last_modified_naive];
+  let mut columns = "published, header, digest, freshness, last_modified";
+  let mut placeholders = "$1, $2, $3, $4, $5";
+  if options.store_raw {
+    let raw_content = assignment
+      .raw_content
+      .as_ref()
+      .context("Raw content was not retained while parsing; use parse_bridge_pool_files_keeping_raw")?;
+    params.push(raw_content);
+    columns = "published, header, digest, freshness, last_modified, raw_content";
+    placeholders = "$1, $2, $3, $4, $5, $6";
+  }
+
+  let inserted = transaction
     .execute(
-      "INSERT INTO bridge_pool_assignments_file (published, header, digest) 
-      VALUES ($1, $2, $3) ON CONFLICT (digest) DO NOTHING",
-      &[&published_naive, &header, &digest],
+      &format!(
+        "INSERT INTO bridge_pool_assignments_file ({}) VALUES ({}) ON CONFLICT (digest) {}",
+        columns, placeholders, on_conflict
+      ),
+      &params,
     )
     .await
     .context("Failed to insert into bridge_pool_assignments_file")?;
-  Ok(())
+  Ok(inserted as usize)
 }
 
 /// Inserts individual assignment entries into the `bridge_pool_assignment` table.
 ///
 /// Processes assignment entries in batches for efficiency, parsing each entry into structured fields.
 /// Each entry has its own unique digest calculated from the raw line bytes combined with the file digest.
+/// Blocklist countries of each entry are written to `bridge_pool_assignment_blocklist` alongside its batch.
 ///
 /// # Arguments
 ///
 /// * `transaction` - Active database transaction.
 /// * `assignment` - Parsed bridge pool assignment data.
 /// * `file_digest` - SHA-256 digest linking to the file table.
+/// * `options` - Export options providing the assignment parser, the excluded fingerprints and whether
+///   to write `last_modified`.
+/// * `throttle` - Pacing shared by all batches of the export.
 ///
 /// # Returns
 ///
-/// * `Ok(())` - Data inserted successfully.
+/// * `Ok(usize)` - Number of assignment rows inserted (entries already present or excluded are
+///   skipped).
 /// * `Err(anyhow::Error)` - Timestamp conversion or batch insertion failed.
 async fn insert_assignment_data(
   transaction: &Transaction<'_>,
   assignment: &ParsedBridgePoolAssignment,
-  file_digest: &str,
-) -> AnyhowResult<()> {
-  let mut batch_data = Vec::new();
+  file_digest: &Digest,
+  options: &ExportOptions,
+  throttle: &mut BatchThrottle,
+) -> AnyhowResult<usize> {
+  let parser = options.assignment_parser.as_ref();
+  let mut inserted = 0;
+  let mut batch_data: Vec<AssignmentRow> = Vec::new();
+  let mut blocklist_data: Vec<(String, String)> = Vec::new();
   let batch_size = 1000;
 
-  let published_naive = DateTime::<Utc>::from_timestamp_millis(assignment.published_millis)
+  let published_naive = datetime_from_millis(assignment.published_millis)
     .context("Invalid published timestamp")?
     .naive_utc();
+  let last_modified_naive = match assignment.last_modified_millis {
+    0 => None,
+    millis => Some(datetime_from_millis(millis).context("Invalid last modified timestamp")?.naive_utc()),
+  };
 
   for (fingerprint, assignment_str) in &assignment.entries {
-    // Get the raw line bytes for this assignment
-    let raw_line = assignment.raw_lines.get(fingerprint)
-      .context(format!("No raw line data found for fingerprint: {}", fingerprint))?;
-    
-    // Compute a unique digest for this assignment
-    let digest = compute_assignment_digest(raw_line, file_digest);
+    if options.excluded_fingerprints.is_excluded(fingerprint) {
+      continue;
+    }
+
+    // Look up the unique digest computed for this assignment while parsing
+    let digest = assignment.assignment_digests.get(fingerprint)
+      .context(format!("No assignment digest found for fingerprint: {}", fingerprint))?;
     
-    let (distribution_method, transport, ip, blocklist, distributed, state, bandwidth, ratio) =
-      parse_assignment_string(assignment_str);
+    let parsed = parser.parse(assignment_str);
+
+    for country in &parsed.blocklist_countries {
+      blocklist_data.push((digest.to_string(), country.clone()));
+    }
 
     batch_data.push((
       published_naive,
       digest.to_string(),
       fingerprint.to_string(),
-      distribution_method,
-      transport,
-      ip,
-      blocklist,
+      parsed.distribution_method,
+      parsed.transport,
+      parsed.ip,
+      parsed.blocklist,
       file_digest.to_string(), // Use file_digest as the foreign key
-      distributed.unwrap_or(false),
-      state,
-      bandwidth,
-      ratio,
+      parsed.distributed.unwrap_or(false),
+      parsed.state,
+      parsed.bandwidth,
+      parsed.bandwidth_bytes,
+      parsed.ratio,
+      last_modified_naive,
     ));
 
     if batch_data.len() >= batch_size {
-      insert_batch(&transaction, &batch_data).await?;
+      throttle.wait().await;
+      inserted += write_batch(transaction, &batch_data, options).await?;
+      write_blocklist_batch(transaction, &batch_data, &blocklist_data, options).await?;
       batch_data.clear();
+      blocklist_data.clear();
     }
   }
 
   if !batch_data.is_empty() {
-    insert_batch(&transaction, &batch_data).await?;
+    throttle.wait().await;
+    inserted += write_batch(transaction, &batch_data, options).await?;
+    write_blocklist_batch(transaction, &batch_data, &blocklist_data, options).await?;
   }
 
+  Ok(inserted)
+}
+
+/// Writes the blocklist countries of a batch of assignment rows.
+///
+/// With [`InsertMethod::Upsert`], the existing blocklist rows of the batch's assignments are deleted
+/// first, so upserted assignments end up with exactly their current countries; the other methods
+/// keep existing assignments, and their blocklist rows, unchanged.
+///
+/// # Arguments
+///
+/// * `transaction` - Active database transaction.
+/// * `batch_data` - The batch's assignment rows, already written.
+/// * `blocklist_data` - Pairs of (assignment digest, country code) for the batch.
+/// * `options` - Export options providing the insert method.
+///
+/// # Returns
+///
+/// * `Ok(())` - Rows written successfully.
+/// * `Err(anyhow::Error)` - Query execution failed.
+async fn write_blocklist_batch(
+  transaction: &Transaction<'_>,
+  batch_data: &[AssignmentRow],
+  blocklist_data: &[(String, String)],
+  options: &ExportOptions,
+) -> AnyhowResult<()> {
+  if options.insert_method == InsertMethod::Upsert {
+    let digests: Vec<&str> = batch_data.iter().map(|row| row.1.as_str()).collect();
+    transaction
+      .execute(
+        "DELETE FROM bridge_pool_assignment_blocklist WHERE assignment_digest = ANY($1::text[])",
+        &[&digests],
+      )
+      .await
+      .context("Failed to delete replaced rows from bridge_pool_assignment_blocklist")?;
+  }
+  insert_blocklist_batch(transaction, blocklist_data).await
+}
+
+/// Inserts the blocklist countries of a batch into the `bridge_pool_assignment_blocklist` table.
+///
+/// Must run after the batch's assignment rows have been inserted, as each row references its assignment.
+///
+/// # Arguments
+///
+/// * `transaction` - Active database transaction.
+/// * `blocklist_data` - Pairs of (assignment digest, country code).
+///
+/// # Returns
+///
+/// * `Ok(())` - Rows inserted successfully (or nothing to insert).
+/// * `Err(anyhow::Error)` - Query execution failed.
+async fn insert_blocklist_batch(
+  transaction: &Transaction<'_>,
+  blocklist_data: &[(String, String)],
+) -> AnyhowResult<()> {
+  if blocklist_data.is_empty() {
+    return Ok(());
+  }
+
+  let (digests, countries): (Vec<&str>, Vec<&str>) = blocklist_data
+    .iter()
+    .map(|(digest, country)| (digest.as_str(), country.as_str()))
+    .unzip();
+
+  transaction
+    .execute(
+      "INSERT INTO bridge_pool_assignment_blocklist (assignment_digest, country)
+      SELECT * FROM UNNEST($1::text[], $2::text[]) ON CONFLICT DO NOTHING",
+      &[&digests, &countries],
+    )
+    .await
+    .context("Failed to insert batch into bridge_pool_assignment_blocklist")?;
+
   Ok(())
 }
 
+/// Paces batch inserts so that consecutive batches are separated by a fixed delay.
+///
+/// The first batch of an export is never delayed; every later batch waits for the configured delay
+/// before being sent, regardless of which file it belongs to.
+struct BatchThrottle {
+  delay: Duration,
+  batches: usize,
+}
+
+impl BatchThrottle {
+  /// Creates a throttle applying `delay` between batches (zero disables it).
+  fn new(delay: Duration) -> Self {
+    Self { delay, batches: 0 }
+  }
+
+  /// Waits for the configured delay unless this is the first batch.
+  async fn wait(&mut self) {
+    if self.batches > 0 && !self.delay.is_zero() {
+      tokio::time::sleep(self.delay).await;
+    }
+    self.batches += 1;
+  }
+}
+
+/// Returns the assignment fields written for each row, in `AssignmentRow` order, including
+/// `last_modified` if enabled.
+fn assignment_fields(with_last_modified: bool) -> &'static [&'static str] {
+  let fields = &ColumnMapping::FIELDS;
+  if with_last_modified {
+    fields
+  } else {
+    &fields[..fields.len() - 1]
+  }
+}
+
+/// Returns the comma-separated assignment columns written for each row, named per `options.column_mapping`.
+fn assignment_column_list(options: &ExportOptions) -> String {
+  assignment_fields(options.with_assignment_last_modified)
+    .iter()
+    .map(|field| options.column_mapping.column(field))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// Returns the values of `data` in the order of [`assignment_fields`].
+fn assignment_params(data: &AssignmentRow, with_last_modified: bool) -> Vec<&(dyn ToSql + Sync)> {
+  let mut params: Vec<&(dyn ToSql + Sync)> = vec![
+    &data.0,  // published
+    &data.1,  // digest
+    &data.2,  // fingerprint
+    &data.3,  // distribution_method
+    &data.4,  // transport
+    &data.5,  // ip
+    &data.6,  // blocklist
+    &data.7,  // bridge_pool_assignments
+    &data.8,  // distributed
+    &data.9,  // state
+    &data.10, // bandwidth
+    &data.11, // bandwidth_bytes
+    &data.12, // ratio
+  ];
+  if with_last_modified {
+    params.push(&data.13); // last_modified
+  }
+  params
+}
+
+/// Writes a batch of assignment rows with the configured [`InsertMethod`].
+///
+/// # Arguments
+///
+/// * `transaction` - Active database transaction.
+/// * `batch_data` - Vector of tuples containing assignment data.
+/// * `options` - Export options providing the insert method, the column names and whether to write
+///   `last_modified`.
+///
+/// # Returns
+///
+/// * `Ok(usize)` - Number of rows inserted (or, for upserts, inserted or updated) by the batch.
+/// * `Err(anyhow::Error)` - Query execution failed.
+async fn write_batch(
+  transaction: &Transaction<'_>,
+  batch_data: &[AssignmentRow],
+  options: &ExportOptions,
+) -> AnyhowResult<usize> {
+  match options.insert_method {
+    InsertMethod::Insert => insert_batch(transaction, batch_data, options, "DO NOTHING").await,
+    InsertMethod::Upsert => {
+      let updates: Vec<String> = assignment_fields(options.with_assignment_last_modified)
+        .iter()
+        .filter(|field| **field != "digest")
+        .map(|field| format!("{0} = EXCLUDED.{0}", options.column_mapping.column(field)))
+        .collect();
+      let on_conflict = format!("DO UPDATE SET {}", updates.join(", "));
+      // A single upsert statement may not touch the same row twice, so keep the first row per
+      // digest like the other methods do.
+      let mut seen = HashSet::new();
+      let unique: Vec<AssignmentRow> =
+        batch_data.iter().filter(|row| seen.insert(row.1.as_str())).cloned().collect();
+      insert_batch(transaction, &unique, options, &on_conflict).await
+    }
+    InsertMethod::Copy => copy_batch(transaction, batch_data, options).await,
+  }
+}
+
 /// Executes a batch insert into the `bridge_pool_assignment` table.
 ///
 /// Constructs a dynamic SQL query for efficient multi-row insertion.
@@ -311,112 +959,788 @@ async fn insert_assignment_data(
 ///
 /// * `transaction` - Active database transaction.
 /// * `batch_data` - Vector of tuples containing assignment data.
+/// * `options` - Export options providing the column names and whether to write `last_modified`.
+/// * `on_conflict` - Action for rows whose digest already exists (e.g., "DO NOTHING").
 ///
 /// # Returns
 ///
-/// * `Ok(())` - Batch inserted successfully.
+/// * `Ok(usize)` - Number of rows written by the batch.
 /// * `Err(anyhow::Error)` - Query execution failed.
 async fn insert_batch(
   transaction: &Transaction<'_>,
-  batch_data: &[(chrono::NaiveDateTime, String, String, String, Option<String>, Option<String>, Option<String>, String, bool, Option<String>, Option<String>, Option<f32>)],
-) -> AnyhowResult<()> {
-  let mut params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = Vec::new();
+  batch_data: &[AssignmentRow],
+  options: &ExportOptions,
+  on_conflict: &str,
+) -> AnyhowResult<usize> {
+  let mut params: Vec<&(dyn ToSql + Sync)> = Vec::new();
   let mut placeholders = Vec::new();
 
-  for (j, data) in batch_data.iter().enumerate() {
-    params.extend_from_slice(&[
-      &data.0,  // published
-      &data.1,  // digest
-      &data.2,  // fingerprint
-      &data.3,  // distribution_method
-      &data.4,  // transport
-      &data.5,  // ip
-      &data.6,  // blocklist
-      &data.7,  // bridge_pool_assignments
-      &data.8,  // distributed
-      &data.9,  // state
-      &data.10, // bandwidth
-      &data.11, // ratio
-    ]);
-    let base = j * 12;
-    let placeholder = format!("(${},${},${},${},${},${},${},${},${},${},${},${})",
-      base + 1, base + 2, base + 3, base + 4, base + 5, base + 6,
-      base + 7, base + 8, base + 9, base + 10, base + 11, base + 12);
-    placeholders.push(placeholder);
+  for data in batch_data {
+    let base = params.len();
+    params.extend(assignment_params(data, options.with_assignment_last_modified));
+    let placeholder: Vec<String> = (base + 1..=params.len()).map(|i| format!("${}", i)).collect();
+    placeholders.push(format!("({})", placeholder.join(",")));
   }
 
   let sql = format!(
-    "INSERT INTO bridge_pool_assignment (
-      published, digest, fingerprint, distribution_method, transport, ip, 
-      blocklist, bridge_pool_assignments, distributed, state, bandwidth, ratio
-    ) VALUES {} ON CONFLICT (digest) DO NOTHING",
-    placeholders.join(",")
+    "INSERT INTO bridge_pool_assignment ({}) VALUES {} ON CONFLICT ({}) {}",
+    assignment_column_list(options),
+    placeholders.join(","),
+    options.column_mapping.column("digest"),
+    on_conflict
   );
 
-  transaction
+  let inserted = transaction
     .execute(sql.as_str(), &params)
     .await
     .context("Failed to insert batch into bridge_pool_assignment")?;
   
-  Ok(())
+  Ok(inserted as usize)
 }
 
-/// Parses an assignment string into structured fields.
+/// Loads a batch into the `bridge_pool_assignment` table with `COPY`.
 ///
-/// Extracts various assignment properties from the string representation.
+/// `COPY` cannot skip rows that already exist, so the batch is copied into a temporary staging table
+/// (dropped at commit) and moved over with `INSERT ... SELECT ... ON CONFLICT DO NOTHING`, which keeps
+/// the same re-import behaviour as [`InsertMethod::Insert`].
 ///
 /// # Arguments
 ///
-/// * `assignment_str` - The assignment string (e.g., "email transport=obfs4").
+/// * `transaction` - Active database transaction.
+/// * `batch_data` - Vector of tuples containing assignment data.
+/// * `options` - Export options providing the column names and whether to write `last_modified`.
 ///
 /// # Returns
 ///
-/// A tuple of extracted fields in the format:
-/// (distribution_method, transport, ip, blocklist, distributed, state, bandwidth, ratio)
-fn parse_assignment_string(assignment_str: &str) -> (
-  String, 
-  Option<String>,
-  Option<String>,
-  Option<String>,
-  Option<bool>,
-  Option<String>,
-  Option<String>,
-  Option<f32>
-) {
-  // Extract distribution method (first token)
-  let parts: Vec<&str> = assignment_str.splitn(2, ' ').collect();
-  let distribution_method = parts[0].to_string();
-  
-  // Default return values
-  let mut transport = None;
-  let mut ip = None;
-  let mut blocklist = None;
-  let mut distributed = None;
-  let mut state = None;
-  let mut bandwidth = None;
-  let mut ratio = None;
-  
-  if parts.len() > 1 {
-    // Process key=value pairs
-    let rest = parts[1];
-    let pairs: Vec<&str> = rest.split_whitespace().collect();
-    
-    for pair in pairs {
-      let kv: Vec<&str> = pair.splitn(2, '=').collect();
-      if kv.len() == 2 {
-        match kv[0] {
-          "transport" => transport = Some(kv[1].to_string()),
-          "ip" => ip = Some(kv[1].to_string()),
-          "blocklist" => blocklist = Some(kv[1].to_string()),
-          "distributed" => distributed = Some(kv[1].to_lowercase() == "true"),
-          "state" => state = Some(kv[1].to_string()),
-          "bandwidth" => bandwidth = Some(kv[1].to_string()),
-          "ratio" => ratio = kv[1].parse::<f32>().ok(),
-          _ => {} // Ignore unknown properties
-        }
+/// * `Ok(usize)` - Number of rows inserted by the batch.
+/// * `Err(anyhow::Error)` - Copying or moving the rows failed.
+async fn copy_batch(
+  transaction: &Transaction<'_>,
+  batch_data: &[AssignmentRow],
+  options: &ExportOptions,
+) -> AnyhowResult<usize> {
+  let with_last_modified = options.with_assignment_last_modified;
+  transaction
+    .batch_execute(
+      "CREATE TEMP TABLE IF NOT EXISTS bridge_pool_assignment_staging
+        (LIKE bridge_pool_assignment INCLUDING DEFAULTS) ON COMMIT DROP;
+      TRUNCATE bridge_pool_assignment_staging",
+    )
+    .await
+    .context("Failed to prepare the bridge_pool_assignment staging table")?;
+
+  let columns = assignment_column_list(options);
+  let mut types = vec![
+    Type::TIMESTAMP, Type::TEXT, Type::TEXT, Type::TEXT, Type::TEXT, Type::TEXT, Type::TEXT,
+    Type::TEXT, Type::BOOL, Type::TEXT, Type::TEXT, Type::INT8, Type::FLOAT4,
+  ];
+  if with_last_modified {
+    types.push(Type::TIMESTAMP);
+  }
+  let sink = transaction
+    .copy_in(&format!("COPY bridge_pool_assignment_staging ({}) FROM STDIN BINARY", columns))
+    .await
+    .context("Failed to start COPY into the staging table")?;
+  let writer = BinaryCopyInWriter::new(sink, &types);
+  futures::pin_mut!(writer);
+  for data in batch_data {
+    writer
+      .as_mut()
+      .write(&assignment_params(data, with_last_modified))
+      .await
+      .context("Failed to COPY row into the staging table")?;
+  }
+  writer.finish().await.context("Failed to finish COPY into the staging table")?;
+
+  let inserted = transaction
+    .execute(
+      &format!(
+        "INSERT INTO bridge_pool_assignment ({0}) SELECT {0} FROM bridge_pool_assignment_staging
+        ON CONFLICT ({1}) DO NOTHING",
+        columns,
+        options.column_mapping.column("digest")
+      ),
+      &[],
+    )
+    .await
+    .context("Failed to move staged rows into bridge_pool_assignment")?;
+  Ok(inserted as usize)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::export::FingerprintFilter;
+  use crate::fetch::BridgePoolFile;
+  use crate::parse::{parse_bridge_pool_files, parse_bridge_pool_files_keeping_raw};
+
+  /// Tests that the DDL covers every table and index, with optional ones only when enabled.
+  #[test]
+  fn test_schema_ddl() {
+    let default_ddl = schema_ddl(&ExportOptions::default()).unwrap().join(";\n");
+    for name in [
+      "TABLE IF NOT EXISTS bridge_pool_assignments_file",
+      "TABLE IF NOT EXISTS bridge_pool_assignment (",
+      "TABLE IF NOT EXISTS bridge_pool_assignment_blocklist",
+      "INDEX IF NOT EXISTS bridge_pool_assignment_file_published",
+      "INDEX IF NOT EXISTS bridge_pool_assignment_published",
+      "INDEX IF NOT EXISTS bridge_pool_assignment_fingerprint\n",
+      "INDEX IF NOT EXISTS bridge_pool_assignment_fingerprint_published_desc_index",
+      "INDEX IF NOT EXISTS bridge_pool_assignment_blocklist_country",
+    ] {
+      assert!(default_ddl.contains(name), "missing {}", name);
+    }
+    assert!(!default_ddl.contains("bridge_pool_assignment_daily"));
+    assert!(!default_ddl.contains("raw_content"));
+
+    let full_ddl = schema_ddl(&ExportOptions {
+      with_daily_summary: true,
+      store_raw: true,
+      ..Default::default()
+    })
+    .unwrap()
+    .join(";\n");
+    assert!(full_ddl.contains("CREATE TABLE IF NOT EXISTS bridge_pool_assignment_daily"));
+    assert!(full_ddl.contains("ADD COLUMN IF NOT EXISTS raw_content BYTEA"));
+    assert!(!full_ddl.contains("search_path"));
+
+    let schema_ddl = schema_ddl(&ExportOptions {
+      schema: Some("metrics".to_string()),
+      ..Default::default()
+    })
+    .unwrap();
+    assert_eq!(schema_ddl[..2], ["CREATE SCHEMA IF NOT EXISTS \"metrics\"", "SET search_path TO \"metrics\""]);
+  }
+
+  /// Tests quoting identifiers, including embedded quotes and invalid names.
+  #[test]
+  fn test_quote_identifier() {
+    assert_eq!(quote_identifier("metrics").unwrap(), "\"metrics\"");
+    assert_eq!(quote_identifier("Tor \"x\"; DROP").unwrap(), "\"Tor \"\"x\"\"; DROP\"");
+    assert!(quote_identifier("").is_err());
+    assert!(quote_identifier(&"a".repeat(64)).is_err());
+    assert!(quote_identifier("a\0b").is_err());
+  }
+
+  /// Tests parsing column mappings and rejecting invalid ones.
+  #[test]
+  fn test_column_mapping() {
+    let mapping = ColumnMapping::parse("fingerprint=bridge_fp, published=Published At").unwrap();
+    assert_eq!(mapping.name("fingerprint"), "bridge_fp");
+    assert_eq!(mapping.column("published"), "\"Published At\"");
+    assert_eq!(mapping.column("digest"), "digest");
+    assert!(!mapping.is_default());
+    assert!(ColumnMapping::parse("").unwrap().is_default());
+
+    assert!(ColumnMapping::parse("fingerprnt=fp").is_err());
+    assert!(ColumnMapping::parse("fingerprint").is_err());
+    assert!(ColumnMapping::parse("fingerprint=a,fingerprint=b").is_err());
+    assert!(ColumnMapping::parse("fingerprint=").is_err());
+    // Two fields in one column, also when the other field is unmapped
+    assert!(ColumnMapping::parse("fingerprint=x,state=x").is_err());
+    assert!(ColumnMapping::parse("fingerprint=digest").is_err());
+  }
+
+  /// Returns the connection string of the PostgreSQL instance used by the database tests.
+  fn test_db_params() -> String {
+    std::env::var("TEST_DB_PARAMS")
+      .unwrap_or_else(|_| "host=localhost user=postgres dbname=postgres".to_string())
+  }
+
+  /// Recreates an empty schema for a single test and returns a client and connection string whose
+  /// `search_path` points at it, so database tests can run concurrently without sharing tables.
+  async fn scratch_schema(name: &str) -> (Client, String) {
+    let schema = format!("bpa_test_{}", name);
+    let db_params = format!("{} options='-c search_path={}'", test_db_params(), schema);
+    let (client, connection) = tokio_postgres::connect(&db_params, NoTls)
+      .await
+      .expect("Failed to connect to the test database");
+    tokio::spawn(connection);
+    client
+      .batch_execute(&format!("DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}", schema))
+      .await
+      .expect("Failed to create the test schema");
+    (client, db_params)
+  }
+
+  /// Parses `(path, content)` pairs into assignments ready for export, keeping their raw content.
+  fn parse_files(files: &[(&str, &str)]) -> Vec<ParsedBridgePoolAssignment> {
+    let files = files
+      .iter()
+      .map(|(path, content)| BridgePoolFile {
+        path: path.to_string(),
+        last_modified: 0,
+        content: content.to_string(),
+        raw_content: content.as_bytes().to_vec(),
+      })
+      .collect();
+    parse_bridge_pool_files_keeping_raw(files).unwrap()
+  }
+
+  /// Tests that the throttle delays every batch but the first.
+  #[tokio::test]
+  async fn test_batch_throttle_applies_delay_between_batches() {
+    let mut throttle = BatchThrottle::new(Duration::from_millis(20));
+    let start = std::time::Instant::now();
+
+    throttle.wait().await;
+    assert!(start.elapsed() < Duration::from_millis(20));
+
+    throttle.wait().await;
+    throttle.wait().await;
+    assert!(start.elapsed() >= Duration::from_millis(40));
+  }
+
+  /// Tests that a zero delay leaves batches unthrottled.
+  #[tokio::test]
+  async fn test_batch_throttle_disabled_by_default() {
+    let mut throttle = BatchThrottle::new(ExportOptions::default().batch_delay);
+    let start = std::time::Instant::now();
+
+    for _ in 0..100 {
+      throttle.wait().await;
+    }
+
+    assert!(start.elapsed() < Duration::from_millis(20));
+  }
+
+  /// Tests that the daily summary counts match the raw assignment rows of a seeded day.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_daily_summary_matches_raw_counts() {
+    let (client, db_params) = scratch_schema("daily_summary").await;
+    let parsed = parse_files(&[
+      ("a", "bridge-pool-assignment 2022-04-09 00:29:37\n\
+        005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n\
+        01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https\n"),
+      ("b", "bridge-pool-assignment 2022-04-09 12:00:00\n\
+        005fd4d7decbb250055b861579e6fdc79ad17bee email\n\
+        02a7c1d2b1c7e5d1d2a1c7e5d1d2a1c7e5d1d2a1 email\n"),
+      ("c", "bridge-pool-assignment 2022-04-10 00:00:00\n\
+        005fd4d7decbb250055b861579e6fdc79ad17bee moat\n"),
+    ]);
+    let options = ExportOptions { with_daily_summary: true, ..Default::default() };
+    export_to_postgres(parsed, &db_params, &options).await.unwrap();
+
+    let raw: Vec<(String, i64)> = client
+      .query(
+        "SELECT distribution_method, COUNT(*) FROM bridge_pool_assignment
+        WHERE published::date = '2022-04-09' GROUP BY distribution_method ORDER BY distribution_method",
+        &[],
+      )
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| (row.get(0), row.get(1)))
+      .collect();
+    let summary: Vec<(String, i64)> = client
+      .query(
+        "SELECT distribution_method, bridge_count FROM bridge_pool_assignment_daily
+        WHERE date = '2022-04-09' ORDER BY distribution_method",
+        &[],
+      )
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| (row.get(0), row.get(1)))
+      .collect();
+
+    assert_eq!(summary, raw);
+    assert_eq!(summary, vec![("email".to_string(), 3), ("https".to_string(), 1)]);
+  }
+
+  /// Tests that each country of a multi-country blocklist gets its own side-table row.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_blocklist_countries_exported() {
+    let (client, db_params) = scratch_schema("blocklist").await;
+    let parsed = parse_files(&[
+      ("a", "bridge-pool-assignment 2022-04-09 00:29:37\n\
+        005fd4d7decbb250055b861579e6fdc79ad17bee moat blocklist=cn,ir\n\
+        01ea4fb2da2086e71e7ca84c683fcadd2aa9036b email\n"),
+    ]);
+    export_to_postgres(parsed, &db_params, &ExportOptions::default()).await.unwrap();
+
+    let rows: Vec<(String, String)> = client
+      .query(
+        "SELECT a.fingerprint, b.country FROM bridge_pool_assignment_blocklist b
+        JOIN bridge_pool_assignment a ON a.digest = b.assignment_digest ORDER BY b.country",
+        &[],
+      )
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| (row.get(0), row.get(1)))
+      .collect();
+    let raw: String = client
+      .query_one("SELECT blocklist FROM bridge_pool_assignment WHERE blocklist IS NOT NULL", &[])
+      .await
+      .unwrap()
+      .get(0);
+
+    let fingerprint = "005fd4d7decbb250055b861579e6fdc79ad17bee".to_string();
+    assert_eq!(rows, vec![(fingerprint.clone(), "cn".to_string()), (fingerprint, "ir".to_string())]);
+    assert_eq!(raw, "cn,ir");
+  }
+
+  /// Tests that raw content stored with `store_raw` re-parses to the same structure.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_store_raw_round_trip() {
+    let (client, db_params) = scratch_schema("store_raw").await;
+    let content = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+      005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n\
+      01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https blocklist=cn\n";
+    let options = ExportOptions { store_raw: true, ..Default::default() };
+    export_to_postgres(parse_files(&[("a", content)]), &db_params, &options).await.unwrap();
+
+    let row = client
+      .query_one("SELECT digest, raw_content FROM bridge_pool_assignments_file", &[])
+      .await
+      .unwrap();
+    let digest: String = row.get(0);
+    let raw_content: Vec<u8> = row.get(1);
+    let reparsed = parse_bridge_pool_files(vec![BridgePoolFile {
+      path: "from-db".to_string(),
+      last_modified: 0,
+      content: String::from_utf8(raw_content.clone()).unwrap(),
+      raw_content,
+    }])
+    .unwrap();
+    let original = parse_files(&[("a", content)]);
+
+    assert_eq!(reparsed[0].published_millis, original[0].published_millis);
+    assert_eq!(reparsed[0].entries, original[0].entries);
+    assert_eq!(reparsed[0].file_digest, Digest::parse(&digest).unwrap());
+  }
+
+  /// Tests that verification passes for a correct export, including re-exports of existing rows, and
+  /// fails when a digest is missing from the database.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_verify_after_export() {
+    let (client, db_params) = scratch_schema("verify").await;
+    let content = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+      005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n\
+      01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https\n";
+    let options = ExportOptions { verify_after_export: true, ..Default::default() };
+
+    export_to_postgres(parse_files(&[("a", content)]), &db_params, &options).await.unwrap();
+    let summary = export_to_postgres(parse_files(&[("a", content)]), &db_params, &options).await.unwrap();
+    assert_eq!(summary, ExportSummary::default());
+
+    let parsed = parse_files(&[("a", content)]);
+    let file_digests = vec![parsed[0].file_digest.to_string()];
+    let mut assignment_digests: Vec<String> =
+      parsed[0].assignment_digests.values().map(|digest| digest.to_string()).collect();
+    verify_export(&client, &file_digests, &assignment_digests, &ColumnMapping::default()).await.unwrap();
+
+    assignment_digests.push("0".repeat(64));
+    let err = verify_export(&client, &file_digests, &assignment_digests, &ColumnMapping::default())
+      .await
+      .unwrap_err();
+    assert_eq!(err.to_string(), "Expected 3 rows in bridge_pool_assignment, found 2");
+  }
+
+  /// Tests that files are tagged with the freshness category of the directory they came from.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_freshness_exported() {
+    let (client, db_params) = scratch_schema("freshness").await;
+    let parsed = parse_files(&[
+      (
+        "recent/bridge-pool-assignments/2022-04-10-00-29-37",
+        "bridge-pool-assignment 2022-04-10 00:29:37\n005fd4d7decbb250055b861579e6fdc79ad17bee email\n",
+      ),
+      (
+        "archive/bridge-pool-assignments/2022-04-09-00-29-37",
+        "bridge-pool-assignment 2022-04-09 00:29:37\n005fd4d7decbb250055b861579e6fdc79ad17bee email\n",
+      ),
+    ]);
+
+    export_to_postgres(parsed, &db_params, &ExportOptions::default()).await.unwrap();
+
+    let freshness: Vec<String> = client
+      .query("SELECT freshness FROM bridge_pool_assignments_file ORDER BY published", &[])
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| row.get(0))
+      .collect();
+    assert_eq!(freshness, ["archive", "recent"]);
+  }
+
+  /// Tests that numeric bandwidths are stored in bytes next to the raw value.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_bandwidth_bytes_exported() {
+    let (client, db_params) = scratch_schema("bandwidth_bytes").await;
+    let parsed = parse_files(&[(
+      "a",
+      "bridge-pool-assignment 2022-04-09 00:29:37\n\
+      005fd4d7decbb250055b861579e6fdc79ad17bee email bandwidth=1024KB\n\
+      00a9bd0ba5a2b8f5ff9a2fe4a1e0a5c26a0d3e3c moat bandwidth=sufficient\n",
+    )]);
+
+    export_to_postgres(parsed, &db_params, &ExportOptions::default()).await.unwrap();
+
+    let rows: Vec<(String, Option<i64>)> = client
+      .query("SELECT bandwidth, bandwidth_bytes FROM bridge_pool_assignment ORDER BY fingerprint", &[])
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| (row.get(0), row.get(1)))
+      .collect();
+    assert_eq!(
+      rows,
+      [("1024KB".to_string(), Some(1_024_000)), ("sufficient".to_string(), None)]
+    );
+  }
+
+  /// Tests that assignment rows carry their file's last-modified time when enabled.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_assignment_last_modified_exported() {
+    let (client, db_params) = scratch_schema("assignment_last_modified").await;
+    let mut parsed = parse_files(&[(
+      "a",
+      "bridge-pool-assignment 2022-04-09 00:29:37\n\
+      005fd4d7decbb250055b861579e6fdc79ad17bee email\n\
+      00a9bd0ba5a2b8f5ff9a2fe4a1e0a5c26a0d3e3c moat\n",
+    )]);
+    parsed[0].last_modified_millis = 1_649_464_200_000;
+    let options = ExportOptions {
+      with_assignment_last_modified: true,
+      ..Default::default()
+    };
+
+    export_to_postgres(parsed, &db_params, &options).await.unwrap();
+
+    let last_modified: Vec<NaiveDateTime> = client
+      .query("SELECT last_modified FROM bridge_pool_assignment", &[])
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| row.get(0))
+      .collect();
+    let expected = datetime_from_millis(1_649_464_200_000).unwrap().naive_utc();
+    assert_eq!(last_modified, [expected, expected]);
+  }
+
+  /// Tests that an excluded fingerprint, given in another case, gets no assignment or blocklist rows
+  /// while the other entries and the file are exported and pass verification.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_excluded_fingerprints() {
+    let (client, db_params) = scratch_schema("excluded_fingerprints").await;
+    let parsed = parse_files(&[("a", "bridge-pool-assignment 2022-04-09 00:29:37
+      005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4 blocklist=ru,cn
+      01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https
+      02a7c1d2b1c7e5d1d2a1c7e5d1d2a1c7e5d1d2a1 moat
+")]);
+    let options = ExportOptions {
+      excluded_fingerprints: FingerprintFilter::new(["005FD4D7DECBB250055B861579E6FDC79AD17BEE"]).unwrap(),
+      verify_after_export: true,
+      ..Default::default()
+    };
+
+    let summary = export_to_postgres(parsed, &db_params, &options).await.unwrap();
+
+    let fingerprints: Vec<String> = client
+      .query("SELECT fingerprint FROM bridge_pool_assignment ORDER BY fingerprint", &[])
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| row.get(0))
+      .collect();
+    let blocklist_rows: i64 = client
+      .query_one("SELECT COUNT(*) FROM bridge_pool_assignment_blocklist", &[])
+      .await
+      .unwrap()
+      .get(0);
+    assert_eq!(
+      fingerprints,
+      ["01ea4fb2da2086e71e7ca84c683fcadd2aa9036b", "02a7c1d2b1c7e5d1d2a1c7e5d1d2a1c7e5d1d2a1"]
+    );
+    assert_eq!(blocklist_rows, 0);
+    assert_eq!(summary.files_exported, 1);
+    assert_eq!(summary.assignments_exported, 2);
+  }
+
+  /// Tests that storing raw content is rejected together with excluded fingerprints, before connecting,
+  /// since the raw content would still hold the excluded lines.
+  #[tokio::test]
+  async fn test_store_raw_rejected_with_excluded_fingerprints() {
+    let options = ExportOptions {
+      store_raw: true,
+      excluded_fingerprints: FingerprintFilter::new(["005fd4d7decbb250055b861579e6fdc79ad17bee"]).unwrap(),
+      ..Default::default()
+    };
+
+    let err = export_to_postgres(Vec::new(), "host=db.invalid", &options).await.unwrap_err();
+
+    assert!(err.to_string().contains("cannot be combined with excluded fingerprints"), "{:#}", err);
+  }
+
+  /// Tests that re-exporting the same content with a changed last-modified time leaves the stored
+  /// metadata alone by default and updates it with `upsert_file_metadata`, without duplicating rows.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_upsert_file_metadata() {
+    let (client, db_params) = scratch_schema("upsert_file_metadata").await;
+    let content = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+      005fd4d7decbb250055b861579e6fdc79ad17bee email\n";
+    let export = |last_modified_millis, upsert_file_metadata| {
+      let mut parsed = parse_files(&[("a", content)]);
+      parsed[0].last_modified_millis = last_modified_millis;
+      let options = ExportOptions {
+        upsert_file_metadata,
+        ..Default::default()
+      };
+      let db_params = db_params.clone();
+      async move { export_to_postgres(parsed, &db_params, &options).await.unwrap() }
+    };
+    let stored = || async {
+      let rows = client
+        .query("SELECT last_modified FROM bridge_pool_assignments_file", &[])
+        .await
+        .unwrap();
+      rows.iter().map(|row| row.get(0)).collect::<Vec<Option<NaiveDateTime>>>()
+    };
+    let first = datetime_from_millis(1_649_464_200_000).unwrap().naive_utc();
+    let second = datetime_from_millis(1_649_467_800_000).unwrap().naive_utc();
+
+    export(1_649_464_200_000, false).await;
+    let summary = export(1_649_467_800_000, false).await;
+    assert_eq!(summary.files_exported, 0);
+    assert_eq!(stored().await, [Some(first)]);
+
+    let summary = export(1_649_467_800_000, true).await;
+    assert_eq!(summary.files_exported, 1);
+    assert_eq!(stored().await, [Some(second)]);
+
+    // Unchanged metadata is not rewritten
+    let summary = export(1_649_467_800_000, true).await;
+    assert_eq!(summary.files_exported, 0);
+    assert_eq!(summary.assignments_exported, 0);
+  }
+
+  /// Tests that a configured schema is created and receives the tables instead of the default schema.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_export_into_custom_schema() {
+    // The scratch schema is the connection's default, standing in for `public`.
+    let (client, db_params) = scratch_schema("custom_schema_default").await;
+    let custom_schema = "bpa_test_Custom \"Schema\"";
+    client
+      .batch_execute("DROP SCHEMA IF EXISTS \"bpa_test_Custom \"\"Schema\"\"\" CASCADE")
+      .await
+      .unwrap();
+    let parsed = parse_files(&[(
+      "a",
+      "bridge-pool-assignment 2022-04-09 00:29:37\n005fd4d7decbb250055b861579e6fdc79ad17bee email\n",
+    )]);
+    let options = ExportOptions {
+      schema: Some(custom_schema.to_string()),
+      verify_after_export: true,
+      ..Default::default()
+    };
+
+    let summary = export_to_postgres(parsed, &db_params, &options).await.unwrap();
+
+    assert_eq!(summary.assignments_exported, 1);
+    let tables_by_schema: Vec<(String, i64)> = client
+      .query(
+        "SELECT table_schema::text, count(*) FROM information_schema.tables
+        WHERE table_schema IN ($1, 'bpa_test_custom_schema_default') GROUP BY table_schema",
+        &[&custom_schema],
+      )
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| (row.get(0), row.get(1)))
+      .collect();
+    assert_eq!(tables_by_schema, [(custom_schema.to_string(), 3)]);
+  }
+
+  /// Tests that an out-of-range publication timestamp fails the export with a clear error.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_extreme_published_timestamp_rejected() {
+    let (_client, db_params) = scratch_schema("extreme_timestamp").await;
+    let mut parsed = parse_files(&[("a", "bridge-pool-assignment 2022-04-09 00:29:37\n")]);
+    parsed[0].published_millis = i64::MIN;
+
+    let err = export_to_postgres(parsed, &db_params, &ExportOptions::default()).await.unwrap_err();
+
+    assert!(format!("{:#}", err).contains("outside the supported range"), "{:#}", err);
+  }
+
+  /// Tests that the stored header is the file's own header line unless the caller overrides it.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_stored_header_matches_source_file() {
+    let (client, db_params) = scratch_schema("header").await;
+    let first = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+      005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n";
+    let second = "bridge-pool-assignment 2022-04-10 00:29:37\n\
+      01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https\n";
+
+    export_to_postgres(parse_files(&[("a", first)]), &db_params, &ExportOptions::default()).await.unwrap();
+    let options = ExportOptions { header: Some("custom-header".to_string()), ..Default::default() };
+    export_to_postgres(parse_files(&[("b", second)]), &db_params, &options).await.unwrap();
+
+    let headers: Vec<String> = client
+      .query("SELECT header FROM bridge_pool_assignments_file ORDER BY published", &[])
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| row.get(0))
+      .collect();
+    assert_eq!(headers, ["bridge-pool-assignment 2022-04-09 00:29:37", "custom-header"]);
+  }
+
+  /// Returns the assignment and blocklist rows as JSON text, ordered by digest.
+  async fn assignment_tables(client: &Client) -> (Vec<String>, Vec<String>) {
+    let mut tables = Vec::new();
+    for sql in [
+      "SELECT row_to_json(a)::text FROM bridge_pool_assignment a ORDER BY digest",
+      "SELECT row_to_json(b)::text FROM bridge_pool_assignment_blocklist b ORDER BY assignment_digest, country",
+    ] {
+      let rows: Vec<String> = client.query(sql, &[]).await.unwrap().iter().map(|row| row.get(0)).collect();
+      tables.push(rows);
+    }
+    let blocklist = tables.pop().unwrap();
+    (tables.pop().unwrap(), blocklist)
+  }
+
+  /// Tests that every insert method yields identical assignment and blocklist rows for a first-time
+  /// import, and that an upsert replaces stale blocklist rows of the assignments it rewrites.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_insert_methods_produce_identical_rows() {
+    let files = [
+      ("a", "bridge-pool-assignment 2022-04-09 00:29:37\n\
+        005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4 ip=4 blocklist=cn,ir\n\
+        01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https distributed=true state=functional bandwidth=1MB ratio=0.5\n"),
+      ("b", "bridge-pool-assignment 2022-04-10 12:00:00\n\
+        005fd4d7decbb250055b861579e6fdc79ad17bee moat\n"),
+    ];
+    let mut tables = Vec::new();
+    for (name, insert_method) in
+      [("insert", InsertMethod::Insert), ("copy", InsertMethod::Copy), ("upsert", InsertMethod::Upsert)]
+    {
+      let (client, db_params) = scratch_schema(&format!("method_{}", name)).await;
+      let options = ExportOptions { insert_method, with_assignment_last_modified: true, ..Default::default() };
+      let summary = export_to_postgres(parse_files(&files), &db_params, &options).await.unwrap();
+      assert_eq!(summary.assignments_exported, 3, "{}", name);
+      tables.push(assignment_tables(&client).await);
+
+      if insert_method == InsertMethod::Upsert {
+        client
+          .batch_execute(
+            "DELETE FROM bridge_pool_assignment_blocklist WHERE country = 'ir';
+            INSERT INTO bridge_pool_assignment_blocklist (assignment_digest, country)
+              SELECT digest, 'ru' FROM bridge_pool_assignment WHERE blocklist IS NOT NULL",
+          )
+          .await
+          .unwrap();
+        export_to_postgres(parse_files(&files), &db_params, &options).await.unwrap();
+        assert_eq!(assignment_tables(&client).await, tables[0], "{}", name);
       }
     }
+    assert_eq!(tables[0].1.len(), 2);
+    assert_eq!(tables[0], tables[1]);
+    assert_eq!(tables[0], tables[2]);
   }
-  
-  (distribution_method, transport, ip, blocklist, distributed, state, bandwidth, ratio)
-} 
\ No newline at end of file
+
+  /// Tests that a table created by the legacy exporter is detected before exporting.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_legacy_schema_detected() {
+    let (client, db_params) = scratch_schema("legacy").await;
+    client
+      .batch_execute(
+        "CREATE TABLE bridge_pool_assignment (
+          id SERIAL PRIMARY KEY,
+          published TIMESTAMP NOT NULL,
+          digest TEXT NOT NULL,
+          fingerprint TEXT NOT NULL,
+          distribution_method TEXT NOT NULL
+        )",
+      )
+      .await
+      .unwrap();
+    let parsed = parse_files(&[(
+      "a",
+      "bridge-pool-assignment 2022-04-09 00:29:37\n005fd4d7decbb250055b861579e6fdc79ad17bee email\n",
+    )]);
+
+    let err = export_to_postgres(parsed, &db_params, &ExportOptions::default()).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("legacy layout (an `id` column)"), "{:#}", err);
+  }
+
+  /// Tests exporting into an existing table with renamed columns through a column mapping.
+  #[tokio::test]
+  #[ignore = "requires PostgreSQL; set TEST_DB_PARAMS"]
+  async fn test_export_with_column_mapping() {
+    let (client, db_params) = scratch_schema("column_mapping").await;
+    client
+      .batch_execute(
+        "CREATE TABLE bridge_pool_assignments_file (
+          published TIMESTAMP NOT NULL, header TEXT NOT NULL, digest TEXT PRIMARY KEY, freshness TEXT
+        );
+        CREATE TABLE bridge_pool_assignment (
+          id BIGSERIAL,
+          published_at TIMESTAMP NOT NULL,
+          row_digest TEXT PRIMARY KEY,
+          bridge_fp TEXT NOT NULL,
+          method TEXT NOT NULL,
+          transport TEXT, ip TEXT, blocklist TEXT, bridge_pool_assignments TEXT,
+          distributed BOOLEAN, state TEXT, bandwidth TEXT, ratio REAL
+        )",
+      )
+      .await
+      .unwrap();
+    let parsed = || {
+      parse_files(&[(
+        "a",
+        "bridge-pool-assignment 2022-04-09 00:29:37\n005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n",
+      )])
+    };
+
+    let options = ExportOptions { with_daily_summary: true, verify_after_export: true, ..Default::default() };
+    let err = export_to_postgres(parsed(), &db_params, &options).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("no column \"published\", \"digest\", \"fingerprint\""), "{:#}", err);
+
+    let options = ExportOptions {
+      column_mapping: ColumnMapping::parse(
+        "published=published_at,digest=row_digest,fingerprint=bridge_fp,distribution_method=method",
+      )
+      .unwrap(),
+      ..options
+    };
+    for insert_method in [InsertMethod::Insert, InsertMethod::Copy, InsertMethod::Upsert] {
+      let options = ExportOptions { insert_method, ..options.clone() };
+      export_to_postgres(parsed(), &db_params, &options).await.unwrap();
+    }
+
+    let rows: Vec<(String, String, String)> = client
+      .query("SELECT bridge_fp, method, transport FROM bridge_pool_assignment", &[])
+      .await
+      .unwrap()
+      .iter()
+      .map(|row| (row.get(0), row.get(1), row.get(2)))
+      .collect();
+    assert_eq!(
+      rows,
+      [("005fd4d7decbb250055b861579e6fdc79ad17bee".to_string(), "email".to_string(), "obfs4".to_string())]
+    );
+    let bridge_count: i64 = client
+      .query_one("SELECT bridge_count FROM bridge_pool_assignment_daily", &[])
+      .await
+      .unwrap()
+      .get(0);
+    assert_eq!(bridge_count, 1);
+  }
+}
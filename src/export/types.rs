@@ -0,0 +1,335 @@
+use super::postgres::quote_identifier;
+use crate::parse::{AssignmentParser, DefaultAssignmentParser, ParsedBridgePoolAssignment};
+use anyhow::{Context, Result as AnyhowResult};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How assignment rows are written to PostgreSQL.
+///
+/// All methods produce the same table contents for a first-time import; they differ in speed and in
+/// how rows that already exist are treated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InsertMethod {
+    /// Multi-row `INSERT ... ON CONFLICT DO NOTHING`: existing rows are kept unchanged.
+    #[default]
+    Insert,
+    /// `COPY` into a staging table, then moved over skipping existing rows. Fastest for large
+    /// imports, with the same re-import behaviour as `Insert`.
+    Copy,
+    /// `INSERT ... ON CONFLICT DO UPDATE`: existing rows, and their blocklist rows, are overwritten
+    /// with the freshly parsed values, e.g. after a parser fix. Slowest, since every existing row is
+    /// rewritten.
+    Upsert,
+}
+
+/// Actual column names of an existing `bridge_pool_assignment` table whose columns are named
+/// differently from the crate's fields.
+///
+/// Fields without an entry keep their own name as column name, so the default mapping describes the
+/// table the crate creates itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnMapping {
+    columns: BTreeMap<&'static str, String>,
+}
+
+impl ColumnMapping {
+    /// The crate's assignment fields, which are also the default column names.
+    pub const FIELDS: [&'static str; 14] = [
+        "published",
+        "digest",
+        "fingerprint",
+        "distribution_method",
+        "transport",
+        "ip",
+        "blocklist",
+        "bridge_pool_assignments",
+        "distributed",
+        "state",
+        "bandwidth",
+        "bandwidth_bytes",
+        "ratio",
+        "last_modified",
+    ];
+
+    /// Creates a mapping from `(field, column)` pairs.
+    ///
+    /// # Arguments
+    ///
+    /// * `pairs` - Crate field names (see [`ColumnMapping::FIELDS`]) and the columns they are stored in.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ColumnMapping)` - The mapping.
+    /// * `Err(anyhow::Error)` - A field is unknown or mapped twice, a column name is not a valid
+    ///   identifier, or two fields would end up in the same column.
+    pub fn new<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> AnyhowResult<Self> {
+        let mut columns = BTreeMap::new();
+        for (field, column) in pairs {
+            let field = Self::FIELDS
+                .into_iter()
+                .find(|known| *known == field)
+                .with_context(|| format!("Unknown field {:?}; expected one of {}", field, Self::FIELDS.join(", ")))?;
+            quote_identifier(column).with_context(|| format!("Invalid column name for field {}", field))?;
+            if columns.insert(field, column.to_string()).is_some() {
+                return Err(anyhow::anyhow!("Field {} is mapped more than once", field));
+            }
+        }
+        let mapping = Self { columns };
+        let mut used = HashSet::new();
+        for field in Self::FIELDS {
+            if !used.insert(mapping.name(field)) {
+                return Err(anyhow::anyhow!("Column {:?} is used for more than one field", mapping.name(field)));
+            }
+        }
+        Ok(mapping)
+    }
+
+    /// Parses a mapping from a comma-separated list of `field=column` pairs, such as
+    /// "fingerprint=bridge_fp,published=published_at".
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The list of pairs.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ColumnMapping)` - The mapping.
+    /// * `Err(anyhow::Error)` - A pair is malformed or the mapping is invalid (see [`ColumnMapping::new`]).
+    pub fn parse(spec: &str) -> AnyhowResult<Self> {
+        let pairs = spec
+            .split(',')
+            .filter(|pair| !pair.trim().is_empty())
+            .map(|pair| {
+                pair.split_once('=')
+                    .map(|(field, column)| (field.trim(), column.trim()))
+                    .with_context(|| format!("Expected field=column, got {:?}", pair))
+            })
+            .collect::<AnyhowResult<Vec<_>>>()?;
+        Self::new(pairs)
+    }
+
+    /// Returns the column name storing `field`.
+    pub fn name<'a>(&'a self, field: &'a str) -> &'a str {
+        self.columns.get(field).map_or(field, String::as_str)
+    }
+
+    /// Returns the column storing `field` as it appears in SQL: quoted if mapped, verbatim otherwise.
+    pub(crate) fn column(&self, field: &str) -> String {
+        match self.columns.get(field) {
+            Some(column) => quote_identifier(column).expect("column names are validated on creation"),
+            None => field.to_string(),
+        }
+    }
+
+    /// Returns `true` if no field is mapped to a different column.
+    pub fn is_default(&self) -> bool {
+        self.columns.iter().all(|(field, column)| field == column)
+    }
+}
+
+/// Bridge fingerprints whose assignments are never exported, e.g. to redact certain bridges or to
+/// focus a dataset.
+///
+/// Fingerprints are compared case-insensitively.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FingerprintFilter {
+    excluded: BTreeSet<String>,
+}
+
+impl FingerprintFilter {
+    /// Creates a filter excluding the given fingerprints.
+    ///
+    /// # Arguments
+    ///
+    /// * `fingerprints` - Fingerprints as 40 hex characters, in any case.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(FingerprintFilter)` - The filter.
+    /// * `Err(anyhow::Error)` - A fingerprint is not 40 hex characters.
+    pub fn new<S: AsRef<str>>(fingerprints: impl IntoIterator<Item = S>) -> AnyhowResult<Self> {
+        let mut excluded = BTreeSet::new();
+        for fingerprint in fingerprints {
+            let fingerprint = fingerprint.as_ref().trim();
+            if fingerprint.len() != 40 || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(anyhow::anyhow!(
+                    "Invalid fingerprint {:?}: expected 40 hex characters",
+                    fingerprint
+                ));
+            }
+            excluded.insert(fingerprint.to_ascii_lowercase());
+        }
+        Ok(Self { excluded })
+    }
+
+    /// Parses a list of fingerprints with one fingerprint per line. Blank lines and lines starting
+    /// with `#` are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The list, e.g. the content of an exclude file.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(FingerprintFilter)` - The filter.
+    /// * `Err(anyhow::Error)` - A line holds something other than a fingerprint.
+    pub fn parse_list(content: &str) -> AnyhowResult<Self> {
+        let mut excluded = BTreeSet::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let filter = Self::new([line]).with_context(|| format!("Line {}", index + 1))?;
+            excluded.extend(filter.excluded);
+        }
+        Ok(Self { excluded })
+    }
+
+    /// Returns a filter excluding the fingerprints of both `self` and `other`.
+    pub fn union(mut self, other: Self) -> Self {
+        self.excluded.extend(other.excluded);
+        self
+    }
+
+    /// Returns `true` if assignments of `fingerprint` must not be exported.
+    pub fn is_excluded(&self, fingerprint: &str) -> bool {
+        !self.excluded.is_empty() && self.excluded.contains(&fingerprint.to_ascii_lowercase())
+    }
+
+    /// Returns the number of excluded fingerprints.
+    pub fn len(&self) -> usize {
+        self.excluded.len()
+    }
+
+    /// Returns `true` if no fingerprint is excluded.
+    pub fn is_empty(&self) -> bool {
+        self.excluded.is_empty()
+    }
+}
+
+/// Options controlling how parsed bridge pool assignments are exported.
+///
+/// The default value reproduces the plain export behaviour: tables are created if missing,
+/// existing rows are kept, and only the two core tables are written.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// If `true`, truncates existing tables before inserting new data.
+    pub clear: bool,
+    /// If `true`, maintains the `bridge_pool_assignment_daily` summary table with per-day,
+    /// per-distribution-method assignment counts, updated within the export transaction.
+    pub with_daily_summary: bool,
+    /// Pause inserted between consecutive assignment batches, so that exports against a shared
+    /// database don't saturate it. A zero duration (the default) disables throttling.
+    pub batch_delay: Duration,
+    /// If `true`, stores each file's raw content (see [`ParsedBridgePoolAssignment::raw_content`]) in
+    /// the `raw_content BYTEA` column of `bridge_pool_assignments_file`, so files can be re-parsed
+    /// later without re-fetching. Files fetched from CollecTor are stored as decoded UTF-8, so a file
+    /// with invalid UTF-8 is stored with U+FFFD in place of those bytes, exactly as it was parsed.
+    /// This roughly doubles the storage needed per file and is therefore opt-in.
+    pub store_raw: bool,
+    /// Header stored for every exported file, overriding the header line captured from the file
+    /// itself (see [`ParsedBridgePoolAssignment::header_or_default`]).
+    pub header: Option<String>,
+    /// Parser turning assignment strings into structured fields; [`DefaultAssignmentParser`] unless
+    /// a historical format needs a different one.
+    pub assignment_parser: Arc<dyn AssignmentParser>,
+    /// If `true`, checks after committing that every exported file and assignment digest is present in
+    /// the database, failing the export if any row is missing.
+    pub verify_after_export: bool,
+    /// If `true`, adds a `last_modified` column to `bridge_pool_assignment` holding CollecTor's
+    /// last-modified time of each assignment's file, so ingestion lag can be queried without joining
+    /// the file table. Off by default to keep the assignment table narrow.
+    pub with_assignment_last_modified: bool,
+    /// PostgreSQL schema to export into. It is created if missing and becomes the connection's
+    /// `search_path`, so all tables are created and queried there. `None` keeps the connection's
+    /// default (usually `public`).
+    pub schema: Option<String>,
+    /// How assignment rows are written; see [`InsertMethod`].
+    pub insert_method: InsertMethod,
+    /// Column names of the `bridge_pool_assignment` table, for exporting into an existing table whose
+    /// columns are named differently. The default mapping uses the crate's own names.
+    pub column_mapping: ColumnMapping,
+    /// If `true`, a file whose digest is already stored gets its mutable metadata (header, freshness
+    /// and last-modified time) updated instead of being skipped, so re-runs keep it current. The
+    /// content and thus the digest are unaffected.
+    pub upsert_file_metadata: bool,
+    /// Fingerprints whose assignments are skipped. Their files are still exported. Cannot be combined
+    /// with `store_raw`, since the stored raw content would still hold the excluded lines.
+    pub excluded_fingerprints: FingerprintFilter,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            clear: false,
+            with_daily_summary: false,
+            batch_delay: Duration::ZERO,
+            store_raw: false,
+            header: None,
+            assignment_parser: Arc::new(DefaultAssignmentParser),
+            verify_after_export: false,
+            with_assignment_last_modified: false,
+            schema: None,
+            insert_method: InsertMethod::default(),
+            column_mapping: ColumnMapping::default(),
+            upsert_file_metadata: false,
+            excluded_fingerprints: FingerprintFilter::default(),
+        }
+    }
+}
+
+/// Summary of a completed export.
+///
+/// Counts only rows that were actually written: files or assignments already present (same digest)
+/// are skipped and not counted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ExportSummary {
+    /// Number of file rows written.
+    pub files_exported: usize,
+    /// Number of assignment rows written.
+    pub assignments_exported: usize,
+    /// The written files that had a header but no bridge entries. Such files are valid but may
+    /// indicate an upstream issue.
+    pub empty_files: Vec<EmptyFile>,
+}
+
+/// A written file that had a header but no bridge entries, as listed in
+/// [`ExportSummary::empty_files`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EmptyFile {
+    /// Digest of the file.
+    pub digest: String,
+    /// Publication time from the file's header, in milliseconds since the epoch.
+    pub published_millis: i64,
+}
+
+impl EmptyFile {
+    /// Creates the entry for a parsed file.
+    ///
+    /// # Arguments
+    ///
+    /// * `parsed` - The parsed file, which has no bridge entries.
+    pub fn of(parsed: &ParsedBridgePoolAssignment) -> Self {
+        Self {
+            digest: parsed.file_digest.to_string(),
+            published_millis: parsed.published_millis,
+        }
+    }
+}
+
+/// A destination for parsed bridge pool assignments.
+///
+/// Implemented by each export backend (PostgreSQL, in-memory, ...), so the pipeline can export
+/// without knowing which backend was selected.
+pub trait Exporter: Send {
+    /// Exports the parsed assignments and returns a summary of what was written.
+    fn export(
+        &mut self,
+        parsed_assignments: Vec<ParsedBridgePoolAssignment>,
+    ) -> BoxFuture<'_, AnyhowResult<ExportSummary>>;
+}
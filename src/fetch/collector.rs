@@ -1,13 +1,25 @@
-use super::types::BridgePoolFile;
+use super::dedup::FetchDeduplicator;
+use super::http::{HttpFetcher, HttpStatusError, ReqwestFetcher};
+use super::types::{BridgePoolFile, CursorPosition, DirectoryNotFoundError, FetchFailure, FetchOptions, FetchStats};
+use crate::utils::millis_from_naive_utc;
 use anyhow::{Context, Result as AnyhowResult};
 use chrono::NaiveDateTime;
-use futures::future::join_all;
-use log::{error, info};
+use futures::future::{join_all, FutureExt};
+use log::{error, info, warn};
 use serde_json::Value;
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::Semaphore;
 use tokio::task::JoinHandle;
 
+/// JSON pointers at which the directory tree may live in a CollecTor `index.json`, in lookup order.
+///
+/// Current CollecTor instances put `directories` at the root, but some snapshots wrap the tree in an
+/// extra top-level `index` object.
+const DIRECTORY_ROOT_POINTERS: &[&str] = &["/directories", "/index/directories"];
+
 /// Fetches bridge pool assignment files from a CollecTor instance.
 ///
 /// This function orchestrates the fetching process by retrieving the `index.json`, filtering files
@@ -15,6 +27,9 @@ use tokio::task::JoinHandle;
 /// contents concurrently. The function limits the number of files fetched to MAX_FILES_TO_FETCH (100)
 /// to prevent excessive resource consumption.
 ///
+/// This is a convenience wrapper around [`Collector`] using `reqwest` and the default
+/// [`FetchOptions`].
+///
 /// # Arguments
 ///
 /// * `collec_tor_base_url` - Base URL of the CollecTor instance (e.g., "https://collector.torproject.org").
@@ -28,7 +43,7 @@ use tokio::task::JoinHandle;
 ///
 /// # Examples
 ///
-/// ```rust
+/// ```rust,no_run
 /// use bridge_pool_assignments::fetch::fetch_bridge_pool_files;
 /// use anyhow::Result;
 ///
@@ -48,15 +63,179 @@ pub async fn fetch_bridge_pool_files(
     dirs: &[&str],
     min_last_modified: i64,
 ) -> AnyhowResult<Vec<BridgePoolFile>> {
-    let base_url = normalize_url(collec_tor_base_url);
-    let index = fetch_index(&base_url).await.context("Failed to fetch index.json")?;
-    let remote_files = collect_remote_files(&index, dirs, min_last_modified)
-        .context("Failed to collect remote files")?;
-    let bridge_files = fetch_file_contents(&base_url, remote_files)
+    Collector::new(Arc::new(ReqwestFetcher::new()), &FetchOptions::default())
+        .fetch_bridge_pool_files(collec_tor_base_url, dirs, min_last_modified)
+        .await
+}
+
+/// Fetches bridge pool assignment files through a shared HTTP fetcher and concurrency limits.
+///
+/// Index requests and file content requests each draw from their own semaphore, sized by
+/// [`FetchOptions::index_concurrency`] and [`FetchOptions::file_concurrency`]. A single `Collector`
+/// can be shared by several concurrent fetches (e.g. one per mirror or directory during an archive
+/// backfill), which then all respect the same limits.
+///
+/// Progress across all fetches of a collector is tracked in a [`FetchStats`], available via
+/// [`Collector::stats`] while fetching is still in progress.
+///
+/// Failed file fetches are retried up to [`FetchOptions::max_retries_per_file`] times, drawing from a
+/// retry budget of [`FetchOptions::max_total_retries`] shared by all fetches of the collector.
+pub struct Collector {
+    fetcher: Arc<dyn HttpFetcher>,
+    index_permits: Arc<Semaphore>,
+    file_permits: Arc<Semaphore>,
+    stats: Arc<Mutex<FetchStats>>,
+    retries: Arc<RetryPolicy>,
+    max_index_depth: usize,
+    skip_missing_dirs: bool,
+    dedup: Option<Arc<FetchDeduplicator>>,
+}
+
+/// Retry limits for file fetches, with the global retry budget tracked atomically across tasks.
+struct RetryPolicy {
+    max_per_file: usize,
+    delay: Duration,
+    remaining: AtomicUsize,
+    exhausted: AtomicBool,
+}
+
+impl RetryPolicy {
+    fn new(options: &FetchOptions) -> Self {
+        Self {
+            max_per_file: options.max_retries_per_file,
+            delay: options.retry_delay,
+            remaining: AtomicUsize::new(options.max_total_retries),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    /// Takes one retry from the budget, returning `false` (and marking the budget as exhausted) if none
+    /// is left.
+    fn try_acquire(&self) -> bool {
+        let acquired = self
+            .remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |remaining| remaining.checked_sub(1))
+            .is_ok();
+        if !acquired {
+            self.exhausted.store(true, Ordering::SeqCst);
+        }
+        acquired
+    }
+
+    /// Whether a retry has been refused because the budget ran out.
+    fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::SeqCst)
+    }
+}
+
+impl Collector {
+    /// Creates a collector using `fetcher` for all HTTP requests.
+    ///
+    /// Concurrency limits below 1 are raised to 1 so that fetching can always make progress.
+    pub fn new(fetcher: Arc<dyn HttpFetcher>, options: &FetchOptions) -> Self {
+        Self {
+            fetcher,
+            index_permits: Arc::new(Semaphore::new(options.index_concurrency.max(1))),
+            file_permits: Arc::new(Semaphore::new(options.file_concurrency.max(1))),
+            stats: Arc::new(Mutex::new(FetchStats::default())),
+            retries: Arc::new(RetryPolicy::new(options)),
+            max_index_depth: options.max_index_depth,
+            skip_missing_dirs: options.skip_missing_dirs,
+            dedup: options.dedup_fetches.then(|| Arc::new(FetchDeduplicator::new())),
+        }
+    }
+
+    /// Shares `dedup` with this collector, so its file fetches are coalesced with fetches of the same file
+    /// URL by every other collector using the same deduplicator.
+    pub fn with_deduplicator(mut self, dedup: Arc<FetchDeduplicator>) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    /// Returns a snapshot of the fetch progress so far.
+    pub fn stats(&self) -> FetchStats {
+        lock_stats(&self.stats).clone()
+    }
+
+    /// Fetches bridge pool assignment files from a CollecTor instance.
+    ///
+    /// See [`fetch_bridge_pool_files`] for a description of the arguments and filtering.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<BridgePoolFile>)` - A vector of fetched bridge pool files.
+    /// * `Err(anyhow::Error)` - An error if no directories or a blank one are given (checked before
+    ///   anything is requested), or if fetching or processing fails.
+    pub async fn fetch_bridge_pool_files(
+        &self,
+        collec_tor_base_url: &str,
+        dirs: &[&str],
+        min_last_modified: i64,
+    ) -> AnyhowResult<Vec<BridgePoolFile>> {
+        if dirs.is_empty() {
+            return Err(anyhow::anyhow!("No directories specified to fetch bridge pool assignment files from"));
+        }
+        if dirs.iter().any(|dir| dir.trim().is_empty()) {
+            return Err(anyhow::anyhow!("Empty directory name in directories: {:?}", dirs));
+        }
+        let base_url = normalize_url(collec_tor_base_url);
+        let index = self.fetch_index(&base_url).await.context("Failed to fetch index.json")?;
+        let remote_files = IndexCursor::new(&index, dirs, min_last_modified, self.max_index_depth)
+            .skipping_missing_dirs(self.skip_missing_dirs);
+        let bridge_files = fetch_file_contents(
+            &self.fetcher,
+            &self.file_permits,
+            &self.stats,
+            &self.retries,
+            self.dedup.as_ref(),
+            &base_url,
+            remote_files,
+        )
         .await
         .context("Failed to fetch file contents")?;
-    info!("Completed fetching {} files", bridge_files.len());
-    Ok(bridge_files)
+        if lock_stats(&self.stats).files_listed == 0 && min_last_modified <= 0 {
+            return Err(anyhow::anyhow!(
+                "No bridge pool assignment files found in directories: {:?}",
+                dirs
+            ));
+        }
+        info!("Completed fetching {} files", bridge_files.len());
+        Ok(bridge_files)
+    }
+
+    /// Fetches and parses the `index.json` from a CollecTor instance.
+    ///
+    /// Waits for an index permit first, so concurrent index requests never exceed the configured
+    /// index concurrency.
+    ///
+    /// # Arguments
+    ///
+    /// * `collec_tor_base_url` - Base URL of the CollecTor instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Value)` - The parsed JSON value of the index.
+    /// * `Err(anyhow::Error)` - An error if fetching or parsing fails.
+    pub async fn fetch_index(&self, collec_tor_base_url: &str) -> AnyhowResult<Value> {
+        let _permit = self
+            .index_permits
+            .acquire()
+            .await
+            .context("Failed to acquire index semaphore")?;
+        let index_url = format!("{}index/index.json", normalize_url(collec_tor_base_url));
+        let resp = self
+            .fetcher
+            .get(&index_url)
+            .await
+            .context("Failed to get index.json")?;
+        let index: Value = serde_json::from_slice(&resp.body).context("Failed to parse index.json")?;
+        Ok(index)
+    }
+}
+
+/// Locks the fetch statistics, recovering them if a task panicked while holding the lock.
+fn lock_stats(stats: &Mutex<FetchStats>) -> std::sync::MutexGuard<'_, FetchStats> {
+    stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
 }
 
 /// Normalizes the base URL by ensuring it ends with a trailing slash.
@@ -78,58 +257,137 @@ fn normalize_url(url: &str) -> String {
     }
 }
 
-/// Fetches and parses the `index.json` from a CollecTor instance.
-///
-/// # Arguments
-///
-/// * `base_url` - The normalized base URL of the CollecTor instance.
-///
-/// # Returns
-///
-/// * `Ok(Value)` - The parsed JSON value of the index.
-/// * `Err(anyhow::Error)` - An error if fetching or parsing fails.
-async fn fetch_index(base_url: &str) -> AnyhowResult<Value> {
-    let index_url = format!("{}index/index.json", base_url);
-    let resp = reqwest::get(&index_url)
-        .await
-        .context("Failed to get index.json")?;
-    let index: Value = resp.json().await.context("Failed to parse index.json")?;
-    Ok(index)
-}
-
-/// Collects file paths and timestamps from the index for specified directories.
+/// Lazily walks the files of the requested directories of an index, one directory at a time.
 ///
-/// This function filters files based on the minimum last-modified timestamp and aggregates them
-/// from the provided directories.
+/// Each directory is only looked up in the index once the files of the previous one have been
+/// consumed, so callers can start fetching before the whole tree has been enumerated. Files within a
+/// directory are yielded in the same order as [`collect_files_from_dir`] returns them (newest first).
 ///
-/// # Arguments
+/// The cursor's [`position`](IndexCursor::position) can be saved and passed to
+/// [`resume`](IndexCursor::resume) to continue a traversal later, e.g. on a freshly fetched copy of
+/// the same index.
 ///
-/// * `index` - The parsed JSON index from CollecTor.
-/// * `remote_directories` - List of directories to collect files from.
-/// * `min_last_modified` - Minimum last-modified timestamp in milliseconds.
+/// After the first error the cursor yields nothing more. A directory missing from the index is such an
+/// error, unless the cursor was created with [`skipping_missing_dirs`](IndexCursor::skipping_missing_dirs).
 ///
-/// # Returns
+/// # Examples
 ///
-/// * `Ok(Vec<(String, i64)>)` - A vector of (file path, last modified timestamp) pairs.
-/// * `Err(anyhow::Error)` - An error if no files are found or parsing fails.
-fn collect_remote_files(
-    index: &Value,
-    remote_directories: &[&str],
+/// ```rust
+/// use bridge_pool_assignments::fetch::IndexCursor;
+/// let index = serde_json::json!({"directories": [{"path": "recent", "files": [
+///     {"path": "2022-04-09-00-29-37", "last_modified": "2022-04-09 00:30"}
+/// ]}]});
+/// let mut cursor = IndexCursor::new(&index, &["recent"], 0, 16);
+/// let (path, _) = cursor.next().unwrap().unwrap();
+/// assert_eq!(path, "recent/2022-04-09-00-29-37");
+/// assert!(cursor.next().is_none());
+/// ```
+pub struct IndexCursor<'a> {
+    index: &'a Value,
+    dirs: &'a [&'a str],
     min_last_modified: i64,
-) -> AnyhowResult<Vec<(String, i64)>> {
-    let mut all_files = Vec::new();
-    for dir in remote_directories {
-        let files = collect_files_from_dir(index, dir, min_last_modified)
-            .context(format!("Failed to collect files from directory: {}", dir))?;
-        all_files.extend(files);
+    max_depth: usize,
+    position: CursorPosition,
+    pending: Option<std::vec::IntoIter<(String, i64)>>,
+    failed: bool,
+    skip_missing_dirs: bool,
+    dirs_skipped: usize,
+}
+
+impl<'a> IndexCursor<'a> {
+    /// Creates a cursor at the first file of the first directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The parsed JSON index from CollecTor.
+    /// * `dirs` - List of directories to collect files from.
+    /// * `min_last_modified` - Minimum last-modified timestamp in milliseconds.
+    /// * `max_depth` - Maximum number of path components a requested directory may have.
+    pub fn new(index: &'a Value, dirs: &'a [&'a str], min_last_modified: i64, max_depth: usize) -> Self {
+        Self::resume(index, dirs, min_last_modified, max_depth, CursorPosition::default())
     }
-    if all_files.is_empty() {
-        return Err(anyhow::anyhow!(
-            "No bridge pool assignment files found in directories: {:?}",
-            remote_directories
-        ));
+
+    /// Creates a cursor continuing from `position`, as previously returned by
+    /// [`position`](IndexCursor::position) for the same directories.
+    pub fn resume(
+        index: &'a Value,
+        dirs: &'a [&'a str],
+        min_last_modified: i64,
+        max_depth: usize,
+        position: CursorPosition,
+    ) -> Self {
+        Self {
+            index,
+            dirs,
+            min_last_modified,
+            max_depth,
+            position,
+            pending: None,
+            failed: false,
+            skip_missing_dirs: false,
+            dirs_skipped: 0,
+        }
+    }
+
+    /// If `skip` is `true`, directories missing from the index are skipped with a warning instead of
+    /// ending the traversal with an error. If every requested directory is missing, the cursor still
+    /// yields an error at the end.
+    pub fn skipping_missing_dirs(mut self, skip: bool) -> Self {
+        self.skip_missing_dirs = skip;
+        self
+    }
+
+    /// Returns the position of the next file the cursor will yield.
+    pub fn position(&self) -> CursorPosition {
+        self.position
+    }
+}
+
+impl Iterator for IndexCursor<'_> {
+    type Item = AnyhowResult<(String, i64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.failed {
+                return None;
+            }
+            if let Some(pending) = self.pending.as_mut() {
+                if let Some(file) = pending.next() {
+                    self.position.files_yielded += 1;
+                    return Some(Ok(file));
+                }
+                self.pending = None;
+                self.position.dir_index += 1;
+                self.position.files_yielded = 0;
+            }
+            let Some(dir) = self.dirs.get(self.position.dir_index).copied() else {
+                if self.dirs_skipped > 0 && self.dirs_skipped == self.dirs.len() {
+                    self.failed = true;
+                    return Some(Err(anyhow::anyhow!("None of the directories exist in the index: {:?}", self.dirs)));
+                }
+                return None;
+            };
+            match collect_files_from_dir(self.index, dir, self.min_last_modified, self.max_depth)
+                .context(format!("Failed to collect files from directory: {}", dir))
+            {
+                Ok(files) => {
+                    let mut files = files.into_iter();
+                    files.by_ref().take(self.position.files_yielded).for_each(drop);
+                    self.pending = Some(files);
+                }
+                Err(e) if self.skip_missing_dirs && e.downcast_ref::<DirectoryNotFoundError>().is_some() => {
+                    warn!("Skipping directory {}: {:#}", dir, e);
+                    self.dirs_skipped += 1;
+                    self.position.dir_index += 1;
+                    self.position.files_yielded = 0;
+                }
+                Err(e) => {
+                    self.failed = true;
+                    return Some(Err(e));
+                }
+            }
+        }
     }
-    Ok(all_files)
 }
 
 /// Collects files from a single directory within the index.
@@ -142,22 +400,32 @@ fn collect_remote_files(
 /// * `index` - The parsed JSON index from CollecTor.
 /// * `dir` - The directory path to collect files from.
 /// * `min_last_modified` - Minimum last-modified timestamp in milliseconds.
+/// * `max_depth` - Maximum number of path components `dir` may have.
 ///
 /// # Returns
 ///
 /// * `Ok(Vec<(String, i64)>)` - A vector of (file path, last modified timestamp) pairs.
-/// * `Err(anyhow::Error)` - An error if the directory is not found or parsing fails.
+/// * `Err(anyhow::Error)` - An error if the directory is not found, is nested deeper than
+///   `max_depth`, or parsing fails.
 fn collect_files_from_dir(
     index: &Value,
     dir: &str,
     min_last_modified: i64,
+    max_depth: usize,
 ) -> AnyhowResult<Vec<(String, i64)>> {
     // Limit the number of files to fetch (same as export limit)
     const MAX_FILES_TO_FETCH: usize = 100;
     
     let mut all_files = Vec::new();
     let dir_path: Vec<&str> = dir.trim_matches('/').split('/').collect();
-    let mut current = &index["directories"];
+    if dir_path.len() > max_depth {
+        return Err(anyhow::anyhow!(
+            "Directory {} is nested deeper than the maximum index depth of {}",
+            dir,
+            max_depth
+        ));
+    }
+    let mut current = find_directories_root(index)?;
     let mut full_path = String::new();
 
     info!("Starting traversal for directory: {}", dir);
@@ -181,6 +449,10 @@ fn collect_files_from_dir(
                                 .as_str()
                                 .context("Missing file path")?
                                 .to_string();
+                            if let Err(reason) = validate_file_name(&file_path) {
+                                warn!("Skipping suspicious file entry {:?} in {}: {}", file_path, full_path, reason);
+                                continue;
+                            }
                             let last_modified_str = file["last_modified"]
                                 .as_str()
                                 .context("Missing last modified")?;
@@ -189,7 +461,8 @@ fn collect_files_from_dir(
                                 "%Y-%m-%d %H:%M",
                             ).map_err(|e| anyhow::anyhow!("Invalid timestamp {}: {}", last_modified_str, e))?;
                             
-                            let last_modified_ms = last_modified.and_utc().timestamp_millis();
+                            let last_modified_ms = millis_from_naive_utc(last_modified)
+                                .context(format!("Invalid last modified time of {}", file_path))?;
 
                             if last_modified_ms >= min_last_modified {
                                 sorted_files.push((file_path, last_modified_ms));
@@ -197,7 +470,7 @@ fn collect_files_from_dir(
                         }
                         
                         // Sort by newest first
-                        sorted_files.sort_by(|a, b| b.1.cmp(&a.1));
+                        sorted_files.sort_by_key(|f| std::cmp::Reverse(f.1));
                         
                         // Take only MAX_FILES_TO_FETCH newest files
                         for (file_path, last_modified_ms) in sorted_files.into_iter().take(MAX_FILES_TO_FETCH) {
@@ -209,7 +482,11 @@ fn collect_files_from_dir(
                     current = &next["directories"];
                 }
             } else {
-                return Err(anyhow::anyhow!("Directory not found: {} in {}", part, full_path));
+                return Err(DirectoryNotFoundError {
+                    part: part.to_string(),
+                    parent: full_path,
+                }
+                .into());
             }
         } else {
             return Err(anyhow::anyhow!("Expected array of directories"));
@@ -219,47 +496,237 @@ fn collect_files_from_dir(
     Ok(all_files)
 }
 
+/// Lists every directory in a CollecTor `index.json`, for discovering valid `--dirs` values.
+///
+/// Directories are listed depth-first in index order, each as its full path followed by the number
+/// of subdirectories and files it directly contains.
+///
+/// Since the whole tree is walked, the index is not trusted: nesting deeper than `max_depth`, and
+/// entries that refer back into the tree (a name such as `.` or `..`, or the same path listed twice),
+/// abort the listing instead of recursing without bound.
+///
+/// # Arguments
+///
+/// * `index` - The parsed JSON index from CollecTor.
+/// * `max_depth` - Maximum nesting depth of directories, see [`FetchOptions::max_index_depth`].
+///
+/// # Returns
+///
+/// * `Ok(Vec<String>)` - One line per directory, e.g. "recent/bridge-pool-assignments (0 directories, 12 files)".
+/// * `Err(anyhow::Error)` - An error if the index holds no directory tree, nests directories deeper
+///   than `max_depth`, or contains self-referential entries.
+///
+/// # Examples
+///
+/// ```rust
+/// use bridge_pool_assignments::fetch::list_directories;
+/// let index = serde_json::json!({"directories": [{"path": "recent", "directories": [], "files": []}]});
+/// assert_eq!(list_directories(&index, 16).unwrap(), vec!["recent (0 directories, 0 files)"]);
+/// ```
+pub fn list_directories(index: &Value, max_depth: usize) -> AnyhowResult<Vec<String>> {
+    let mut walk = DirectoryWalk {
+        max_depth,
+        seen: HashSet::new(),
+        lines: Vec::new(),
+    };
+    walk.list_below(find_directories_root(index)?, "", 1)?;
+    Ok(walk.lines)
+}
+
+/// State of a [`list_directories`] walk: its limits, the paths visited so far and the output lines.
+struct DirectoryWalk {
+    max_depth: usize,
+    seen: HashSet<String>,
+    lines: Vec<String>,
+}
+
+impl DirectoryWalk {
+    /// Appends a line for each directory in `directories` and, recursively, their subdirectories.
+    ///
+    /// `depth` is the nesting depth of the entries in `directories`, starting at 1 for the root.
+    fn list_below(&mut self, directories: &Value, parent_path: &str, depth: usize) -> AnyhowResult<()> {
+        for directory in directories.as_array().into_iter().flatten() {
+            let Some(name) = directory["path"].as_str() else {
+                continue;
+            };
+            let path = if parent_path.is_empty() {
+                name.to_string()
+            } else {
+                format!("{}/{}", parent_path, name)
+            };
+            if depth > self.max_depth {
+                return Err(anyhow::anyhow!(
+                    "Index nests directories deeper than the maximum depth of {} at {}",
+                    self.max_depth,
+                    path
+                ));
+            }
+            if let Err(reason) = validate_file_name(name) {
+                return Err(anyhow::anyhow!("Invalid directory entry {:?} in index: {}", path, reason));
+            }
+            if !self.seen.insert(path.clone()) {
+                return Err(anyhow::anyhow!("Directory {} is listed more than once in index", path));
+            }
+            let count = |key: &str| directory[key].as_array().map_or(0, |entries| entries.len());
+            self.lines.push(format!(
+                "{} ({} directories, {} files)",
+                path,
+                count("directories"),
+                count("files")
+            ));
+            self.list_below(&directory["directories"], &path, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a file entry from the index is a plain, safe file name.
+///
+/// The name is appended to the directory path to build both the fetch URL and (for local or cached
+/// sources) a file system path, so it must be a single, non-empty path component: no separators, no
+/// `.` or `..` (path traversal), and no control characters.
+///
+/// # Arguments
+///
+/// * `name` - The `path` value of a file entry in the index.
+///
+/// # Returns
+///
+/// * `Ok(())` - The name is safe to use.
+/// * `Err(&str)` - The reason the name was rejected.
+fn validate_file_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("empty path");
+    }
+    if name == "." || name == ".." || name.split(['/', '\\']).any(|part| part == "..") {
+        return Err("path traversal");
+    }
+    if name.contains(['/', '\\']) {
+        return Err("not a plain file name");
+    }
+    if name.chars().any(char::is_control) {
+        return Err("control characters");
+    }
+    Ok(())
+}
+
+/// Locates the top-level directory tree within the index.
+///
+/// Checks each location in `DIRECTORY_ROOT_POINTERS` in order and returns the first one holding an
+/// array of directories.
+///
+/// # Arguments
+///
+/// * `index` - The parsed JSON index from CollecTor.
+///
+/// # Returns
+///
+/// * `Ok(&Value)` - The array of top-level directories.
+/// * `Err(anyhow::Error)` - An error if none of the known locations holds a directory tree.
+fn find_directories_root(index: &Value) -> AnyhowResult<&Value> {
+    DIRECTORY_ROOT_POINTERS
+        .iter()
+        .filter_map(|pointer| index.pointer(pointer))
+        .find(|directories| directories.is_array())
+        .with_context(|| {
+            format!(
+                "No directory tree found in index.json (looked for: {})",
+                DIRECTORY_ROOT_POINTERS.join(", ")
+            )
+        })
+}
+
 /// Fetches the contents of multiple files concurrently.
 ///
 /// This function uses tokio's async runtime and a semaphore to limit concurrent requests,
-/// preventing server overload.
+/// preventing server overload. Failed fetches are retried while the shared retry budget lasts; once
+/// it is exhausted, files that have not been fetched yet are skipped and the whole fetch fails.
 ///
 /// # Arguments
 ///
+/// * `fetcher` - The HTTP fetcher used for each request.
+/// * `semaphore` - Semaphore limiting the number of concurrent file requests.
+/// * `stats` - Fetch statistics, updated as each file completes.
+/// * `retries` - Retry limits and the shared retry budget.
+/// * `dedup` - If set, coalesces fetches of files already being fetched elsewhere.
 /// * `base_url` - The normalized base URL of the CollecTor instance.
-/// * `remote_files` - A vector of (file path, last modified timestamp) pairs.
+/// * `remote_files` - (file path, last modified timestamp) pairs, fetched as they are yielded.
 ///
 /// # Returns
 ///
 /// * `Ok(Vec<BridgePoolFile>)` - A vector of fetched file contents.
-/// * `Err(anyhow::Error)` - An error if fetching fails for any file.
+/// * `Err(anyhow::Error)` - An error if listing the files failed or the retry budget was exhausted.
 async fn fetch_file_contents(
+    fetcher: &Arc<dyn HttpFetcher>,
+    semaphore: &Arc<Semaphore>,
+    stats: &Arc<Mutex<FetchStats>>,
+    retries: &Arc<RetryPolicy>,
+    dedup: Option<&Arc<FetchDeduplicator>>,
     base_url: &str,
-    remote_files: Vec<(String, i64)>,
+    remote_files: impl IntoIterator<Item = AnyhowResult<(String, i64)>>,
 ) -> AnyhowResult<Vec<BridgePoolFile>> {
-    // Limit to 50 concurrent requests to avoid overwhelming the server
-    let semaphore = Arc::new(Semaphore::new(50));
-    
-    // Create a task for each file to fetch
-    let fetch_tasks: Vec<JoinHandle<AnyhowResult<BridgePoolFile>>> = remote_files
-        .into_iter()
-        .map(|(path, _)| {
+    // Start a task for each file as soon as it is listed
+    let mut fetch_tasks: Vec<JoinHandle<AnyhowResult<BridgePoolFile>>> = Vec::new();
+    for remote_file in remote_files {
+        let (path, last_modified) = match remote_file {
+            Ok(remote_file) => remote_file,
+            Err(e) => {
+                fetch_tasks.iter().for_each(JoinHandle::abort);
+                return Err(e.context("Failed to collect remote files"));
+            }
+        };
+        lock_stats(stats).files_listed += 1;
+        fetch_tasks.push({
+            let fetcher = Arc::clone(fetcher);
             let base_url = base_url.to_string();
             let path = path.to_string();
-            let semaphore = Arc::clone(&semaphore);
+            let semaphore = Arc::clone(semaphore);
+            let stats = Arc::clone(stats);
+            let retries = Arc::clone(retries);
+            let dedup = dedup.cloned();
             
             let permit = semaphore.acquire_owned();
             tokio::spawn(async move {
                 let _permit = permit.await.context("Failed to acquire semaphore")?;
-                let content = fetch_file_content(&base_url, &path)
-                    .await
-                    .context(format!("Failed to fetch content for {}", path))?;
+                let result = match dedup {
+                    Some(dedup) => {
+                        let file_url = format!("{}{}", base_url, path);
+                        let fetch = {
+                            let (stats, path) = (Arc::clone(&stats), path.clone());
+                            move || {
+                                async move {
+                                    fetch_file_content_with_retries(fetcher.as_ref(), &retries, &stats, &base_url, &path)
+                                        .await
+                                }
+                                .boxed()
+                            }
+                        };
+                        dedup.fetch(&file_url, last_modified, fetch).await
+                    }
+                    None => fetch_file_content_with_retries(fetcher.as_ref(), &retries, &stats, &base_url, &path).await,
+                };
+                let mut stats = lock_stats(&stats);
+                match &result {
+                    Ok(_) => stats.files_fetched += 1,
+                    Err(e) => {
+                        stats.files_failed += 1;
+                        if let Some(status_error) = e.downcast_ref::<HttpStatusError>() {
+                            *stats.failed_statuses.entry(status_error.status).or_default() += 1;
+                        }
+                        stats.failed_files.push(FetchFailure {
+                            path: path.clone(),
+                            error: format!("{:#}", e),
+                        });
+                    }
+                }
+                drop(stats);
+                let content = result.context(format!("Failed to fetch content for {}", path))?;
                 info!("Fetched content for {}", path);
                 
                 Ok(content)
             })
-        })
-        .collect();
+        });
+    }
 
     let results = join_all(fetch_tasks).await;
     let mut bridge_files = Vec::new();
@@ -284,9 +751,64 @@ async fn fetch_file_contents(
         bridge_files.len(),
         errors
     );
+    if retries.is_exhausted() {
+        return Err(anyhow::anyhow!(
+            "Retry budget exhausted; {} of {} files could not be fetched",
+            errors,
+            errors + bridge_files.len()
+        ));
+    }
     Ok(bridge_files)
 }
 
+/// Fetches a single file, retrying failures while both the per-file limit and the shared retry budget
+/// allow it.
+///
+/// Responses with a final status (see [`HttpStatusError::is_retryable`]), such as `404 Not Found`, are
+/// not retried: the file is gone, and asking again would only spend the budget.
+///
+/// # Arguments
+///
+/// * `fetcher` - The HTTP fetcher used for the request.
+/// * `retries` - Retry limits and the shared retry budget.
+/// * `stats` - Fetch statistics, updated with each retry taken.
+/// * `base_url` - The normalized base URL of the CollecTor instance.
+/// * `file_path` - The relative path of the file to fetch.
+///
+/// # Returns
+///
+/// * `Ok(BridgePoolFile)` - The fetched file.
+/// * `Err(anyhow::Error)` - The last error, once no more retries are allowed, or an error if the
+///   budget was already exhausted before this file was attempted.
+async fn fetch_file_content_with_retries(
+    fetcher: &dyn HttpFetcher,
+    retries: &RetryPolicy,
+    stats: &Mutex<FetchStats>,
+    base_url: &str,
+    file_path: &str,
+) -> AnyhowResult<BridgePoolFile> {
+    if retries.is_exhausted() {
+        return Err(anyhow::anyhow!("Retry budget exhausted; not fetching {}", file_path));
+    }
+    let mut attempt = 0;
+    loop {
+        let err = match fetch_file_content(fetcher, base_url, file_path).await {
+            Ok(file) => return Ok(file),
+            Err(e) => e,
+        };
+        let retryable = err
+            .downcast_ref::<HttpStatusError>()
+            .is_none_or(HttpStatusError::is_retryable);
+        if !retryable || attempt >= retries.max_per_file || !retries.try_acquire() {
+            return Err(err);
+        }
+        attempt += 1;
+        lock_stats(stats).retries += 1;
+        warn!("Retrying {} (attempt {}) after error: {:#}", file_path, attempt + 1, err);
+        tokio::time::sleep(retries.delay).await;
+    }
+}
+
 /// Fetches the content of a single file from CollecTor.
 ///
 /// Retrieves both the text content and raw bytes of the file for both parsing and
@@ -294,37 +816,45 @@ async fn fetch_file_contents(
 ///
 /// # Arguments
 ///
+/// * `fetcher` - The HTTP fetcher used for the request.
 /// * `base_url` - The normalized base URL of the CollecTor instance.
 /// * `file_path` - The relative path of the file to fetch.
 ///
 /// # Returns
 ///
 /// * `Ok(BridgePoolFile)` - The fetched file with content, raw bytes, and metadata.
-/// * `Err(anyhow::Error)` - An error if fetching or reading the file fails.
-async fn fetch_file_content(base_url: &str, file_path: &str) -> AnyhowResult<BridgePoolFile> {
+/// * `Err(anyhow::Error)` - An error if fetching or reading the file fails; an [`HttpStatusError`] for
+///   a non-2xx response.
+async fn fetch_file_content(
+    fetcher: &dyn HttpFetcher,
+    base_url: &str,
+    file_path: &str,
+) -> AnyhowResult<BridgePoolFile> {
     let file_url = format!("{}{}", base_url, file_path);
-    let resp = reqwest::get(&file_url)
+    let resp = fetcher
+        .get(&file_url)
         .await
         .context("Failed to get file")?;
-        
-    // Extract last_modified from headers
-    let last_modified = if let Some(last_mod_header) = resp.headers().get("Last-Modified") {
-        if let Ok(last_mod_str) = last_mod_header.to_str() {
-            // Parse date header to timestamp
-            chrono::DateTime::parse_from_rfc2822(last_mod_str)
-                .map(|dt| dt.timestamp_millis())
-                .unwrap_or(0)
-        } else {
-            0
+    if !(200..300).contains(&resp.status) {
+        return Err(HttpStatusError {
+            status: resp.status,
+            url: file_url,
         }
-    } else {
-        0
+        .into());
+    }
+        
+    // Extract last_modified from headers; a missing header leaves it unknown (0)
+    let last_modified = match resp.last_modified.as_deref() {
+        Some(last_mod_str) => parse_last_modified_header(last_mod_str)
+            .context(format!("Invalid Last-Modified header for {}", file_url))?,
+        None => 0,
     };
     
-    // Get the text content first (this consumes the response)
-    let text = resp.text().await.context("Failed to get response text")?;
+    // Decode the body as text, replacing invalid UTF-8 like `reqwest`'s `text()` does
+    let text = String::from_utf8_lossy(&resp.body).into_owned();
     
-    // Use the text content to also create raw_content
+    // Use the text content to also create raw_content, so digests and stored raw content match the
+    // decoded text that is parsed; invalid UTF-8 bytes are not preserved
     let raw_content = text.as_bytes().to_vec();
     
     Ok(BridgePoolFile {
@@ -335,9 +865,145 @@ async fn fetch_file_content(base_url: &str, file_path: &str) -> AnyhowResult<Bri
     })
 }
 
+/// Parses an RFC 2822 `Last-Modified` header value into milliseconds since the epoch.
+///
+/// # Arguments
+///
+/// * `value` - The header value (e.g., "Sat, 09 Apr 2022 00:30:00 GMT").
+///
+/// # Returns
+///
+/// * `Ok(i64)` - The timestamp in milliseconds since the epoch.
+/// * `Err(anyhow::Error)` - An error if the value is malformed or out of range.
+fn parse_last_modified_header(value: &str) -> AnyhowResult<i64> {
+    let datetime = chrono::DateTime::parse_from_rfc2822(value)
+        .map_err(|e| anyhow::anyhow!("Malformed timestamp {:?}: {}", value, e))?;
+    millis_from_naive_utc(datetime.naive_utc())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fetch::{Freshness, HttpResponse};
+    use futures::future::BoxFuture;
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// An `HttpFetcher` that answers every request with an empty index after a short delay, while
+    /// recording the highest number of requests it saw in flight at once.
+    #[derive(Default)]
+    struct ConcurrencyTrackingFetcher {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+    }
+
+    impl HttpFetcher for ConcurrencyTrackingFetcher {
+        fn get<'a>(&'a self, _url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+            Box::pin(async move {
+                let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok(HttpResponse {
+                    status: 200,
+                    last_modified: None,
+                    body: br#"{"directories": []}"#.to_vec(),
+                })
+            })
+        }
+    }
+
+    /// An `HttpFetcher` for a mirror whose index lists `files` files but which fails every file request,
+    /// counting the file requests it receives.
+    struct FailingMirrorFetcher {
+        files: usize,
+        file_requests: AtomicUsize,
+    }
+
+    impl HttpFetcher for FailingMirrorFetcher {
+        fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+            Box::pin(async move {
+                if url.ends_with("index/index.json") {
+                    let files: Vec<Value> = (0..self.files)
+                        .map(|i| serde_json::json!({"path": format!("file-{}", i), "last_modified": "2022-04-09 00:30"}))
+                        .collect();
+                    let index = serde_json::json!({"directories": [{"path": "recent", "directories": [
+                        {"path": "bridge-pool-assignments", "files": files}
+                    ]}]});
+                    return Ok(HttpResponse {
+                        status: 200,
+                        last_modified: None,
+                        body: index.to_string().into_bytes(),
+                    });
+                }
+                self.file_requests.fetch_add(1, Ordering::SeqCst);
+                Ok(HttpResponse {
+                    status: 503,
+                    last_modified: None,
+                    body: Vec::new(),
+                })
+            })
+        }
+    }
+
+    /// Serves an index of files named after the HTTP status each one responds with.
+    struct StatusFetcher {
+        statuses: Vec<u16>,
+        requests: Mutex<BTreeMap<u16, usize>>,
+    }
+
+    impl HttpFetcher for StatusFetcher {
+        fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+            Box::pin(async move {
+                if url.ends_with("index/index.json") {
+                    let files: Vec<Value> = self
+                        .statuses
+                        .iter()
+                        .map(|status| serde_json::json!({"path": status.to_string(), "last_modified": "2022-04-09 00:30"}))
+                        .collect();
+                    let index = serde_json::json!({"directories": [{"path": "recent", "files": files}]});
+                    return Ok(HttpResponse {
+                        status: 200,
+                        last_modified: None,
+                        body: index.to_string().into_bytes(),
+                    });
+                }
+                let status: u16 = url.rsplit('/').next().unwrap().parse().unwrap();
+                *self.requests.lock().unwrap().entry(status).or_default() += 1;
+                Ok(HttpResponse {
+                    status,
+                    last_modified: None,
+                    body: b"bridge-pool-assignment 2022-04-09 00:29:37\n".to_vec(),
+                })
+            })
+        }
+    }
+
+    /// Serves an index listing one file, `recent/file`, whose content is its own URL and which takes a
+    /// moment to arrive, so fetches from different mirrors overlap.
+    struct MirrorFetcher;
+
+    impl HttpFetcher for MirrorFetcher {
+        fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+            Box::pin(async move {
+                let body = if url.ends_with("index/index.json") {
+                    serde_json::json!({"directories": [{"path": "recent", "files": [
+                        {"path": "file", "last_modified": "2022-04-09 00:30"}
+                    ]}]})
+                    .to_string()
+                } else {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    url.to_string()
+                };
+                Ok(HttpResponse {
+                    status: 200,
+                    last_modified: None,
+                    body: body.into_bytes(),
+                })
+            })
+        }
+    }
 
     /// Tests the `normalize_url` function to ensure it correctly adds a trailing slash.
     #[test]
@@ -351,4 +1017,360 @@ mod tests {
             "https://example.com/"
         );
     }
+
+    /// Tests that traversal still works when the directory tree is wrapped in an extra root object.
+    #[test]
+    fn test_collect_files_from_wrapped_index() {
+        let index = serde_json::json!({
+            "index": {
+                "build_revision": "abc123",
+                "path": "https://collector.torproject.org",
+                "directories": [{
+                    "path": "recent",
+                    "directories": [{
+                        "path": "bridge-pool-assignments",
+                        "files": [
+                            {"path": "2022-04-09-00-29-37", "last_modified": "2022-04-09 00:30"}
+                        ]
+                    }]
+                }]
+            }
+        });
+
+        let files = collect_files_from_dir(&index, "recent/bridge-pool-assignments", 0, 16).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, "recent/bridge-pool-assignments/2022-04-09-00-29-37");
+    }
+
+    /// Tests that an index without any directory tree produces a clear error.
+    #[test]
+    fn test_collect_files_without_directory_tree() {
+        let index = serde_json::json!({ "build_revision": "abc123" });
+
+        let err = collect_files_from_dir(&index, "recent/bridge-pool-assignments", 0, 16).unwrap_err();
+
+        assert!(err.to_string().contains("No directory tree found in index.json"));
+    }
+
+    /// Tests deriving the freshness category from CollecTor paths.
+    #[test]
+    fn test_freshness_from_path() {
+        assert_eq!(Freshness::from_path("recent/bridge-pool-assignments/2022-04-09-00-29-37"), Freshness::Recent);
+        assert_eq!(Freshness::from_path("/archive/bridge-pool-assignments/x.tar.xz"), Freshness::Archive);
+        assert_eq!(Freshness::from_path("bridge-pool-assignments/2022-04-09-00-29-37"), Freshness::Unknown);
+    }
+
+    /// Tests that file entries with empty or path-traversing names are skipped while valid ones are kept.
+    #[test]
+    fn test_collect_files_rejects_suspicious_paths() {
+        let index = serde_json::json!({"directories": [{"path": "recent", "directories": [{
+            "path": "bridge-pool-assignments",
+            "files": [
+                {"path": "2022-04-09-00-29-37", "last_modified": "2022-04-09 00:30"},
+                {"path": "../../etc/passwd", "last_modified": "2022-04-09 00:30"},
+                {"path": "..", "last_modified": "2022-04-09 00:30"},
+                {"path": "", "last_modified": "2022-04-09 00:30"},
+                {"path": "nested/2022-04-10-00-29-37", "last_modified": "2022-04-10 00:30"}
+            ]
+        }]}]});
+
+        let files = collect_files_from_dir(&index, "recent/bridge-pool-assignments", 0, 16).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].0, "recent/bridge-pool-assignments/2022-04-09-00-29-37");
+        assert_eq!(validate_file_name("../x"), Err("path traversal"));
+        assert_eq!(validate_file_name(""), Err("empty path"));
+    }
+
+    /// Tests listing the directory tree of a sample index.
+    #[test]
+    fn test_list_directories() {
+        let index = serde_json::json!({
+            "path": "https://collector.torproject.org",
+            "directories": [
+                {"path": "archive", "directories": [
+                    {"path": "bridge-pool-assignments", "files": [
+                        {"path": "bridge-pool-assignments-2022-04.tar.xz"},
+                        {"path": "bridge-pool-assignments-2022-05.tar.xz"}
+                    ]}
+                ]},
+                {"path": "recent", "directories": [
+                    {"path": "bridge-pool-assignments", "files": [
+                        {"path": "2022-04-09-00-29-37"}
+                    ]},
+                    {"path": "exit-lists", "files": []}
+                ]}
+            ]
+        });
+
+        assert_eq!(
+            list_directories(&index, 16).unwrap(),
+            vec![
+                "archive (1 directories, 0 files)",
+                "archive/bridge-pool-assignments (0 directories, 2 files)",
+                "recent (2 directories, 0 files)",
+                "recent/bridge-pool-assignments (0 directories, 1 files)",
+                "recent/exit-lists (0 directories, 0 files)",
+            ]
+        );
+    }
+
+    /// Tests that the cursor yields files before later directories are looked up, matches the eager
+    /// per-directory traversal, and can be resumed from a saved position.
+    #[test]
+    fn test_index_cursor_yields_incrementally() {
+        let directories: Vec<Value> = (0..50)
+            .map(|dir| {
+                let files: Vec<Value> = (0..20)
+                    .map(|file| serde_json::json!({
+                        "path": format!("2022-04-{:02}-00-{:02}-00", file + 1, dir),
+                        "last_modified": format!("2022-04-{:02} 00:{:02}", file + 1, dir),
+                    }))
+                    .collect();
+                serde_json::json!({"path": format!("d{}", dir), "files": files})
+            })
+            .collect();
+        let index = serde_json::json!({ "directories": directories });
+        let names: Vec<String> = (0..50).map(|dir| format!("d{}", dir)).collect();
+        let mut dirs: Vec<&str> = names.iter().map(String::as_str).collect();
+
+        let eager: Vec<(String, i64)> = dirs
+            .iter()
+            .flat_map(|dir| collect_files_from_dir(&index, dir, 0, 16).unwrap())
+            .collect();
+        let lazy: Vec<(String, i64)> = IndexCursor::new(&index, &dirs, 0, 16).map(Result::unwrap).collect();
+        assert_eq!(eager.len(), 1000);
+        assert_eq!(lazy, eager);
+
+        let mut cursor = IndexCursor::new(&index, &dirs, 0, 16);
+        let first: Vec<(String, i64)> = cursor.by_ref().take(30).map(Result::unwrap).collect();
+        assert_eq!(cursor.position(), CursorPosition { dir_index: 1, files_yielded: 10 });
+        let rest: Vec<(String, i64)> = IndexCursor::resume(&index, &dirs, 0, 16, cursor.position())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(first, eager[..30]);
+        assert_eq!(rest, eager[30..]);
+
+        // A missing directory at the end only fails once the cursor reaches it.
+        dirs.push("missing");
+        let mut cursor = IndexCursor::new(&index, &dirs, 0, 16);
+        assert!(cursor.by_ref().take(1000).all(|file| file.is_ok()));
+        assert!(cursor.next().unwrap().is_err());
+        assert!(cursor.next().is_none());
+    }
+
+    /// Tests that a missing directory fails the traversal by default, is skipped with
+    /// `skipping_missing_dirs`, and still fails it if no requested directory exists.
+    #[test]
+    fn test_index_cursor_skip_missing_dirs() {
+        let index = serde_json::json!({"directories": [{"path": "recent", "files": [
+            {"path": "2022-04-09-00-29-37", "last_modified": "2022-04-09 00:30"}
+        ]}]});
+        let dirs = ["recnet", "recent"];
+
+        let results: Vec<AnyhowResult<(String, i64)>> = IndexCursor::new(&index, &dirs, 0, 16).collect();
+        assert_eq!(results.len(), 1);
+        let err = results[0].as_ref().unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<DirectoryNotFoundError>(),
+            Some(&DirectoryNotFoundError { part: "recnet".to_string(), parent: String::new() })
+        );
+
+        let files: Vec<String> = IndexCursor::new(&index, &dirs, 0, 16)
+            .skipping_missing_dirs(true)
+            .map(|file| file.unwrap().0)
+            .collect();
+        assert_eq!(files, ["recent/2022-04-09-00-29-37"]);
+
+        let results: Vec<AnyhowResult<(String, i64)>> = IndexCursor::new(&index, &["recnet", "archive"], 0, 16)
+            .skipping_missing_dirs(true)
+            .collect();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].as_ref().unwrap_err().to_string().contains("None of the directories exist"));
+    }
+
+    /// Tests that a deeply nested index is rejected cleanly once it exceeds the depth limit.
+    #[test]
+    fn test_index_depth_limit() {
+        let mut tree = serde_json::json!([]);
+        for level in (0..20).rev() {
+            tree = serde_json::json!([{"path": format!("d{}", level), "directories": tree}]);
+        }
+        let index = serde_json::json!({ "directories": tree });
+
+        let err = list_directories(&index, 16).unwrap_err();
+        assert!(err.to_string().contains("deeper than the maximum depth of 16 at d0/d1/"), "{}", err);
+        assert_eq!(list_directories(&index, 20).unwrap().len(), 20);
+
+        let deep_dir: Vec<String> = (0..20).map(|level| format!("d{}", level)).collect();
+        let err = collect_files_from_dir(&index, &deep_dir.join("/"), 0, 16).unwrap_err();
+        assert!(err.to_string().contains("nested deeper than the maximum index depth of 16"), "{}", err);
+    }
+
+    /// Tests that self-referential or repeated directory entries abort the listing.
+    #[test]
+    fn test_list_directories_rejects_cycles() {
+        let self_reference = serde_json::json!({"directories": [{"path": "recent", "directories": [
+            {"path": "..", "directories": []}
+        ]}]});
+        let err = list_directories(&self_reference, 16).unwrap_err();
+        assert!(err.to_string().contains("path traversal"), "{}", err);
+
+        let repeated = serde_json::json!({"directories": [
+            {"path": "recent", "directories": []},
+            {"path": "recent", "directories": []}
+        ]});
+        let err = list_directories(&repeated, 16).unwrap_err();
+        assert!(err.to_string().contains("listed more than once"), "{}", err);
+    }
+
+    /// Tests that index entries with out-of-range modification times are rejected with a clear error.
+    #[test]
+    fn test_collect_files_extreme_last_modified() {
+        let index = serde_json::json!({"directories": [{"path": "recent", "directories": [{
+            "path": "bridge-pool-assignments",
+            "files": [{"path": "far-future", "last_modified": "+20000-01-01 00:00"}]
+        }]}]});
+
+        let err = collect_files_from_dir(&index, "recent/bridge-pool-assignments", 0, 16).unwrap_err();
+
+        assert!(format!("{:#}", err).contains("Invalid last modified time of far-future"), "{:#}", err);
+    }
+
+    /// Tests parsing `Last-Modified` headers, including malformed and out-of-range values.
+    #[test]
+    fn test_parse_last_modified_header() {
+        assert_eq!(
+            parse_last_modified_header("Sat, 09 Apr 2022 00:30:00 GMT").unwrap(),
+            1649464200000
+        );
+        assert!(parse_last_modified_header("yesterday")
+            .unwrap_err()
+            .to_string()
+            .contains("Malformed timestamp"));
+        let err = parse_last_modified_header("Mon, 01 Jan 0001 00:00:00 +0100").unwrap_err();
+        assert!(format!("{:#}", err).contains("outside the supported range"), "{:#}", err);
+    }
+
+    /// Tests that concurrent index fetches through one collector respect the index concurrency limit.
+    #[tokio::test]
+    async fn test_index_fetches_respect_index_concurrency() {
+        let fetcher = Arc::new(ConcurrencyTrackingFetcher::default());
+        let options = FetchOptions {
+            index_concurrency: 2,
+            ..Default::default()
+        };
+        let collector = Collector::new(fetcher.clone(), &options);
+
+        let mirrors: Vec<String> = (0..8).map(|i| format!("https://mirror{}.example", i)).collect();
+        let results = join_all(mirrors.iter().map(|mirror| collector.fetch_index(mirror))).await;
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(fetcher.max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    /// Tests that an empty directory list or a blank entry is rejected before the index is requested.
+    #[tokio::test]
+    async fn test_empty_dirs_rejected() {
+        let fetcher = Arc::new(ConcurrencyTrackingFetcher::default());
+        let collector = Collector::new(fetcher.clone(), &FetchOptions::default());
+
+        let err = collector
+            .fetch_bridge_pool_files("https://collector.example", &[], 0)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "No directories specified to fetch bridge pool assignment files from");
+
+        let err = collector
+            .fetch_bridge_pool_files("https://collector.example", &["recent/bridge-pool-assignments", " "], 0)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Empty directory name"), "{:#}", err);
+
+        assert_eq!(fetcher.max_in_flight.load(Ordering::SeqCst), 0);
+    }
+
+    /// Tests that a failing mirror stops being retried once the shared retry budget is used up, and
+    /// that the remaining files are abandoned with an error.
+    #[tokio::test]
+    async fn test_retry_budget_exhausted_by_failing_mirror() {
+        let fetcher = Arc::new(FailingMirrorFetcher {
+            files: 20,
+            file_requests: AtomicUsize::new(0),
+        });
+        let options = FetchOptions {
+            file_concurrency: 1,
+            max_retries_per_file: 3,
+            max_total_retries: 5,
+            retry_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let collector = Collector::new(fetcher.clone(), &options);
+
+        let err = collector
+            .fetch_bridge_pool_files("https://mirror.example", &["recent/bridge-pool-assignments"], 0)
+            .await
+            .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("Retry budget exhausted"));
+        // The first file uses three retries, the second the remaining two; nothing else is attempted.
+        assert_eq!(fetcher.file_requests.load(Ordering::SeqCst), 2 + 5);
+        let stats = collector.stats();
+        assert_eq!(stats.retries, 5);
+        assert_eq!(stats.files_failed, 20);
+    }
+
+    /// Tests that collectors for different mirrors sharing one deduplicator each get their own mirror's
+    /// copy of a file with the same path and timestamp, even while both fetches are in flight.
+    #[tokio::test]
+    async fn test_shared_deduplicator_keeps_mirrors_apart() {
+        let fetcher = Arc::new(MirrorFetcher);
+        let dedup = Arc::new(FetchDeduplicator::new());
+        let first = Collector::new(fetcher.clone(), &FetchOptions::default()).with_deduplicator(Arc::clone(&dedup));
+        let second = Collector::new(fetcher, &FetchOptions::default()).with_deduplicator(Arc::clone(&dedup));
+
+        let (first_files, second_files) = tokio::join!(
+            first.fetch_bridge_pool_files("https://first.example", &["recent"], 0),
+            second.fetch_bridge_pool_files("https://second.example", &["recent"], 0),
+        );
+
+        assert_eq!(first_files.unwrap()[0].content, "https://first.example/recent/file");
+        assert_eq!(second_files.unwrap()[0].content, "https://second.example/recent/file");
+        assert_eq!(dedup.in_flight(), 0);
+    }
+
+    /// Tests that a 404 fails its file at once while a 500 is retried, with both statuses reported.
+    #[tokio::test]
+    async fn test_http_status_outcomes() {
+        let fetcher = Arc::new(StatusFetcher {
+            statuses: vec![200, 404, 500],
+            requests: Mutex::new(BTreeMap::new()),
+        });
+        let options = FetchOptions {
+            max_retries_per_file: 2,
+            retry_delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let collector = Collector::new(fetcher.clone(), &options);
+
+        let files = collector
+            .fetch_bridge_pool_files("https://mirror.example", &["recent"], 0)
+            .await
+            .unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path, "recent/200");
+        assert_eq!(*fetcher.requests.lock().unwrap(), BTreeMap::from([(200, 1), (404, 1), (500, 3)]));
+        let stats = collector.stats();
+        assert_eq!(stats.files_failed, 2);
+        assert_eq!(stats.retries, 2);
+        assert_eq!(stats.failed_statuses, BTreeMap::from([(404, 1), (500, 1)]));
+        assert_eq!(stats.failed_files.len(), 2);
+
+        let not_found = HttpStatusError { status: 404, url: String::new() };
+        assert!(!not_found.is_retryable());
+        assert!(HttpStatusError { status: 503, ..not_found.clone() }.is_retryable());
+        assert!(HttpStatusError { status: 429, ..not_found }.is_retryable());
+    }
 } 
\ No newline at end of file
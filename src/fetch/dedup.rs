@@ -0,0 +1,158 @@
+use super::http::HttpStatusError;
+use super::types::BridgePoolFile;
+use anyhow::Result as AnyhowResult;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A file fetch that several callers may await at once.
+type SharedFetch = Shared<BoxFuture<'static, Result<BridgePoolFile, Arc<anyhow::Error>>>>;
+
+/// Coalesces concurrent fetches of the same file into a single request.
+///
+/// Fetches are keyed by the full file URL and the last-modified timestamp listed in the index, so the
+/// same path on different mirrors is fetched separately and a file that changed in between is fetched
+/// anew. Only fetches still in flight are shared; once a fetch completes
+/// its entry is dropped, so the cache never grows beyond the number of concurrent fetches.
+///
+/// A deduplicator can be shared (via `Arc`) between collectors whose runs overlap, e.g. schedules
+/// fetching overlapping directories.
+#[derive(Default)]
+pub struct FetchDeduplicator {
+    in_flight: Mutex<HashMap<(String, i64), SharedFetch>>,
+}
+
+impl FetchDeduplicator {
+    /// Creates an empty deduplicator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fetches a file, joining a fetch of the same file already in flight instead of starting another.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The full URL of the file, including the base URL of the CollecTor instance.
+    /// * `last_modified` - The file's last-modified timestamp from the index, in milliseconds.
+    /// * `fetch` - Starts the actual fetch; only called if no fetch for the key is in flight.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(BridgePoolFile)` - The fetched file, shared by all callers of the same fetch.
+    /// * `Err(anyhow::Error)` - The error of the shared fetch. An [`HttpStatusError`] is passed on as
+    ///   such, so callers can still tell why the file could not be fetched.
+    pub async fn fetch<F>(&self, url: &str, last_modified: i64, fetch: F) -> AnyhowResult<BridgePoolFile>
+    where
+        F: FnOnce() -> BoxFuture<'static, AnyhowResult<BridgePoolFile>>,
+    {
+        let key = (url.to_string(), last_modified);
+        let shared = self
+            .lock()
+            .entry(key.clone())
+            .or_insert_with(|| fetch().map(|result| result.map_err(Arc::new)).boxed().shared())
+            .clone();
+        let result = shared.clone().await;
+
+        // Whoever finishes first drops the entry, unless a newer fetch has replaced it meanwhile
+        let mut in_flight = self.lock();
+        if in_flight.get(&key).is_some_and(|entry| entry.ptr_eq(&shared)) {
+            in_flight.remove(&key);
+        }
+        drop(in_flight);
+
+        result.map_err(|e| match e.downcast_ref::<HttpStatusError>() {
+            Some(status_error) => status_error.clone().into(),
+            None => anyhow::anyhow!("{:#}", e),
+        })
+    }
+
+    /// Returns the number of fetches currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(String, i64), SharedFetch>> {
+        self.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Starts a fetch of `path` that counts its invocations and takes a moment to complete.
+    fn counted_fetch(
+        calls: &Arc<AtomicUsize>,
+        path: &'static str,
+    ) -> impl FnOnce() -> BoxFuture<'static, AnyhowResult<BridgePoolFile>> {
+        let calls = Arc::clone(calls);
+        move || {
+            async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok(BridgePoolFile {
+                    path: path.to_string(),
+                    last_modified: 0,
+                    content: "content".to_string(),
+                    raw_content: b"content".to_vec(),
+                })
+            }
+            .boxed()
+        }
+    }
+
+    /// Tests that concurrent fetches of the same file share a single underlying fetch.
+    #[tokio::test]
+    async fn test_concurrent_fetches_are_coalesced() {
+        let dedup = FetchDeduplicator::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let (first, second) = tokio::join!(
+            dedup.fetch("a", 1, counted_fetch(&calls, "a")),
+            dedup.fetch("a", 1, counted_fetch(&calls, "a")),
+        );
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(first.unwrap().content, "content");
+        assert_eq!(second.unwrap().content, "content");
+        assert_eq!(dedup.in_flight(), 0);
+    }
+
+    /// Tests that different keys and completed fetches are not shared.
+    #[tokio::test]
+    async fn test_distinct_and_sequential_fetches_are_not_coalesced() {
+        let dedup = FetchDeduplicator::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let (a, b) = tokio::join!(
+            dedup.fetch("a", 1, counted_fetch(&calls, "a")),
+            dedup.fetch("a", 2, counted_fetch(&calls, "a")),
+        );
+        a.unwrap();
+        b.unwrap();
+        dedup.fetch("a", 1, counted_fetch(&calls, "a")).await.unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    /// Tests that every waiter gets the shared fetch's HTTP status error.
+    #[tokio::test]
+    async fn test_shared_status_error_is_preserved() {
+        let dedup = FetchDeduplicator::new();
+        let failing = || {
+            async {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Err(HttpStatusError { status: 404, url: "u".to_string() }.into())
+            }
+            .boxed()
+        };
+
+        let (first, second) = tokio::join!(dedup.fetch("a", 1, failing), dedup.fetch("a", 1, failing));
+        for result in [first, second] {
+            let err = result.unwrap_err();
+            assert_eq!(err.downcast_ref::<HttpStatusError>().map(|e| e.status), Some(404));
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use anyhow::{Context, Result as AnyhowResult};
+use futures::future::BoxFuture;
+
+/// A minimal HTTP response as seen by the fetching code.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    /// HTTP status code (e.g., 200).
+    pub status: u16,
+    /// Raw value of the `Last-Modified` header, if present.
+    pub last_modified: Option<String>,
+    /// Response body.
+    pub body: Vec<u8>,
+}
+
+/// A response whose status code is not in the 2xx range.
+///
+/// Returned (wrapped in an `anyhow::Error`) when a file fetch gets a non-success status, so callers
+/// can tell a file that is gone from a mirror that is failing, e.g. with
+/// `err.downcast_ref::<HttpStatusError>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpStatusError {
+    /// HTTP status code of the response (e.g., 404).
+    pub status: u16,
+    /// URL that was requested.
+    pub url: String,
+}
+
+impl HttpStatusError {
+    /// Whether repeating the request may succeed: server errors (5xx), `408 Request Timeout` and
+    /// `429 Too Many Requests`. Other client errors, such as `404 Not Found`, are final.
+    pub fn is_retryable(&self) -> bool {
+        self.status >= 500 || self.status == 408 || self.status == 429
+    }
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unexpected HTTP status {} for {}", self.status, self.url)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// Performs HTTP GET requests on behalf of the collector.
+///
+/// Abstracting the transport keeps the fetch logic independent of `reqwest`, so tests (or callers with
+/// special needs, such as a local mirror) can substitute their own implementation.
+pub trait HttpFetcher: Send + Sync {
+    /// Fetches `url` and returns its response.
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>>;
+}
+
+/// HTTP protocol version used by a [`ReqwestFetcher`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// HTTP/1.1 only: one connection per request in flight, up to the fetch concurrency.
+    Http1,
+    /// HTTP/2 only, without negotiation ("prior knowledge"): all requests to a host are multiplexed
+    /// over one connection. Fails against servers that only speak HTTP/1.1.
+    Http2,
+    /// HTTP/2 if the server offers it during the TLS handshake (ALPN), HTTP/1.1 otherwise, including
+    /// for plain `http://` URLs.
+    #[default]
+    Auto,
+}
+
+/// The default `HttpFetcher`, backed by a shared `reqwest::Client` so connections are pooled.
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestFetcher {
+    client: reqwest::Client,
+    http_version: HttpVersion,
+}
+
+impl ReqwestFetcher {
+    /// Creates a fetcher using a default `reqwest::Client`, which negotiates the HTTP version
+    /// ([`HttpVersion::Auto`]).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a fetcher whose client uses the given HTTP version.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_version` - The HTTP version to use, or [`HttpVersion::Auto`] to negotiate it.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ReqwestFetcher)` - The fetcher.
+    /// * `Err(anyhow::Error)` - An error if the client cannot be built (e.g., no TLS backend).
+    pub fn with_http_version(http_version: HttpVersion) -> AnyhowResult<Self> {
+        let builder = reqwest::Client::builder();
+        let builder = match http_version {
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+            HttpVersion::Auto => builder,
+        };
+        let client = builder.build().context("Failed to build HTTP client")?;
+        Ok(Self { client, http_version })
+    }
+
+    /// Returns the HTTP version the client is configured for.
+    pub fn http_version(&self) -> HttpVersion {
+        self.http_version
+    }
+}
+
+impl HttpFetcher for ReqwestFetcher {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+        Box::pin(async move {
+            let resp = self.client.get(url).send().await.context("Failed to send request")?;
+            let status = resp.status().as_u16();
+            let last_modified = resp
+                .headers()
+                .get("Last-Modified")
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string());
+            let body = resp.bytes().await.context("Failed to read response body")?.to_vec();
+            Ok(HttpResponse {
+                status,
+                last_modified,
+                body,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Fetches from a local server answering a single HTTP/1.1 response, and returns the result along
+    /// with the first bytes the client sent.
+    async fn capture_request(fetcher: &ReqwestFetcher) -> (AnyhowResult<HttpResponse>, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/index/index.json", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0; 1024];
+            let read = stream.read(&mut request).await.unwrap();
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}")
+                .await;
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+        let response = fetcher.get(&url).await;
+        (response, server.await.unwrap())
+    }
+
+    /// Tests that the client speaks the requested HTTP version, and that negotiation falls back to
+    /// HTTP/1.1 against a server that only speaks HTTP/1.1.
+    #[tokio::test]
+    async fn test_reqwest_fetcher_http_version() {
+        let http1 = ReqwestFetcher::with_http_version(HttpVersion::Http1).unwrap();
+        let (response, request) = capture_request(&http1).await;
+        assert_eq!(http1.http_version(), HttpVersion::Http1);
+        assert!(request.starts_with("GET /index/index.json HTTP/1.1\r\n"), "{:?}", request);
+        assert_eq!(response.unwrap().body, b"{}");
+
+        let http2 = ReqwestFetcher::with_http_version(HttpVersion::Http2).unwrap();
+        let (response, request) = capture_request(&http2).await;
+        assert!(request.starts_with("PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n"), "{:?}", request);
+        assert!(response.is_err());
+
+        let auto = ReqwestFetcher::with_http_version(HttpVersion::Auto).unwrap();
+        let (response, request) = capture_request(&auto).await;
+        assert!(request.starts_with("GET /index/index.json HTTP/1.1\r\n"), "{:?}", request);
+        assert_eq!(response.unwrap().status, 200);
+        assert_eq!(ReqwestFetcher::new().http_version(), HttpVersion::Auto);
+    }
+}
@@ -9,15 +9,26 @@
 //! ## Usage
 //!
 //! The primary entry point is `fetch_bridge_pool_files`, which takes a base URL, a list of directories,
-//! and a minimum last-modified timestamp to filter files.
+//! and a minimum last-modified timestamp to filter files. `IndexCursor` walks the files of an
+//! already fetched index lazily, one directory at a time, and can resume from a saved position.
 //!
 //! ## Submodules
 //!
 //! - **collector**: Contains the logic for fetching data from a CollecTor instance.
+//! - **dedup**: Coalesces concurrent fetches of the same file into a single request.
+//! - **http**: Defines the `HttpFetcher` abstraction over HTTP requests and its `reqwest` implementation,
+//!   which can be restricted to HTTP/1.1 or HTTP/2.
 //! - **types**: Defines data structures used in the fetching process.
 
 mod collector;
+mod dedup;
+mod http;
 mod types;
 
-pub use collector::fetch_bridge_pool_files;
-pub use types::BridgePoolFile; 
\ No newline at end of file
+pub use collector::{fetch_bridge_pool_files, list_directories, Collector, IndexCursor};
+pub use dedup::FetchDeduplicator;
+pub use http::{HttpFetcher, HttpResponse, HttpStatusError, HttpVersion, ReqwestFetcher};
+pub use types::{
+    available_parallelism, default_file_concurrency, BridgePoolFile, CursorPosition, DirectoryNotFoundError,
+    FetchFailure, FetchOptions, FetchStats, Freshness,
+}; 
\ No newline at end of file
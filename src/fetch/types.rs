@@ -1,11 +1,15 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fmt::Debug;
+use std::num::NonZeroUsize;
+use std::time::Duration;
 
 /// Represents a fetched bridge pool assignment file's metadata and content.
 ///
 /// This struct encapsulates the path, last-modified timestamp, and content of a bridge pool
 /// assignment file, making it suitable for parsing or database export. It stores both the 
 /// text content as a String and the raw bytes for digest calculation.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BridgePoolFile {
     /// Relative path of the file (e.g., "bridge_pool_assignments/2022-04-09-00-29-37").
     pub path: String,
@@ -13,6 +17,196 @@ pub struct BridgePoolFile {
     pub last_modified: i64,
     /// Raw textual content of the file.
     pub content: String,
-    /// Raw bytes content of the file for SHA-256 digest calculation.
+    /// Bytes of the file for SHA-256 digest calculation. When fetched from CollecTor, these are the
+    /// UTF-8 bytes of `content`, so invalid UTF-8 in the response is already replaced by U+FFFD.
     pub raw_content: Vec<u8>,
-} 
\ No newline at end of file
+}
+
+impl BridgePoolFile {
+    /// Returns the CollecTor freshness category of the file, derived from its path.
+    pub fn freshness(&self) -> Freshness {
+        Freshness::from_path(&self.path)
+    }
+}
+
+/// Which part of CollecTor a file was fetched from.
+///
+/// CollecTor serves the last few days of data below `recent/` and older data below `archive/`;
+/// knowing the origin lets operators apply different retention policies to each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Freshness {
+    /// Fetched from below `recent/`.
+    Recent,
+    /// Fetched from below `archive/`.
+    Archive,
+    /// Fetched from anywhere else, or of unknown origin.
+    #[default]
+    Unknown,
+}
+
+impl Freshness {
+    /// Derives the freshness category from the first component of a CollecTor path
+    /// (e.g., "recent/bridge-pool-assignments/2022-04-09-00-29-37").
+    pub fn from_path(path: &str) -> Self {
+        match path.trim_start_matches('/').split('/').next() {
+            Some("recent") => Freshness::Recent,
+            Some("archive") => Freshness::Archive,
+            _ => Freshness::Unknown,
+        }
+    }
+
+    /// Returns the category name stored in the database ("recent", "archive" or "unknown").
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Freshness::Recent => "recent",
+            Freshness::Archive => "archive",
+            Freshness::Unknown => "unknown",
+        }
+    }
+}
+
+/// Options controlling how files are fetched from CollecTor.
+///
+/// Index (metadata) requests and file content requests are limited by separate concurrency
+/// limits, so that a burst of index requests during multi-directory backfills cannot starve
+/// or overwhelm content fetches, and vice versa.
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    /// Maximum number of file content requests in flight at once.
+    pub file_concurrency: usize,
+    /// Maximum number of `index.json` requests in flight at once.
+    pub index_concurrency: usize,
+    /// Maximum number of retries for a single file after its first attempt fails.
+    pub max_retries_per_file: usize,
+    /// Maximum number of retries across all file fetches of a collector.
+    ///
+    /// Once the budget is used up, failed files are no longer retried and files not yet fetched are
+    /// abandoned, so a mirror that is down fails the run quickly instead of multiplying requests.
+    pub max_total_retries: usize,
+    /// Pause before each retry.
+    pub retry_delay: Duration,
+    /// Maximum directory nesting followed in `index.json`.
+    ///
+    /// Guards against malformed or hostile indexes; CollecTor's own tree is only a few levels deep.
+    pub max_index_depth: usize,
+    /// Whether concurrent fetches of the same file (same path and last-modified time) share a single
+    /// request; see [`FetchDeduplicator`](crate::fetch::FetchDeduplicator).
+    pub dedup_fetches: bool,
+    /// Whether a requested directory missing from `index.json` is skipped with a warning instead of
+    /// failing the fetch. The fetch still fails if none of the requested directories exist.
+    pub skip_missing_dirs: bool,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            file_concurrency: default_file_concurrency(available_parallelism()),
+            index_concurrency: 4,
+            max_retries_per_file: 3,
+            max_total_retries: 50,
+            retry_delay: Duration::from_secs(1),
+            max_index_depth: 16,
+            dedup_fetches: false,
+            skip_missing_dirs: false,
+        }
+    }
+}
+
+/// Upper bound of [`default_file_concurrency`], keeping a large machine from flooding CollecTor.
+const MAX_DEFAULT_FILE_CONCURRENCY: usize = 50;
+
+/// Returns the default number of concurrent file fetches for a machine with `parallelism` CPUs.
+///
+/// Fetching is network-bound, so several requests per CPU are kept in flight: 8 per CPU, capped at 50.
+///
+/// # Arguments
+///
+/// * `parallelism` - The number of CPUs available, e.g. from [`available_parallelism`].
+///
+/// # Returns
+///
+/// The default file concurrency, at least 1.
+pub fn default_file_concurrency(parallelism: usize) -> usize {
+    parallelism.saturating_mul(8).clamp(1, MAX_DEFAULT_FILE_CONCURRENCY)
+}
+
+/// Returns the number of CPUs available to this process, or 1 if it cannot be determined.
+pub fn available_parallelism() -> usize {
+    std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
+
+/// Counters describing the progress of a fetch.
+///
+/// Updated live by the `Collector` as files are listed and fetched, so they can be observed while a
+/// fetch is still running.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FetchStats {
+    /// Number of files selected from the index for fetching.
+    pub files_listed: usize,
+    /// Number of files fetched successfully.
+    pub files_fetched: usize,
+    /// Number of files whose fetch failed.
+    pub files_failed: usize,
+    /// Number of retries spent from the retry budget.
+    pub retries: usize,
+    /// Number of failed files by the HTTP status of their last attempt, for failures caused by a
+    /// non-2xx response.
+    pub failed_statuses: BTreeMap<u16, usize>,
+    /// The files whose fetch failed, with the error of their last attempt.
+    pub failed_files: Vec<FetchFailure>,
+}
+
+/// A file that could not be fetched.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FetchFailure {
+    /// Relative path of the file.
+    pub path: String,
+    /// The error of the last attempt, including its causes.
+    pub error: String,
+}
+
+/// Error returned when a requested directory is missing from `index.json`.
+///
+/// Callers can detect it with `err.downcast_ref::<DirectoryNotFoundError>()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryNotFoundError {
+    /// The missing path component (e.g., "bridge-pool-assignment").
+    pub part: String,
+    /// Path of the directory that was searched for it (empty for the index root).
+    pub parent: String,
+}
+
+impl std::fmt::Display for DirectoryNotFoundError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Directory not found: {} in {}", self.part, self.parent)
+    }
+}
+
+impl std::error::Error for DirectoryNotFoundError {}
+
+/// Position of an [`IndexCursor`](crate::fetch::IndexCursor) within its traversal.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CursorPosition {
+    /// Index of the current directory in the list of requested directories.
+    pub dir_index: usize,
+    /// Number of files of the current directory already yielded.
+    pub files_yielded: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the default file concurrency across machine sizes.
+    #[test]
+    fn test_default_file_concurrency() {
+        assert_eq!(default_file_concurrency(0), 1);
+        assert_eq!(default_file_concurrency(1), 8);
+        assert_eq!(default_file_concurrency(4), 32);
+        assert_eq!(default_file_concurrency(6), 48);
+        assert_eq!(default_file_concurrency(7), 50);
+        assert_eq!(default_file_concurrency(128), 50);
+        assert_eq!(default_file_concurrency(usize::MAX), 50);
+    }
+}
@@ -7,8 +7,12 @@
 //!
 //! - **fetch**: Retrieves bridge pool assignment files from a CollecTor instance.
 //! - **parse**: Extracts structured data from the raw file content.
-//! - **export**: Exports parsed data to a PostgreSQL database.
+//! - **export**: Exports parsed data to a PostgreSQL database (or another `Exporter` backend).
+//! - **analysis**: Computes reports over parsed data, such as assignment lines recurring across files.
+//! - **pipeline**: Runs the fetch, parse, and export stages in sequence.
+//! - **stats**: Serves live statistics of a run over HTTP.
 //! - **utils**: Contains utility functions used across the other modules.
+//! - **testutil**: Generates synthetic documents for tests and benchmarks (`testutil` feature only).
 //!
 //! ## Digest Calculation
 //!
@@ -21,4 +25,9 @@
 pub mod fetch;
 pub mod parse;
 pub mod export;
-pub mod utils;
\ No newline at end of file
+pub mod analysis;
+pub mod pipeline;
+pub mod stats;
+pub mod utils;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
@@ -0,0 +1,249 @@
+use super::types::{AssignmentParser, BridgeAssignment};
+
+/// The built-in [`AssignmentParser`], delegating to [`parse_assignment`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultAssignmentParser;
+
+impl AssignmentParser for DefaultAssignmentParser {
+    fn parse(&self, assignment_str: &str) -> BridgeAssignment {
+        parse_assignment(assignment_str)
+    }
+}
+
+/// Parses an assignment string into its structured fields.
+///
+/// The first token is the distribution method; the remaining whitespace-separated tokens are
+/// `key=value` pairs. Unknown keys and tokens without a `=` are ignored.
+///
+/// # Arguments
+///
+/// * `assignment_str` - The assignment string (e.g., "email transport=obfs4 blocklist=cn,ir").
+///
+/// # Returns
+///
+/// A `BridgeAssignment` holding the extracted fields.
+///
+/// # Examples
+///
+/// ```rust
+/// use bridge_pool_assignments::parse::parse_assignment;
+/// let assignment = parse_assignment("email transport=obfs4 blocklist=cn,ir");
+/// assert_eq!(assignment.distribution_method, "email");
+/// assert_eq!(assignment.transport.as_deref(), Some("obfs4"));
+/// assert_eq!(assignment.blocklist_countries, vec!["cn", "ir"]);
+/// ```
+pub fn parse_assignment(assignment_str: &str) -> BridgeAssignment {
+    // Extract distribution method (first token)
+    let parts: Vec<&str> = assignment_str.splitn(2, ' ').collect();
+    let mut assignment = BridgeAssignment {
+        distribution_method: parts[0].to_string(),
+        ..Default::default()
+    };
+
+    if parts.len() > 1 {
+        // Process key=value pairs
+        for pair in parts[1].split_whitespace() {
+            let kv: Vec<&str> = pair.splitn(2, '=').collect();
+            if kv.len() == 2 {
+                match kv[0] {
+                    "transport" => assignment.transport = Some(kv[1].to_string()),
+                    "ip" => assignment.ip = Some(kv[1].to_string()),
+                    "blocklist" => {
+                        assignment.blocklist = Some(kv[1].to_string());
+                        assignment.blocklist_countries = parse_blocklist(kv[1]);
+                    }
+                    "distributed" => assignment.distributed = Some(kv[1].to_lowercase() == "true"),
+                    "state" => assignment.state = Some(kv[1].to_string()),
+                    "bandwidth" => {
+                        assignment.bandwidth = Some(kv[1].to_string());
+                        assignment.bandwidth_bytes = parse_bandwidth_bytes(kv[1]);
+                    }
+                    "ratio" => assignment.ratio = parse_ratio(kv[1]),
+                    _ => {} // Ignore unknown properties
+                }
+            }
+        }
+    }
+
+    assignment
+}
+
+/// Converts a numeric `bandwidth` value with an optional unit into bytes.
+///
+/// Units are case-insensitive. Byte units with SI prefixes (`KB`, `MB`, `GB`, `TB`) use base 1000 and
+/// binary ones (`KiB`, `MiB`, `GiB`, `TiB`) base 1024; bit units (`bit`, `Kbit`, `Mbit`, `Gbit`) use
+/// base 1000 and are divided by 8. A number without a unit, or with `B`, is taken as bytes. Fractions
+/// are rounded to the nearest byte.
+///
+/// Qualitative values such as "sufficient" have no numeric form and yield `None` silently; a number
+/// with an unrecognized unit also yields `None`, but logs a warning.
+///
+/// # Arguments
+///
+/// * `bandwidth` - The raw value of the `bandwidth` key.
+///
+/// # Returns
+///
+/// The bandwidth in bytes, or `None` if it is not numeric or out of range.
+fn parse_bandwidth_bytes(bandwidth: &str) -> Option<i64> {
+    let unit_start = bandwidth
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(bandwidth.len());
+    let (number, unit) = bandwidth.split_at(unit_start);
+    let value: f64 = number.parse().ok()?;
+    let multiplier = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "kb" => 1e3,
+        "mb" => 1e6,
+        "gb" => 1e9,
+        "tb" => 1e12,
+        "kib" => 1024.0,
+        "mib" => 1024.0 * 1024.0,
+        "gib" => 1024.0 * 1024.0 * 1024.0,
+        "tib" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        "bit" => 1.0 / 8.0,
+        "kbit" => 1e3 / 8.0,
+        "mbit" => 1e6 / 8.0,
+        "gbit" => 1e9 / 8.0,
+        _ => {
+            log::warn!("Unrecognized bandwidth unit {:?} in {:?}", unit, bandwidth);
+            return None;
+        }
+    };
+    let bytes = (value * multiplier).round();
+    // i64::MAX is not exactly representable as f64, so compare against the next power of two.
+    (bytes < 9_223_372_036_854_775_808.0).then_some(bytes as i64)
+}
+
+/// Parses a `ratio` value, tolerating minor format variations.
+///
+/// Surrounding whitespace is trimmed. A trailing `%` marks a percentage, which is divided by 100, so
+/// `50%` and `0.5` are the same ratio; a trailing `f` (as in the float literal `0.5f`) is dropped. Values
+/// that still do not parse as a finite number yield `None` and log a warning.
+///
+/// # Arguments
+///
+/// * `ratio` - The raw value of the `ratio` key.
+///
+/// # Returns
+///
+/// The ratio, or `None` if it is not numeric.
+fn parse_ratio(ratio: &str) -> Option<f32> {
+    let trimmed = ratio.trim();
+    let (number, scale) = match trimmed.strip_suffix('%') {
+        Some(percent) => (percent.trim_end(), 0.01),
+        None => (trimmed.strip_suffix(['f', 'F']).unwrap_or(trimmed), 1.0),
+    };
+    match number.parse::<f32>() {
+        Ok(value) if value.is_finite() => Some(value * scale),
+        _ => {
+            log::warn!("Unparsable ratio {:?}", ratio);
+            None
+        }
+    }
+}
+
+/// Splits a `blocklist` value into its individual country/region codes.
+///
+/// Entries are separated by commas, trimmed, lowercased, and empty entries are dropped, so
+/// `"CN, ir,"` yields `["cn", "ir"]`.
+///
+/// # Arguments
+///
+/// * `blocklist` - The raw value of the `blocklist` key.
+///
+/// # Returns
+///
+/// The blocklist entries in their original order.
+fn parse_blocklist(blocklist: &str) -> Vec<String> {
+    blocklist
+        .split(',')
+        .map(|entry| entry.trim().to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests parsing an assignment carrying several key=value pairs.
+    #[test]
+    fn test_parse_assignment_fields() {
+        let assignment = parse_assignment(
+            "https ip=4 transport=obfs4 distributed=true state=functional bandwidth=high ratio=0.5",
+        );
+
+        assert_eq!(assignment.distribution_method, "https");
+        assert_eq!(assignment.ip.as_deref(), Some("4"));
+        assert_eq!(assignment.transport.as_deref(), Some("obfs4"));
+        assert_eq!(assignment.distributed, Some(true));
+        assert_eq!(assignment.state.as_deref(), Some("functional"));
+        assert_eq!(assignment.bandwidth.as_deref(), Some("high"));
+        assert_eq!(assignment.bandwidth_bytes, None);
+        assert_eq!(assignment.ratio, Some(0.5));
+        assert_eq!(assignment.blocklist, None);
+        assert!(assignment.blocklist_countries.is_empty());
+    }
+
+    /// Tests that a multi-country blocklist is split into its entries while keeping the raw value.
+    #[test]
+    fn test_parse_assignment_multi_country_blocklist() {
+        let assignment = parse_assignment("moat transport=obfs4 blocklist=cn,IR,ru");
+
+        assert_eq!(assignment.blocklist.as_deref(), Some("cn,IR,ru"));
+        assert_eq!(assignment.blocklist_countries, vec!["cn", "ir", "ru"]);
+    }
+
+    /// Tests converting each recognized bandwidth unit into bytes.
+    #[test]
+    fn test_parse_bandwidth_units() {
+        let cases = [
+            ("512", 512),
+            ("512B", 512),
+            ("1024KB", 1_024_000),
+            ("1.5MB", 1_500_000),
+            ("2GB", 2_000_000_000),
+            ("1TB", 1_000_000_000_000),
+            ("1KiB", 1024),
+            ("1MiB", 1_048_576),
+            ("1GiB", 1_073_741_824),
+            ("1TiB", 1_099_511_627_776),
+            ("800bit", 100),
+            ("8Kbit", 1000),
+            ("8mbit", 1_000_000),
+            ("8Gbit", 1_000_000_000),
+        ];
+        for (value, bytes) in cases {
+            assert_eq!(parse_bandwidth_bytes(value), Some(bytes), "{}", value);
+        }
+
+        let assignment = parse_assignment("email bandwidth=1024KB");
+        assert_eq!(assignment.bandwidth.as_deref(), Some("1024KB"));
+        assert_eq!(assignment.bandwidth_bytes, Some(1_024_000));
+    }
+
+    /// Tests that unrecognized units, qualitative values and overflowing numbers have no byte count.
+    #[test]
+    fn test_parse_bandwidth_fallback() {
+        for value in ["1024furlongs", "sufficient", "insufficient", "", ".", "1e30GB", "99999999999TB"] {
+            assert_eq!(parse_bandwidth_bytes(value), None, "{}", value);
+        }
+        let assignment = parse_assignment("email bandwidth=10parsecs");
+        assert_eq!(assignment.bandwidth.as_deref(), Some("10parsecs"));
+        assert_eq!(assignment.bandwidth_bytes, None);
+    }
+
+    /// Tests ratios given as fractions, percentages and with a float suffix, and unparsable ones.
+    #[test]
+    fn test_parse_ratio() {
+        let cases = [("0.5", 0.5), ("50%", 0.5), ("12.5 %", 0.125), (" 0.25 ", 0.25), ("0.5f", 0.5), ("1", 1.0)];
+        for (value, expected) in cases {
+            assert_eq!(parse_ratio(value), Some(expected), "{}", value);
+        }
+        for value in ["xyz", "", "%", "NaN", "inf", "0.5x"] {
+            assert_eq!(parse_ratio(value), None, "{}", value);
+        }
+        assert_eq!(parse_assignment("email ratio=50%").ratio, Some(0.5));
+    }
+}
@@ -1,9 +1,29 @@
-use super::types::ParsedBridgePoolAssignment;
-use crate::fetch::BridgePoolFile;
+use super::types::{ParseFailure, ParsedBridgePoolAssignment};
+use crate::fetch::{BridgePoolFile, Freshness};
+use crate::utils::{compute_assignment_digest, compute_file_digest, millis_from_naive_utc, AssignmentDigestStrategy};
 use anyhow::{Context, Result as AnyhowResult};
 use chrono::NaiveDateTime;
 use std::collections::BTreeMap;
 
+/// Keywords opening the various Tor descriptor types that may end up in a directory by mistake.
+const DESCRIPTOR_KEYWORDS: &[&str] = &[
+    "router",
+    "extra-info",
+    "network-status-version",
+    "dir-key-certificate-version",
+    "onion-key",
+];
+
+/// Header tokens designating UTC, in which bridge pool assignment times are always given.
+const UTC_DESIGNATORS: &[&str] = &["UTC", "Z", "GMT", "UT"];
+
+/// Abbreviations of time zones other than UTC, which a header time must not be read in.
+const NON_UTC_TIME_ZONES: &[&str] = &[
+    "WET", "WEST", "BST", "IST", "CET", "CEST", "EET", "EEST", "MSK", "EST", "EDT", "CST", "CDT", "MST", "MDT",
+    "PST", "PDT", "AKST", "AKDT", "HST", "JST", "KST", "HKT", "SGT", "AWST", "ACST", "ACDT", "AEST", "AEDT",
+    "NZST", "NZDT",
+];
+
 /// Parses bridge pool assignment files into a structured format.
 ///
 /// This function processes each provided `BridgePoolFile`, extracting the publication timestamp and
@@ -36,18 +56,231 @@ use std::collections::BTreeMap;
 /// ```
 pub fn parse_bridge_pool_files(
     bridge_pool_files: Vec<BridgePoolFile>,
+) -> AnyhowResult<Vec<ParsedBridgePoolAssignment>> {
+    parse_files(bridge_pool_files, false, AssignmentDigestStrategy::default())
+}
+
+/// Parses bridge pool assignment files like [`parse_bridge_pool_files`], but keeps each file's raw
+/// bytes in [`ParsedBridgePoolAssignment::raw_content`].
+///
+/// Only needed when the raw bytes are exported as well (e.g. `ExportOptions::store_raw`), since it
+/// keeps every file's content in memory until export.
+///
+/// # Arguments
+///
+/// * `bridge_pool_files` - A vector of `BridgePoolFile` structs containing the file path and content.
+///
+/// # Returns
+///
+/// * `Ok(Vec<ParsedBridgePoolAssignment>)` - A vector of parsed bridge pool assignments.
+/// * `Err(anyhow::Error)` - An error if parsing fails for any file.
+pub fn parse_bridge_pool_files_keeping_raw(
+    bridge_pool_files: Vec<BridgePoolFile>,
+) -> AnyhowResult<Vec<ParsedBridgePoolAssignment>> {
+    parse_files(bridge_pool_files, true, AssignmentDigestStrategy::default())
+}
+
+/// Parses bridge pool assignment files like [`parse_bridge_pool_files`], computing assignment digests
+/// with `strategy` instead of the default [`AssignmentDigestStrategy`].
+///
+/// # Arguments
+///
+/// * `bridge_pool_files` - A vector of `BridgePoolFile` structs containing the file path and content.
+/// * `keep_raw` - Whether parsed files keep their raw bytes, as in [`parse_bridge_pool_files_keeping_raw`].
+/// * `strategy` - How assignment digests are computed.
+///
+/// # Returns
+///
+/// * `Ok(Vec<ParsedBridgePoolAssignment>)` - A vector of parsed bridge pool assignments.
+/// * `Err(anyhow::Error)` - An error if parsing fails for any file.
+pub fn parse_bridge_pool_files_with_strategy(
+    bridge_pool_files: Vec<BridgePoolFile>,
+    keep_raw: bool,
+    strategy: AssignmentDigestStrategy,
+) -> AnyhowResult<Vec<ParsedBridgePoolAssignment>> {
+    parse_files(bridge_pool_files, keep_raw, strategy)
+}
+
+/// Parses bridge pool assignment files, skipping what cannot be parsed instead of failing.
+///
+/// Files that cannot be parsed at all are skipped, as are lines that are not a fingerprint followed
+/// by an assignment (which [`parse_bridge_pool_files`] drops silently). Each skipped file or line is
+/// returned as a [`ParseFailure`], e.g. for a dead-letter file to reprocess later.
+///
+/// # Arguments
+///
+/// * `bridge_pool_files` - A vector of `BridgePoolFile` structs containing the file path and content.
+/// * `keep_raw` - Whether parsed files keep their raw bytes, as in [`parse_bridge_pool_files_keeping_raw`].
+/// * `strategy` - How assignment digests are computed.
+///
+/// # Returns
+///
+/// The parsed assignments of all files that could be parsed, and the skipped files and lines.
+pub fn parse_bridge_pool_files_best_effort(
+    bridge_pool_files: Vec<BridgePoolFile>,
+    keep_raw: bool,
+    strategy: AssignmentDigestStrategy,
+) -> (Vec<ParsedBridgePoolAssignment>, Vec<ParseFailure>) {
+    let mut parsed_assignments = Vec::new();
+    let mut failures = Vec::new();
+
+    for file in bridge_pool_files {
+        let mut skipped_lines = Vec::new();
+        match parse_file(&file, &mut skipped_lines, strategy) {
+            Ok(mut parsed) => {
+                if !skipped_lines.is_empty() {
+                    let file_digest = compute_file_digest(&file.raw_content).to_string();
+                    failures.extend(skipped_lines.into_iter().map(|line| ParseFailure {
+                        path: file.path.clone(),
+                        file_digest: file_digest.clone(),
+                        line: Some(line),
+                        error: "Line is not a fingerprint followed by an assignment".to_string(),
+                    }));
+                }
+                if keep_raw {
+                    keep_raw_content(&mut parsed, file);
+                }
+                parsed_assignments.extend(parsed);
+            }
+            Err(e) => failures.push(ParseFailure {
+                file_digest: compute_file_digest(&file.raw_content).to_string(),
+                path: file.path,
+                line: None,
+                error: format!("{:#}", e),
+            }),
+        }
+    }
+
+    (parsed_assignments, failures)
+}
+
+/// Parses each file, computing its digest and dropping its raw bytes unless `keep_raw` is set.
+fn parse_files(
+    bridge_pool_files: Vec<BridgePoolFile>,
+    keep_raw: bool,
+    strategy: AssignmentDigestStrategy,
 ) -> AnyhowResult<Vec<ParsedBridgePoolAssignment>> {
     let mut parsed_assignments = Vec::new();
 
     for file in bridge_pool_files {
-        let parsed = parse_single_bridge_pool_file(&file.content, file.raw_content)
+        let mut parsed = parse_file(&file, &mut Vec::new(), strategy)
             .context(format!("Failed to parse file: {}", file.path))?;
-        parsed_assignments.push(parsed);
+        if keep_raw {
+            keep_raw_content(&mut parsed, file);
+        }
+        parsed_assignments.extend(parsed);
     }
 
     Ok(parsed_assignments)
 }
 
+/// Parses every document of `file` (see [`parse_multi`]) and copies the file's metadata into each.
+fn parse_file(
+    file: &BridgePoolFile,
+    skipped_lines: &mut Vec<String>,
+    strategy: AssignmentDigestStrategy,
+) -> AnyhowResult<Vec<ParsedBridgePoolAssignment>> {
+    let mut documents = parse_documents(&file.content, &file.raw_content, skipped_lines, strategy)?;
+    for parsed in &mut documents {
+        parsed.freshness = file.freshness();
+        parsed.last_modified_millis = file.last_modified;
+    }
+    Ok(documents)
+}
+
+/// Stores the raw bytes of `file` on its parsed documents: the whole file for a single document, or
+/// each document's own bytes for a file of several documents.
+fn keep_raw_content(documents: &mut [ParsedBridgePoolAssignment], file: BridgePoolFile) {
+    match documents {
+        [single] => single.raw_content = Some(file.raw_content),
+        multiple => {
+            for (parsed, document) in multiple.iter_mut().zip(split_documents(&file.content)) {
+                parsed.raw_content = Some(document.as_bytes().to_vec());
+            }
+        }
+    }
+}
+
+/// Parses content that may hold several concatenated bridge pool assignment documents.
+///
+/// Archive files sometimes concatenate several documents, each opened by its own
+/// "bridge-pool-assignment" header. The content is split before every header but the first, and each
+/// document is parsed on its own. A document's digest is computed from its own bytes, so the documents
+/// of one file get distinct digests. Content with a single header is parsed as a whole, with its
+/// digest computed over all of `raw_content`.
+///
+/// # Arguments
+///
+/// * `content` - The string content of the file.
+/// * `raw_content` - The raw bytes of the file content, used for the digest of a single document.
+///
+/// # Returns
+///
+/// * `Ok(Vec<ParsedBridgePoolAssignment>)` - One parsed assignment per document, in file order.
+/// * `Err(anyhow::Error)` - An error if any document fails to parse.
+///
+/// # Examples
+///
+/// ```rust
+/// use bridge_pool_assignments::parse::parse_multi;
+/// let content = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+///     005fd4d7decbb250055b861579e6fdc79ad17bee email\n\
+///     bridge-pool-assignment 2022-04-09 00:59:37\n\
+///     005fd4d7decbb250055b861579e6fdc79ad17bee moat\n";
+/// let documents = parse_multi(content, content.as_bytes()).unwrap();
+/// assert_eq!(documents.len(), 2);
+/// assert_eq!(documents[1].entries["005fd4d7decbb250055b861579e6fdc79ad17bee"], "moat");
+/// ```
+pub fn parse_multi(content: &str, raw_content: &[u8]) -> AnyhowResult<Vec<ParsedBridgePoolAssignment>> {
+    parse_documents(content, raw_content, &mut Vec::new(), AssignmentDigestStrategy::default())
+}
+
+/// Parses every document of `content` like [`parse_multi`], collecting skipped lines as
+/// [`parse_single_bridge_pool_file`] does.
+fn parse_documents(
+    content: &str,
+    raw_content: &[u8],
+    skipped_lines: &mut Vec<String>,
+    strategy: AssignmentDigestStrategy,
+) -> AnyhowResult<Vec<ParsedBridgePoolAssignment>> {
+    let documents = split_documents(content);
+    if documents.len() == 1 {
+        return Ok(vec![parse_single_bridge_pool_file(content, raw_content, skipped_lines, strategy)?]);
+    }
+    let count = documents.len();
+    documents
+        .into_iter()
+        .enumerate()
+        .map(|(i, document)| {
+            parse_single_bridge_pool_file(document, document.as_bytes(), skipped_lines, strategy)
+                .with_context(|| format!("Failed to parse document {} of {}", i + 1, count))
+        })
+        .collect()
+}
+
+/// Splits `content` before every "bridge-pool-assignment" header line except the first.
+///
+/// Anything before the first header stays with the first document, so content with at most one header
+/// is returned unchanged as a single document.
+fn split_documents(content: &str) -> Vec<&str> {
+    let mut documents = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    let mut seen_header = false;
+    for line in content.split_inclusive('\n') {
+        if line.trim().starts_with("bridge-pool-assignment") {
+            if seen_header {
+                documents.push(&content[start..offset]);
+                start = offset;
+            }
+            seen_header = true;
+        }
+        offset += line.len();
+    }
+    documents.push(&content[start..]);
+    documents
+}
+
 /// Parses a single bridge pool assignment file's content.
 ///
 /// This internal function processes the content of a single file, extracting the timestamp and
@@ -56,16 +289,23 @@ pub fn parse_bridge_pool_files(
 /// # Arguments
 ///
 /// * `content` - The string content of the bridge pool assignment file.
-/// * `raw_content` - The raw bytes of the file content for digest calculation.
+/// * `raw_content` - The raw bytes of the file content, used for the file digest only.
+/// * `skipped_lines` - Receives the non-empty lines skipped because they are not bridge entries.
+/// * `strategy` - How assignment digests are computed.
 ///
 /// # Returns
 ///
 /// * `Ok(ParsedBridgePoolAssignment)` - The parsed data.
 /// * `Err(anyhow::Error)` - An error if parsing fails (e.g., missing or invalid lines).
-fn parse_single_bridge_pool_file(content: &str, raw_content: Vec<u8>) -> AnyhowResult<ParsedBridgePoolAssignment> {
+fn parse_single_bridge_pool_file(
+    content: &str,
+    raw_content: &[u8],
+    skipped_lines: &mut Vec<String>,
+    strategy: AssignmentDigestStrategy,
+) -> AnyhowResult<ParsedBridgePoolAssignment> {
     let mut lines = content.lines();
     let mut published_millis = None;
-    let mut raw_lines = BTreeMap::new();
+    let mut assignment_digests = BTreeMap::new();
 
     // Find and parse the "bridge-pool-assignment" line
     let mut header_line = None;
@@ -79,11 +319,25 @@ fn parse_single_bridge_pool_file(content: &str, raw_content: Vec<u8>) -> AnyhowR
         }
     }
 
-    // Ensure we found a bridge-pool-assignment line
-    let published_millis = published_millis.context("No bridge-pool-assignment line found")?;
+    // Ensure we found a bridge-pool-assignment line, explaining why if we didn't
+    let published_millis = match published_millis {
+        Some(published_millis) => published_millis,
+        None if content.trim().is_empty() => return Err(anyhow::anyhow!("File is empty")),
+        None => match detect_foreign_content(content) {
+            Some(kind) => {
+                return Err(anyhow::anyhow!(
+                    "Content is not a bridge pool assignment (looks like {})",
+                    kind
+                ))
+            }
+            None => return Err(anyhow::anyhow!("No bridge-pool-assignment line found")),
+        },
+    };
 
-    // Parse remaining lines for bridge entries
+    // Parse remaining lines for bridge entries, digesting each raw line as it is read
+    let file_digest = compute_file_digest(raw_content);
     let mut entries = BTreeMap::new();
+    let mut duplicate_fingerprints = Vec::new();
     
     // Reset lines iterator to process from beginning for raw line capture
     let content_lines = content.lines();
@@ -91,29 +345,80 @@ fn parse_single_bridge_pool_file(content: &str, raw_content: Vec<u8>) -> AnyhowR
     for line in content_lines {
         let trimmed = line.trim();
         
-        // Skip header line, we already processed it
-        if Some(trimmed) == header_line {
+        // Skip header line, we already processed it, and annotations such as CollecTor's
+        // "@type bridge-pool-assignment 1.0"
+        if Some(trimmed) == header_line || trimmed.starts_with('@') {
             continue;
         }
         
-        if let Some((fingerprint, assignment)) = parse_bridge_line(trimmed)? {
-            entries.insert(fingerprint.clone(), assignment);
-            // Store raw line bytes for digest calculation
-            raw_lines.insert(fingerprint, trimmed.as_bytes().to_vec());
+        match parse_bridge_line(trimmed)? {
+            Some((fingerprint, assignment)) => {
+                if entries.insert(fingerprint.clone(), assignment).is_some() {
+                    duplicate_fingerprints.push(fingerprint.clone());
+                }
+                assignment_digests.insert(fingerprint, compute_assignment_digest(trimmed.as_bytes(), &file_digest, strategy));
+            }
+            None if !trimmed.is_empty() => skipped_lines.push(trimmed.to_string()),
+            None => {}
         }
     }
 
     Ok(ParsedBridgePoolAssignment {
         published_millis,
         entries,
-        raw_content,
-        raw_lines,
+        file_digest,
+        raw_content: None,
+        assignment_digests,
+        header: header_line.map(str::to_string),
+        freshness: Freshness::Unknown,
+        last_modified_millis: 0,
+        duplicate_fingerprints,
     })
 }
 
+/// Recognizes content that is obviously something other than a bridge pool assignment.
+///
+/// Only the first non-empty line is inspected: HTML pages (e.g. an error page served with status 200)
+/// start with a tag, XML documents with an `<?xml` declaration, and other Tor descriptors start with an
+/// `@type` annotation naming a different type or with their own opening keyword.
+///
+/// # Arguments
+///
+/// * `content` - The string content of the file.
+///
+/// # Returns
+///
+/// A description of the detected content (e.g. "an HTML page"), or `None` if nothing obvious was found.
+fn detect_foreign_content(content: &str) -> Option<String> {
+    let first_line = content.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let lowercase = first_line.to_ascii_lowercase();
+    if lowercase.starts_with("<!doctype html") || lowercase.starts_with("<html") {
+        return Some("an HTML page".to_string());
+    }
+    if lowercase.starts_with("<?xml") {
+        return Some("an XML document".to_string());
+    }
+
+    let mut parts = first_line.split_whitespace();
+    let keyword = parts.next()?;
+    if keyword == "@type" {
+        return match parts.next() {
+            Some("bridge-pool-assignment") | None => None,
+            Some(descriptor_type) => Some(format!("a {} descriptor", descriptor_type)),
+        };
+    }
+    if DESCRIPTOR_KEYWORDS.contains(&keyword) {
+        return Some(format!("a Tor descriptor starting with \"{}\"", keyword));
+    }
+    None
+}
+
 /// Parses the "bridge-pool-assignment" line to extract the publication timestamp.
 ///
-/// The expected format is "bridge-pool-assignment YYYY-MM-DD HH:MM:SS".
+/// The expected format is "bridge-pool-assignment YYYY-MM-DD HH:MM:SS". The time may be followed by a
+/// UTC designator ("UTC", "Z", "GMT" or "UT") and/or a free-form annotation, which are ignored; the
+/// verbatim line (trailing tokens included) is kept as the file's header. A numeric offset such as
+/// "+02:00" or a known non-UTC zone such as "CET" is rejected rather than silently read as UTC.
 ///
 /// # Arguments
 ///
@@ -125,16 +430,42 @@ fn parse_single_bridge_pool_file(content: &str, raw_content: Vec<u8>) -> AnyhowR
 /// * `Err(anyhow::Error)` - An error if the line is malformed or the timestamp is invalid.
 fn parse_bridge_pool_assignment_line(line: &str) -> AnyhowResult<i64> {
     let parts: Vec<&str> = line.split_whitespace().collect();
-    if parts.len() != 3 || parts[0] != "bridge-pool-assignment" {
+    if parts.len() < 3 || parts[0] != "bridge-pool-assignment" {
         return Err(anyhow::anyhow!("Invalid bridge-pool-assignment line: {}", line));
     }
     let date = parts[1];
     let time = parts[2];
+    if let Some(zone) = parts.get(3).filter(|token| is_non_utc_time_zone(token)) {
+        return Err(anyhow::anyhow!(
+            "Unsupported time zone {:?} in bridge-pool-assignment line (only UTC is supported): {}",
+            zone,
+            line
+        ));
+    }
+    if parts.len() > 3 {
+        log::debug!("Ignoring trailing tokens {:?} of header {:?}", &parts[3..], line);
+    }
     let timestamp_str = format!("{} {}", date, time);
     let naive_dt = NaiveDateTime::parse_from_str(&timestamp_str, "%Y-%m-%d %H:%M:%S")
         .context("Failed to parse timestamp")?;
-    let published_millis = naive_dt.and_utc().timestamp_millis();
-    Ok(published_millis)
+    millis_from_naive_utc(naive_dt)
+}
+
+/// Returns whether a header token names a time zone other than UTC: a numeric offset such as "+02:00"
+/// or "-0500", or one of [`NON_UTC_TIME_ZONES`]. UTC designators and other tokens, such as
+/// annotations, are not.
+///
+/// # Arguments
+///
+/// * `token` - The token following the time in a "bridge-pool-assignment" line.
+fn is_non_utc_time_zone(token: &str) -> bool {
+    if UTC_DESIGNATORS.contains(&token) {
+        return false;
+    }
+    let bytes = token.as_bytes();
+    let is_offset = matches!(bytes.first(), Some(b'+') | Some(b'-'))
+        && bytes.get(1).is_some_and(u8::is_ascii_digit);
+    is_offset || NON_UTC_TIME_ZONES.contains(&token)
 }
 
 /// Parses a bridge entry line to extract the fingerprint and assignment string.
@@ -173,8 +504,7 @@ bridge-pool-assignment 2022-04-09 00:29:37
 005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4
 01ea4fb2da2086e71e7ca84c683fcadd2aa9036b email transport=obfs4
 ";
-        let raw_content = content.as_bytes().to_vec();
-        let result = parse_single_bridge_pool_file(content, raw_content).unwrap();
+        let result = parse_single_bridge_pool_file(content, content.as_bytes(), &mut Vec::new(), Default::default()).unwrap();
         
         assert_eq!(result.published_millis, 1649464177000);
         assert_eq!(result.entries.len(), 2);
@@ -186,8 +516,115 @@ bridge-pool-assignment 2022-04-09 00:29:37
             result.entries["01ea4fb2da2086e71e7ca84c683fcadd2aa9036b"],
             "email transport=obfs4"
         );
-        assert!(result.raw_lines.contains_key("005fd4d7decbb250055b861579e6fdc79ad17bee"));
-        assert!(result.raw_lines.contains_key("01ea4fb2da2086e71e7ca84c683fcadd2aa9036b"));
+        assert!(result.assignment_digests.contains_key("005fd4d7decbb250055b861579e6fdc79ad17bee"));
+        assert!(result.assignment_digests.contains_key("01ea4fb2da2086e71e7ca84c683fcadd2aa9036b"));
+        assert_eq!(result.header.as_deref(), Some("bridge-pool-assignment 2022-04-09 00:29:37"));
+    }
+
+    /// Tests that CollecTor's `@type` annotation is neither an entry nor a skipped line.
+    #[test]
+    fn test_parse_single_bridge_pool_file_with_type_annotation() {
+        let content = "\
+@type bridge-pool-assignment 1.0
+bridge-pool-assignment 2022-04-09 00:29:37
+005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4
+";
+        let mut skipped_lines = Vec::new();
+        let result = parse_single_bridge_pool_file(content, content.as_bytes(), &mut skipped_lines, Default::default()).unwrap();
+
+        assert_eq!(result.entries.keys().collect::<Vec<_>>(), ["005fd4d7decbb250055b861579e6fdc79ad17bee"]);
+        assert!(skipped_lines.is_empty());
+    }
+
+    /// Tests that digests computed while parsing match the digests of the raw file bytes, and that the
+    /// raw bytes are only kept on request.
+    #[test]
+    fn test_parse_computes_unchanged_digests() {
+        let content = "\
+bridge-pool-assignment 2022-04-09 00:29:37
+005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4
+01ea4fb2da2086e71e7ca84c683fcadd2aa9036b email transport=obfs4
+";
+        let file = || BridgePoolFile {
+            path: "file1".to_string(),
+            last_modified: 0,
+            content: content.to_string(),
+            raw_content: content.as_bytes().to_vec(),
+        };
+
+        let parsed = parse_bridge_pool_files(vec![file()]).unwrap().remove(0);
+        let kept = parse_bridge_pool_files_keeping_raw(vec![file()]).unwrap().remove(0);
+
+        assert_eq!(
+            parsed.file_digest.as_str(),
+            "45df2d36098298d289de00a6891614e09284a4be5466026f9e033965a0a73b41"
+        );
+        assert_eq!(
+            parsed.assignment_digests["005fd4d7decbb250055b861579e6fdc79ad17bee"].as_str(),
+            "d896a7021ec058ee3d835c7d7cec30677dea44c5e8b28de8a0f8f8b9b480448e"
+        );
+        assert_eq!(
+            parsed.assignment_digests["01ea4fb2da2086e71e7ca84c683fcadd2aa9036b"],
+            compute_assignment_digest(
+                b"01ea4fb2da2086e71e7ca84c683fcadd2aa9036b email transport=obfs4",
+                &parsed.file_digest,
+                AssignmentDigestStrategy::default()
+            )
+        );
+        assert_eq!(parsed.raw_content, None);
+        assert_eq!(kept.file_digest, parsed.file_digest);
+        assert_eq!(kept.raw_content.as_deref(), Some(content.as_bytes()));
+    }
+
+    /// Tests that assignment digests are computed with the requested strategy.
+    #[test]
+    fn test_parse_with_digest_strategy() {
+        let content = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+            005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n";
+        let file = BridgePoolFile {
+            path: "file1".to_string(),
+            last_modified: 0,
+            content: content.to_string(),
+            raw_content: content.as_bytes().to_vec(),
+        };
+        let strategy = AssignmentDigestStrategy::FileDigestBytesThenLine;
+
+        let parsed = parse_bridge_pool_files_with_strategy(vec![file.clone()], false, strategy).unwrap().remove(0);
+
+        assert_eq!(
+            parsed.assignment_digests["005fd4d7decbb250055b861579e6fdc79ad17bee"],
+            compute_assignment_digest(
+                b"005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4",
+                &parsed.file_digest,
+                strategy
+            )
+        );
+        assert_ne!(
+            parsed.assignment_digests["005fd4d7decbb250055b861579e6fdc79ad17bee"],
+            parse_bridge_pool_files(vec![file.clone()]).unwrap()[0].assignment_digests
+                ["005fd4d7decbb250055b861579e6fdc79ad17bee"]
+        );
+    }
+
+    /// Tests that a repeated fingerprint is reported once per repetition, while only its last
+    /// assignment is kept in the entries.
+    #[test]
+    fn test_parse_reports_duplicate_fingerprints() {
+        let content = "\
+bridge-pool-assignment 2022-04-09 00:29:37
+005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4
+01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https
+005fd4d7decbb250055b861579e6fdc79ad17bee moat
+005fd4d7decbb250055b861579e6fdc79ad17bee moat
+";
+        let result = parse_single_bridge_pool_file(content, content.as_bytes(), &mut Vec::new(), Default::default()).unwrap();
+
+        assert_eq!(result.entries.len(), 2);
+        assert_eq!(result.entries["005fd4d7decbb250055b861579e6fdc79ad17bee"], "moat");
+        assert_eq!(
+            result.duplicate_fingerprints,
+            ["005fd4d7decbb250055b861579e6fdc79ad17bee", "005fd4d7decbb250055b861579e6fdc79ad17bee"]
+        );
     }
 
     /// Tests parsing a bridge pool assignment file with an invalid header.
@@ -197,12 +634,58 @@ bridge-pool-assignment 2022-04-09 00:29:37
 invalid-header 2022-04-09 00:29:37
 005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4
 ";
-        let raw_content = content.as_bytes().to_vec();
-        let result = parse_single_bridge_pool_file(content, raw_content);
+        let result = parse_single_bridge_pool_file(content, content.as_bytes(), &mut Vec::new(), Default::default());
         
         assert!(result.is_err());
     }
 
+    /// Tests that an HTML error page or XML document is reported as foreign content rather than a
+    /// missing header.
+    #[test]
+    fn test_parse_single_bridge_pool_file_html_body() {
+        let content = "<!DOCTYPE html>\n<html><head><title>404 Not Found</title></head>\n<body>Not Found</body></html>\n";
+        let err = parse_single_bridge_pool_file(content, content.as_bytes(), &mut Vec::new(), Default::default()).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Content is not a bridge pool assignment (looks like an HTML page)"
+        );
+
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>NoSuchKey</Code></Error>\n";
+        let err = parse_single_bridge_pool_file(xml, xml.as_bytes(), &mut Vec::new(), Default::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Content is not a bridge pool assignment (looks like an XML document)"
+        );
+    }
+
+    /// Tests that a relay descriptor is reported as foreign content, both with and without an `@type`
+    /// annotation.
+    #[test]
+    fn test_parse_single_bridge_pool_file_relay_descriptor_body() {
+        let annotated = "@type server-descriptor 1.0\nrouter test 192.0.2.1 9001 0 0\nplatform Tor 0.4.8.9\n";
+        let err = parse_single_bridge_pool_file(annotated, annotated.as_bytes(), &mut Vec::new(), Default::default()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Content is not a bridge pool assignment (looks like a server-descriptor descriptor)"
+        );
+
+        let bare = "router test 192.0.2.1 9001 0 0\nplatform Tor 0.4.8.9\n";
+        let err = parse_single_bridge_pool_file(bare, bare.as_bytes(), &mut Vec::new(), Default::default()).unwrap_err();
+        assert!(err.to_string().contains("looks like a Tor descriptor starting with \"router\""));
+    }
+
+    /// Tests that empty files and files merely lacking the header get their own errors.
+    #[test]
+    fn test_parse_single_bridge_pool_file_empty_vs_missing_header() {
+        let err = parse_single_bridge_pool_file("\n  \n", b"\n  \n", &mut Vec::new(), Default::default()).unwrap_err();
+        assert_eq!(err.to_string(), "File is empty");
+
+        let content = "005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n";
+        let err = parse_single_bridge_pool_file(content, content.as_bytes(), &mut Vec::new(), Default::default()).unwrap_err();
+        assert_eq!(err.to_string(), "No bridge-pool-assignment line found");
+    }
+
     /// Tests parsing a bridge pool assignment file with an invalid timestamp format.
     #[test]
     fn test_parse_bridge_pool_assignment_line_invalid_timestamp() {
@@ -212,6 +695,50 @@ invalid-header 2022-04-09 00:29:37
         assert!(result.is_err());
     }
 
+    /// Tests that a trailing UTC designator or annotation after the time, even an uppercase one, is
+    /// ignored while the verbatim header keeps it, and that offsets and non-UTC zones are rejected.
+    #[test]
+    fn test_parse_bridge_pool_assignment_line_trailing_tokens() {
+        for line in [
+            "bridge-pool-assignment 2022-04-09 00:29:37",
+            "bridge-pool-assignment  2022-04-09  00:29:37",
+            "bridge-pool-assignment 2022-04-09 00:29:37 UTC",
+            "bridge-pool-assignment 2022-04-09 00:29:37 Z",
+            "bridge-pool-assignment 2022-04-09 00:29:37 GMT",
+            "bridge-pool-assignment 2022-04-09 00:29:37 UT",
+            "bridge-pool-assignment 2022-04-09 00:29:37 (regenerated by bridgedb 0.9)",
+            "bridge-pool-assignment 2022-04-09 00:29:37 TEST",
+            "bridge-pool-assignment 2022-04-09 00:29:37 FINAL rerun",
+            "bridge-pool-assignment 2022-04-09 00:29:37 UTC (regenerated by bridgedb 0.9)",
+        ] {
+            assert_eq!(parse_bridge_pool_assignment_line(line).unwrap(), 1649464177000, "{}", line);
+        }
+        assert!(parse_bridge_pool_assignment_line("bridge-pool-assignment 2022-04-09").is_err());
+        for zone in ["+02:00", "-0500", "CET", "EDT"] {
+            let line = format!("bridge-pool-assignment 2022-04-09 00:29:37 {}", zone);
+            let err = parse_bridge_pool_assignment_line(&line).unwrap_err();
+            assert!(err.to_string().contains("Unsupported time zone"), "{}: {}", line, err);
+        }
+
+        let content = "bridge-pool-assignment 2022-04-09 00:29:37 UTC\n\
+            005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n";
+        let parsed = parse_single_bridge_pool_file(content, content.as_bytes(), &mut Vec::new(), Default::default()).unwrap();
+        assert_eq!(parsed.header.as_deref(), Some("bridge-pool-assignment 2022-04-09 00:29:37 UTC"));
+        assert_eq!(parsed.entries.len(), 1);
+    }
+
+    /// Tests that headers with far-future or far-past dates are rejected with a clear error.
+    #[test]
+    fn test_parse_bridge_pool_assignment_line_extreme_dates() {
+        for line in [
+            "bridge-pool-assignment +20000-01-01 00:00:00",
+            "bridge-pool-assignment -20000-01-01 00:00:00",
+        ] {
+            let err = parse_bridge_pool_assignment_line(line).unwrap_err();
+            assert!(format!("{:#}", err).contains("outside the supported range"), "{:#}", err);
+        }
+    }
+
     /// Tests parsing multiple bridge pool assignment files.
     #[test]
     fn test_parse_bridge_pool_files() {
@@ -238,4 +765,56 @@ invalid-header 2022-04-09 00:29:37
         assert_eq!(parsed[0].entries.len(), 1);
         assert_eq!(parsed[1].entries.len(), 1);
     }
+
+    /// Tests that a plain-text file named like an `.xz` archive, as a mislabeled file on CollecTor would
+    /// be, parses as-is: nothing decompresses content based on its file name.
+    #[test]
+    fn test_parse_plain_text_named_xz() {
+        let content = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+            005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n";
+        let files = vec![BridgePoolFile {
+            path: "recent/bridge-pool-assignments/2022-04-09-00-29-37.xz".to_string(),
+            last_modified: 0,
+            content: content.to_string(),
+            raw_content: content.as_bytes().to_vec(),
+        }];
+
+        let parsed = parse_bridge_pool_files(files).unwrap();
+
+        assert_eq!(parsed.len(), 1);
+        assert_eq!(parsed[0].published_millis, 1649464177000);
+        assert_eq!(
+            parsed[0].entries.get("005fd4d7decbb250055b861579e6fdc79ad17bee").map(String::as_str),
+            Some("email transport=obfs4")
+        );
+        assert_eq!(parsed[0].file_digest, compute_file_digest(content.as_bytes()));
+    }
+
+    /// Tests that concatenated documents are parsed separately, each with its own digest and raw bytes.
+    #[test]
+    fn test_parse_concatenated_documents() {
+        let first = "bridge-pool-assignment 2022-04-09 00:29:37\n005fd4d7decbb250055b861579e6fdc79ad17bee email\n";
+        let second = "bridge-pool-assignment 2022-04-09 00:59:37\n005fd4d7decbb250055b861579e6fdc79ad17bee moat\n";
+        let content = format!("{}{}", first, second);
+
+        let documents = parse_multi(&content, content.as_bytes()).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].published_millis, 1649464177000);
+        assert_eq!(documents[1].published_millis, 1649465977000);
+        assert_eq!(documents[0].entries["005fd4d7decbb250055b861579e6fdc79ad17bee"], "email");
+        assert_eq!(documents[1].entries["005fd4d7decbb250055b861579e6fdc79ad17bee"], "moat");
+        assert_eq!(documents[0].file_digest, compute_file_digest(first.as_bytes()));
+        assert_eq!(documents[1].file_digest, compute_file_digest(second.as_bytes()));
+
+        let files = vec![BridgePoolFile {
+            path: "archive".to_string(),
+            last_modified: 1,
+            content: content.clone(),
+            raw_content: content.as_bytes().to_vec(),
+        }];
+        let parsed = parse_bridge_pool_files_keeping_raw(files).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[1].last_modified_millis, 1);
+        assert_eq!(parsed[1].raw_content.as_deref(), Some(second.as_bytes()));
+    }
 } 
\ No newline at end of file
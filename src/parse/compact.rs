@@ -0,0 +1,242 @@
+use super::types::ParsedBridgePoolAssignment;
+use crate::fetch::Freshness;
+use crate::utils::Digest;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+/// Shares one allocation per distinct distribution method across compacted files.
+///
+/// There are only a handful of distribution methods ("email", "https", "moat", ...), repeated on every
+/// line, so storing each once saves an allocation per entry.
+#[derive(Debug, Default)]
+pub struct MethodInterner {
+    methods: HashSet<Arc<str>>,
+}
+
+impl MethodInterner {
+    /// Creates an empty interner.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the shared copy of `method`, adding it on first use.
+    fn intern(&mut self, method: &str) -> Arc<str> {
+        if let Some(interned) = self.methods.get(method) {
+            return Arc::clone(interned);
+        }
+        let interned: Arc<str> = Arc::from(method);
+        self.methods.insert(Arc::clone(&interned));
+        interned
+    }
+}
+
+/// A single assignment of a [`CompactBridgePoolAssignment`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactAssignment {
+    distribution_method: Arc<str>,
+    /// Everything after the distribution method, without the separating space (may be empty).
+    options: Box<str>,
+    digest: [u8; 32],
+}
+
+impl CompactAssignment {
+    /// Returns the distribution method (e.g., "email").
+    pub fn distribution_method(&self) -> &str {
+        &self.distribution_method
+    }
+
+    /// Returns the assignment string as it appeared in the file (e.g. "email transport=obfs4").
+    pub fn assignment_str(&self) -> String {
+        if self.options.is_empty() {
+            self.distribution_method.to_string()
+        } else {
+            format!("{} {}", self.distribution_method, self.options)
+        }
+    }
+
+    /// Returns the assignment digest.
+    pub fn digest(&self) -> Digest {
+        Digest::from_bytes(self.digest)
+    }
+}
+
+/// A memory-saving form of [`ParsedBridgePoolAssignment`], for callers holding many parsed files
+/// at once.
+///
+/// Entries live in a single vector sorted by fingerprint rather than in two maps keyed by fingerprint,
+/// distribution methods are shared through a [`MethodInterner`], and assignment digests are kept as
+/// raw bytes instead of hex strings. Converting with [`CompactBridgePoolAssignment::from_parsed`]
+/// and back with [`CompactBridgePoolAssignment::into_parsed`] is lossless.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactBridgePoolAssignment {
+    /// The time in milliseconds since the epoch when this descriptor was published.
+    pub published_millis: i64,
+    /// SHA-256 digest of the file's raw content.
+    pub file_digest: Digest,
+    /// Raw content of the file, if it was retained while parsing.
+    pub raw_content: Option<Vec<u8>>,
+    /// The verbatim header line of the file, if known.
+    pub header: Option<String>,
+    /// CollecTor freshness category of the source file.
+    pub freshness: Freshness,
+    /// Time in milliseconds since the epoch when CollecTor last modified the source file, or 0.
+    pub last_modified_millis: i64,
+    /// Fingerprints listed more than once in the file, once per repeated line.
+    pub duplicate_fingerprints: Vec<String>,
+    entries: Vec<(Box<str>, CompactAssignment)>,
+}
+
+impl CompactBridgePoolAssignment {
+    /// Compacts a parsed file.
+    ///
+    /// # Arguments
+    ///
+    /// * `parsed` - The parsed file.
+    /// * `interner` - Shares distribution methods; reuse it across files for the largest savings.
+    ///
+    /// # Returns
+    ///
+    /// The compact form of `parsed`. An entry without an assignment digest, which parsing never
+    /// produces, gets its digest recomputed from the entry's line with the default
+    /// [`AssignmentDigestStrategy`](crate::utils::AssignmentDigestStrategy).
+    pub fn from_parsed(parsed: ParsedBridgePoolAssignment, interner: &mut MethodInterner) -> Self {
+        let ParsedBridgePoolAssignment {
+            published_millis,
+            entries,
+            file_digest,
+            raw_content,
+            mut assignment_digests,
+            header,
+            freshness,
+            last_modified_millis,
+            duplicate_fingerprints,
+        } = parsed;
+
+        let entries = entries
+            .into_iter()
+            .map(|(fingerprint, assignment_str)| {
+                let digest = assignment_digests.remove(&fingerprint).unwrap_or_else(|| {
+                    let line = format!("{} {}", fingerprint, assignment_str);
+                    crate::utils::compute_assignment_digest(line.as_bytes(), &file_digest, Default::default())
+                });
+                let (method, options) = assignment_str.split_once(' ').unwrap_or((&assignment_str, ""));
+                let assignment = CompactAssignment {
+                    distribution_method: interner.intern(method),
+                    options: options.into(),
+                    digest: digest.to_bytes(),
+                };
+                (fingerprint.into_boxed_str(), assignment)
+            })
+            .collect();
+
+        Self {
+            published_millis,
+            file_digest,
+            raw_content,
+            header,
+            freshness,
+            last_modified_millis,
+            duplicate_fingerprints,
+            entries,
+        }
+    }
+
+    /// Expands this file back into a [`ParsedBridgePoolAssignment`], e.g. to hand it to an exporter.
+    pub fn into_parsed(self) -> ParsedBridgePoolAssignment {
+        let mut entries = BTreeMap::new();
+        let mut assignment_digests = BTreeMap::new();
+        for (fingerprint, assignment) in self.entries {
+            let fingerprint = String::from(fingerprint);
+            assignment_digests.insert(fingerprint.clone(), assignment.digest());
+            entries.insert(fingerprint, assignment.assignment_str());
+        }
+        ParsedBridgePoolAssignment {
+            published_millis: self.published_millis,
+            entries,
+            file_digest: self.file_digest,
+            raw_content: self.raw_content,
+            assignment_digests,
+            header: self.header,
+            freshness: self.freshness,
+            last_modified_millis: self.last_modified_millis,
+            duplicate_fingerprints: self.duplicate_fingerprints,
+        }
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the file has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the assignment of the bridge with `fingerprint`, if the file lists it.
+    pub fn get(&self, fingerprint: &str) -> Option<&CompactAssignment> {
+        self.entries
+            .binary_search_by(|(entry, _)| (**entry).cmp(fingerprint))
+            .ok()
+            .map(|index| &self.entries[index].1)
+    }
+
+    /// Iterates over the fingerprints and assignments in fingerprint order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &CompactAssignment)> {
+        self.entries.iter().map(|(fingerprint, assignment)| (&**fingerprint, assignment))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::BridgePoolFile;
+    use crate::parse::parse_bridge_pool_files;
+    use crate::testutil::generate_bridge_pool_file;
+
+    fn parse(content: &str) -> ParsedBridgePoolAssignment {
+        parse_bridge_pool_files(vec![BridgePoolFile {
+            path: "recent/bridge-pool-assignments/file".to_string(),
+            last_modified: 0,
+            content: content.to_string(),
+            raw_content: content.as_bytes().to_vec(),
+        }])
+        .unwrap()
+        .remove(0)
+    }
+
+    /// Tests that the compact form holds the same entries and digests as the parsed form, and expands
+    /// back into identical parsed data.
+    #[test]
+    fn test_compact_round_trip() {
+        let mut content = generate_bridge_pool_file(500, 7);
+        content.push_str("ffffffffffffffffffffffffffffffffffffffff unallocated\n");
+        let parsed = parse(&content);
+        let mut interner = MethodInterner::new();
+
+        let compact = CompactBridgePoolAssignment::from_parsed(parse(&content), &mut interner);
+
+        assert_eq!(compact.len(), parsed.entries.len());
+        for ((fingerprint, assignment), (expected_fingerprint, expected_str)) in
+            compact.entries().zip(&parsed.entries)
+        {
+            assert_eq!(fingerprint, expected_fingerprint);
+            assert_eq!(assignment.assignment_str(), *expected_str);
+            assert_eq!(assignment.digest(), parsed.assignment_digests[fingerprint]);
+        }
+        let unallocated = compact.get("ffffffffffffffffffffffffffffffffffffffff").unwrap();
+        assert_eq!(unallocated.distribution_method(), "unallocated");
+        assert_eq!(unallocated.assignment_str(), "unallocated");
+        assert!(compact.get("0000000000000000000000000000000000000000").is_none());
+        assert!(interner.methods.len() < 10);
+
+        let expanded = compact.into_parsed();
+        assert_eq!(expanded.entries, parsed.entries);
+        assert_eq!(expanded.assignment_digests, parsed.assignment_digests);
+        assert_eq!(expanded.file_digest, parsed.file_digest);
+        assert_eq!(expanded.published_millis, parsed.published_millis);
+        assert_eq!(expanded.header, parsed.header);
+        assert_eq!(expanded.freshness, parsed.freshness);
+        assert_eq!(expanded.duplicate_fingerprints, parsed.duplicate_fingerprints);
+    }
+}
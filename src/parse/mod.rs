@@ -13,10 +13,26 @@
 //! ## Submodules
 //!
 //! - **bridge_pool**: Contains the core parsing logic for bridge pool assignment files.
-//! - **types**: Defines data structures used in the parsing process.
+//! - **assignment**: Splits assignment strings into structured `BridgeAssignment` fields.
+//! - **compact**: A memory-saving form of parsed files for callers holding many of them at once.
+//! - **validate**: Opt-in semantic and format checks flagging inconsistent or malformed assignments as
+//!   warnings.
+//! - **types**: Defines data structures used in the parsing process and the `AssignmentParser` trait.
 
+mod assignment;
 mod bridge_pool;
+mod compact;
 mod types;
+mod validate;
 
-pub use bridge_pool::parse_bridge_pool_files;
-pub use types::ParsedBridgePoolAssignment; 
\ No newline at end of file
+pub use assignment::{parse_assignment, DefaultAssignmentParser};
+pub use bridge_pool::{
+    parse_bridge_pool_files, parse_bridge_pool_files_best_effort, parse_bridge_pool_files_keeping_raw,
+    parse_bridge_pool_files_with_strategy, parse_multi,
+};
+pub use compact::{CompactAssignment, CompactBridgePoolAssignment, MethodInterner};
+pub use types::{
+    AssignmentParser, AssignmentWarning, BridgeAssignment, ParseFailure, ParsedBridgePoolAssignment,
+    ValidationRule,
+};
+pub use validate::{format_warnings, validate_assignments}; 
\ No newline at end of file
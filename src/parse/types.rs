@@ -1,19 +1,148 @@
+use crate::fetch::Freshness;
+use crate::utils::Digest;
+use serde::Serialize;
 use std::collections::BTreeMap;
 use std::fmt::Debug;
 
+/// Header keyword stored for files whose verbatim header line is unknown.
+const DEFAULT_HEADER: &str = "bridge-pool-assignment";
+
 /// Represents a parsed bridge pool assignment, containing the publication timestamp and a map of bridge entries.
 /// 
-/// This struct stores both the structured data extracted from the file and the raw bytes needed for
-/// digest calculation according to the original metrics library approach.
-#[derive(Debug)]
+/// This struct stores the structured data extracted from the file together with the file digest, which
+/// is computed while parsing so the file's raw bytes need not be kept around until export.
+#[derive(Debug, Clone)]
 pub struct ParsedBridgePoolAssignment {
     /// The time in milliseconds since the epoch when this descriptor was published.
     pub published_millis: i64,
     /// A map of bridge fingerprints (SHA-1 digests as 40-character hex strings) to their assignment strings.
     pub entries: BTreeMap<String, String>,
-    /// Raw content of the file for file digest calculation using SHA-256.
-    pub raw_content: Vec<u8>,
-    /// Map of fingerprints to raw line bytes for individual assignment digest calculation using SHA-256.
-    /// Each line's bytes are used to generate a unique digest for database storage.
-    pub raw_lines: BTreeMap<String, Vec<u8>>,
-} 
\ No newline at end of file
+    /// SHA-256 digest of the file's raw content.
+    pub file_digest: Digest,
+    /// Raw content of the file (the bytes its digest was computed from), only retained when parsing with
+    /// [`parse_bridge_pool_files_keeping_raw`](crate::parse::parse_bridge_pool_files_keeping_raw).
+    pub raw_content: Option<Vec<u8>>,
+    /// Map of fingerprints to assignment digests, each the SHA-256 digest of the entry's raw line
+    /// combined with the file digest as chosen by an
+    /// [`AssignmentDigestStrategy`](crate::utils::AssignmentDigestStrategy) (see
+    /// [`compute_assignment_digest`](crate::utils::compute_assignment_digest)). Computed while parsing,
+    /// so the raw lines need not be kept until export.
+    pub assignment_digests: BTreeMap<String, Digest>,
+    /// The verbatim header line of the file (e.g., "bridge-pool-assignment 2022-04-09 00:29:37"),
+    /// if known.
+    pub header: Option<String>,
+    /// CollecTor freshness category of the source file (recent or archive).
+    pub freshness: Freshness,
+    /// Time in milliseconds since the epoch when CollecTor last modified the source file, or 0 if
+    /// unknown.
+    pub last_modified_millis: i64,
+    /// Fingerprints listed more than once in the file, once per repeated line, in file order. Only the
+    /// last line of a repeated fingerprint is kept in `entries`. Informational: frequent duplicates
+    /// may indicate a malformed file or a producer bug.
+    pub duplicate_fingerprints: Vec<String>,
+}
+
+impl ParsedBridgePoolAssignment {
+    /// Returns the header to store for this file: the verbatim header line, or the plain
+    /// "bridge-pool-assignment" keyword if it wasn't captured.
+    pub fn header_or_default(&self) -> &str {
+        self.header.as_deref().unwrap_or(DEFAULT_HEADER)
+    }
+}
+
+/// Structured fields of a single assignment string (e.g. "email transport=obfs4 blocklist=cn,ir").
+///
+/// The distribution method is always present; all other fields are optional `key=value` pairs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BridgeAssignment {
+    /// Method of distribution (e.g., "email", "https", "moat").
+    pub distribution_method: String,
+    /// Pluggable transport (e.g., "obfs4").
+    pub transport: Option<String>,
+    /// IP version or address the bridge is distributed for.
+    pub ip: Option<String>,
+    /// Raw `blocklist` value as it appeared in the file (e.g., "cn,ir").
+    pub blocklist: Option<String>,
+    /// Individual country/region codes of the blocklist, lowercased (e.g., ["cn", "ir"]).
+    pub blocklist_countries: Vec<String>,
+    /// Whether the bridge has been distributed.
+    pub distributed: Option<bool>,
+    /// State information.
+    pub state: Option<String>,
+    /// Bandwidth value as it appeared in the file (e.g., "sufficient" or "1024KB").
+    pub bandwidth: Option<String>,
+    /// Numeric bandwidth in bytes, if `bandwidth` is a number with a recognized unit.
+    pub bandwidth_bytes: Option<i64>,
+    /// Ratio value; a percentage such as `50%` is stored as the fraction `0.5`.
+    pub ratio: Option<f32>,
+}
+
+/// Turns an assignment string into its structured fields.
+///
+/// BridgeDB has formatted assignment strings differently over time. The default implementation,
+/// [`DefaultAssignmentParser`](crate::parse::DefaultAssignmentParser), understands the current format;
+/// callers processing historical data can supply their own implementation to the exporters instead
+/// of forking the crate.
+pub trait AssignmentParser: Debug + Send + Sync {
+    /// Parses a single assignment string (e.g. "email transport=obfs4 blocklist=cn,ir").
+    fn parse(&self, assignment_str: &str) -> BridgeAssignment;
+}
+
+/// A file or line skipped by
+/// [`parse_bridge_pool_files_best_effort`](crate::parse::parse_bridge_pool_files_best_effort).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParseFailure {
+    /// Relative path of the file.
+    pub path: String,
+    /// Hex-encoded SHA-256 digest of the file's raw content.
+    pub file_digest: String,
+    /// The skipped line, if only a line failed; `None` if the whole file was skipped.
+    pub line: Option<String>,
+    /// Why the file or line was skipped.
+    pub error: String,
+}
+
+/// An inconsistency found in an assignment by
+/// [`validate_assignments`](crate::parse::validate_assignments) or
+/// [`format_warnings`](crate::parse::format_warnings).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AssignmentWarning {
+    /// Fingerprint of the bridge whose assignment is inconsistent.
+    pub fingerprint: String,
+    /// Publication time of the file containing the assignment, in milliseconds since the epoch.
+    pub published_millis: i64,
+    /// The rule the assignment breaks.
+    pub rule: ValidationRule,
+}
+
+/// Rules checked by [`validate_assignments`](crate::parse::validate_assignments) and, for malformed
+/// values, [`format_warnings`](crate::parse::format_warnings).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationRule {
+    /// A `transport` is given without the `ip` version it is reachable over.
+    TransportWithoutIp,
+    /// The `ip` value is not a comma-separated list of IP versions `4` and `6`.
+    UnknownIpVersion,
+    /// An `ip` is given for the `unallocated` method, which never hands bridges out.
+    IpOnUnallocated,
+    /// The fingerprint is not a 40-character hex string.
+    MalformedFingerprint,
+    /// A `ratio` is given that does not parse as a number.
+    UnparsableRatio,
+    /// A numeric `bandwidth` is given with a unit that is not recognized.
+    UnrecognizedBandwidthUnit,
+}
+
+impl std::fmt::Display for ValidationRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ValidationRule::TransportWithoutIp => "transport given without ip",
+            ValidationRule::UnknownIpVersion => "ip is not a list of IP versions 4 and 6",
+            ValidationRule::IpOnUnallocated => "ip given for the unallocated distribution method",
+            ValidationRule::MalformedFingerprint => "fingerprint is not 40 hex characters",
+            ValidationRule::UnparsableRatio => "ratio is not a number",
+            ValidationRule::UnrecognizedBandwidthUnit => "bandwidth has an unrecognized unit",
+        })
+    }
+}
@@ -0,0 +1,200 @@
+use super::types::{AssignmentParser, AssignmentWarning, BridgeAssignment, ParsedBridgePoolAssignment, ValidationRule};
+
+/// Distribution method of bridges that BridgeDB keeps back instead of handing out.
+const UNALLOCATED_METHOD: &str = "unallocated";
+
+/// Checks parsed assignments for combinations of fields that should not occur together.
+///
+/// Such inconsistencies usually point at a parser bug or at a change in the upstream format, so they
+/// are reported rather than rejected. The rules are those of [`ValidationRule`]:
+///
+/// - a `transport` requires an `ip` version;
+/// - an `ip` must be a comma-separated list of `4` and `6`;
+/// - the `unallocated` method must not carry an `ip`.
+///
+/// # Arguments
+///
+/// * `parsed` - The parsed bridge pool files.
+/// * `parser` - The parser turning assignment strings into fields.
+///
+/// # Returns
+///
+/// One warning per broken rule, in file and fingerprint order.
+pub fn validate_assignments(
+    parsed: &[ParsedBridgePoolAssignment],
+    parser: &dyn AssignmentParser,
+) -> Vec<AssignmentWarning> {
+    let mut warnings = Vec::new();
+    for file in parsed {
+        for (fingerprint, assignment_str) in &file.entries {
+            for rule in validate_assignment(&parser.parse(assignment_str)) {
+                warnings.push(AssignmentWarning {
+                    fingerprint: fingerprint.clone(),
+                    published_millis: file.published_millis,
+                    rule,
+                });
+            }
+        }
+    }
+    warnings
+}
+
+/// Checks parsed assignments for values the parser could only skip.
+///
+/// Parsing keeps going when a value is malformed, dropping the value (and logging it) instead of
+/// failing the file. This reports each such value, so callers can refuse data that did not parse
+/// cleanly:
+///
+/// - a fingerprint must be 40 hex characters;
+/// - a `ratio` must be a number;
+/// - a numeric `bandwidth` must have a recognized unit.
+///
+/// # Arguments
+///
+/// * `parsed` - The parsed bridge pool files.
+/// * `parser` - The parser turning assignment strings into fields.
+///
+/// # Returns
+///
+/// One warning per malformed value, in file and fingerprint order.
+pub fn format_warnings(
+    parsed: &[ParsedBridgePoolAssignment],
+    parser: &dyn AssignmentParser,
+) -> Vec<AssignmentWarning> {
+    let mut warnings = Vec::new();
+    for file in parsed {
+        for (fingerprint, assignment_str) in &file.entries {
+            let mut broken = Vec::new();
+            if fingerprint.len() != 40 || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+                broken.push(ValidationRule::MalformedFingerprint);
+            }
+            broken.extend(check_values(assignment_str, &parser.parse(assignment_str)));
+            for rule in broken {
+                warnings.push(AssignmentWarning {
+                    fingerprint: fingerprint.clone(),
+                    published_millis: file.published_millis,
+                    rule,
+                });
+            }
+        }
+    }
+    warnings
+}
+
+/// Returns the format rules broken by values present in `assignment_str` but dropped while parsing.
+fn check_values(assignment_str: &str, assignment: &BridgeAssignment) -> Vec<ValidationRule> {
+    let mut broken = Vec::new();
+    let has_ratio = assignment_str.split_whitespace().any(|part| part.starts_with("ratio="));
+    if has_ratio && assignment.ratio.is_none() {
+        broken.push(ValidationRule::UnparsableRatio);
+    }
+    let numeric_bandwidth = assignment
+        .bandwidth
+        .as_deref()
+        .is_some_and(|bandwidth| bandwidth.starts_with(|c: char| c.is_ascii_digit()));
+    if numeric_bandwidth && assignment.bandwidth_bytes.is_none() {
+        broken.push(ValidationRule::UnrecognizedBandwidthUnit);
+    }
+    broken
+}
+
+/// Returns the rules a single assignment breaks.
+fn validate_assignment(assignment: &BridgeAssignment) -> Vec<ValidationRule> {
+    let mut broken = Vec::new();
+    if assignment.transport.is_some() && assignment.ip.is_none() {
+        broken.push(ValidationRule::TransportWithoutIp);
+    }
+    if let Some(ip) = &assignment.ip {
+        if !ip.split(',').all(|version| version == "4" || version == "6") {
+            broken.push(ValidationRule::UnknownIpVersion);
+        }
+        if assignment.distribution_method == UNALLOCATED_METHOD {
+            broken.push(ValidationRule::IpOnUnallocated);
+        }
+    }
+    broken
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{parse_assignment, DefaultAssignmentParser};
+    use crate::utils::Digest;
+    use std::collections::BTreeMap;
+
+    fn parsed_file(entries: &[(&str, &str)]) -> ParsedBridgePoolAssignment {
+        ParsedBridgePoolAssignment {
+            published_millis: 1_649_464_177_000,
+            entries: entries
+                .iter()
+                .map(|(fingerprint, assignment)| (fingerprint.to_string(), assignment.to_string()))
+                .collect(),
+            file_digest: Digest::parse(&"0".repeat(64)).unwrap(),
+            raw_content: None,
+            assignment_digests: BTreeMap::new(),
+            header: None,
+            freshness: Default::default(),
+            last_modified_millis: 0,
+            duplicate_fingerprints: Vec::new(),
+        }
+    }
+
+    /// Tests that a transport without an ip is flagged with the bridge it belongs to.
+    #[test]
+    fn test_transport_without_ip() {
+        let file = parsed_file(&[("005fd4d7decbb250055b861579e6fdc79ad17bee", "email transport=obfs4")]);
+
+        let warnings = validate_assignments(&[file], &DefaultAssignmentParser);
+
+        assert_eq!(
+            warnings,
+            vec![AssignmentWarning {
+                fingerprint: "005fd4d7decbb250055b861579e6fdc79ad17bee".to_string(),
+                published_millis: 1_649_464_177_000,
+                rule: ValidationRule::TransportWithoutIp,
+            }]
+        );
+    }
+
+    /// Tests that consistent assignments pass and each remaining rule is detected.
+    #[test]
+    fn test_validate_assignment_rules() {
+        assert!(validate_assignment(&parse_assignment("https ip=4 transport=obfs4 distributed=true")).is_empty());
+        assert!(validate_assignment(&parse_assignment("moat ip=4,6 transport=obfs4")).is_empty());
+        assert!(validate_assignment(&parse_assignment("unallocated")).is_empty());
+        assert_eq!(
+            validate_assignment(&parse_assignment("https ip=5 transport=obfs4")),
+            vec![ValidationRule::UnknownIpVersion]
+        );
+        assert_eq!(
+            validate_assignment(&parse_assignment("unallocated ip=4")),
+            vec![ValidationRule::IpOnUnallocated]
+        );
+    }
+
+    /// Tests that malformed fingerprints, ratios and bandwidth units are reported, and well-formed
+    /// values are not.
+    #[test]
+    fn test_format_warnings() {
+        let file = parsed_file(&[
+            ("005fd4d7decbb250055b861579e6fdc79ad17bee", "email ratio=0.5 bandwidth=1024KB"),
+            ("105fd4d7decbb250055b861579e6fdc79ad17bee", "email ratio=half bandwidth=sufficient"),
+            ("205fd4d7decbb250055b861579e6fdc79ad17bee", "email bandwidth=10parsecs"),
+            ("not-a-fingerprint", "email"),
+        ]);
+
+        let rules: Vec<(String, ValidationRule)> = format_warnings(&[file], &DefaultAssignmentParser)
+            .into_iter()
+            .map(|warning| (warning.fingerprint, warning.rule))
+            .collect();
+
+        assert_eq!(
+            rules,
+            vec![
+                ("105fd4d7decbb250055b861579e6fdc79ad17bee".to_string(), ValidationRule::UnparsableRatio),
+                ("205fd4d7decbb250055b861579e6fdc79ad17bee".to_string(), ValidationRule::UnrecognizedBandwidthUnit),
+                ("not-a-fingerprint".to_string(), ValidationRule::MalformedFingerprint),
+            ]
+        );
+    }
+}
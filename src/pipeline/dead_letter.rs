@@ -0,0 +1,64 @@
+use super::types::{DeadLetterEntry, DeadLetterStage};
+use crate::fetch::FetchFailure;
+use crate::parse::ParseFailure;
+use anyhow::{Context, Result as AnyhowResult};
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+impl From<&FetchFailure> for DeadLetterEntry {
+    fn from(failure: &FetchFailure) -> Self {
+        Self {
+            stage: DeadLetterStage::Fetch,
+            path: failure.path.clone(),
+            digest: None,
+            line: None,
+            error: failure.error.clone(),
+        }
+    }
+}
+
+impl From<ParseFailure> for DeadLetterEntry {
+    fn from(failure: ParseFailure) -> Self {
+        Self {
+            stage: DeadLetterStage::Parse,
+            path: failure.path,
+            digest: Some(failure.file_digest),
+            line: failure.line,
+            error: failure.error,
+        }
+    }
+}
+
+/// Appends `entries` to the dead-letter file at `path`, one JSON object per line.
+///
+/// The file is created if missing and never truncated, so the entries of consecutive runs accumulate
+/// until an operator has reprocessed and removed them.
+///
+/// # Arguments
+///
+/// * `path` - Path of the dead-letter file.
+/// * `entries` - The entries to append; nothing is written (and no file created) if empty.
+///
+/// # Returns
+///
+/// * `Ok(())` - All entries were written and flushed.
+/// * `Err(anyhow::Error)` - The file could not be opened or written.
+pub fn append_dead_letters(path: &Path, entries: &[DeadLetterEntry]) -> AnyhowResult<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open dead-letter file {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    for entry in entries {
+        serde_json::to_writer(&mut writer, entry).context("Failed to serialize dead-letter entry")?;
+        writer.write_all(b"\n")?;
+    }
+    writer
+        .flush()
+        .with_context(|| format!("Failed to write dead-letter file {}", path.display()))
+}
@@ -0,0 +1,26 @@
+//! # Fetch, Parse, and Export Pipeline
+//!
+//! This module ties the `fetch`, `parse`, and `export` modules together into a single run: files are
+//! fetched from CollecTor, parsed into `ParsedBridgePoolAssignment`s, and handed to an `Exporter`.
+//!
+//! ## Usage
+//!
+//! Build a [`PipelineConfig`] and call [`run_pipeline`] with an `HttpFetcher` and the `Exporter` of
+//! the selected backend.
+//!
+//! ## Submodules
+//!
+//! - **dead_letter**: Appends failed files and lines to a dead-letter file.
+//! - **progress**: Reports the progress of each stage as live bars (`progress` feature) or log lines.
+//! - **runner**: Contains the pipeline logic.
+//! - **types**: Defines the pipeline configuration and dead-letter entries.
+
+mod dead_letter;
+mod progress;
+mod runner;
+mod types;
+
+pub use dead_letter::append_dead_letters;
+pub use progress::{LogProgress, ProgressDisplay, ProgressObserver, Stage};
+pub use runner::run_pipeline;
+pub use types::{default_parse_concurrency, DeadLetterEntry, DeadLetterStage, PipelineConfig};
@@ -0,0 +1,243 @@
+use log::info;
+use std::fmt::Debug;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A stage of a pipeline run, as reported to a [`ProgressObserver`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Fetching files from CollecTor.
+    Fetch,
+    /// Parsing the fetched files.
+    Parse,
+    /// Exporting the parsed files.
+    Export,
+}
+
+impl Stage {
+    /// All stages, in the order they run.
+    pub const ALL: [Stage; 3] = [Stage::Fetch, Stage::Parse, Stage::Export];
+
+    /// Returns the lowercase name of the stage (e.g., "fetch").
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Fetch => "fetch",
+            Stage::Parse => "parse",
+            Stage::Export => "export",
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Stage::Fetch => 0,
+            Stage::Parse => 1,
+            Stage::Export => 2,
+        }
+    }
+}
+
+/// Receives the progress of a pipeline run, counted in files.
+///
+/// Set on [`PipelineConfig::progress`](crate::pipeline::PipelineConfig::progress). Fetch progress is
+/// reported as files complete, parse progress after each file, and export progress once the
+/// exporter's transaction is done, since an export is all-or-nothing (or, with a dead-letter file,
+/// after each file).
+pub trait ProgressObserver: Debug + Send + Sync {
+    /// Called when `done` of `total` files of `stage` have been processed.
+    fn update(&self, stage: Stage, done: u64, total: u64);
+
+    /// Called once `stage` has completed successfully.
+    fn finish(&self, stage: Stage);
+}
+
+/// How progress is displayed, as chosen by [`ProgressDisplay::detect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressDisplay {
+    /// Live-updating bars, one per stage (`progress` feature only).
+    Bars,
+    /// Plain log lines, which stay readable when output is piped or captured (e.g. in CI).
+    LogLines,
+}
+
+impl ProgressDisplay {
+    /// Chooses the display for the current process: bars if stderr, where they are drawn, is a
+    /// terminal and the `progress` feature is enabled, log lines otherwise.
+    pub fn detect() -> Self {
+        Self::for_terminal(std::io::stderr().is_terminal())
+    }
+
+    /// Chooses the display for output that is or is not a terminal.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_terminal` - Whether the output is an interactive terminal.
+    ///
+    /// # Returns
+    ///
+    /// [`ProgressDisplay::Bars`] for a terminal if the `progress` feature is enabled, otherwise
+    /// [`ProgressDisplay::LogLines`].
+    pub fn for_terminal(is_terminal: bool) -> Self {
+        if is_terminal && cfg!(feature = "progress") {
+            ProgressDisplay::Bars
+        } else {
+            ProgressDisplay::LogLines
+        }
+    }
+
+    /// Creates an observer drawing progress this way.
+    pub fn observer(self) -> Arc<dyn ProgressObserver> {
+        self.observer_with_log_writer().0
+    }
+
+    /// Creates an observer drawing progress this way, and a writer for the process's log output
+    /// (e.g. an `env_logger` pipe target) that does not garble it.
+    ///
+    /// # Returns
+    ///
+    /// The observer, and a writer to stderr. With bars, the writer clears them while a log line is
+    /// written and redraws them below it.
+    pub fn observer_with_log_writer(self) -> (Arc<dyn ProgressObserver>, Box<dyn Write + Send>) {
+        match self {
+            #[cfg(feature = "progress")]
+            ProgressDisplay::Bars => {
+                let progress = bars::BarProgress::new();
+                let log_writer = Box::new(progress.log_writer());
+                (Arc::new(progress), log_writer)
+            }
+            _ => (Arc::new(LogProgress::default()), Box::new(std::io::stderr())),
+        }
+    }
+}
+
+/// Logs the progress of each stage in steps of 10%, for output that is not a terminal.
+#[derive(Debug, Default)]
+pub struct LogProgress {
+    /// Last logged tenth of each stage's progress, plus one (0 if nothing was logged yet).
+    logged_tenths: [AtomicU64; 3],
+}
+
+impl ProgressObserver for LogProgress {
+    fn update(&self, stage: Stage, done: u64, total: u64) {
+        if total == 0 {
+            return;
+        }
+        let tenth = done.min(total) * 10 / total + 1;
+        if self.logged_tenths[stage.index()].fetch_max(tenth, Ordering::Relaxed) < tenth {
+            info!("Progress: {} {}/{} file(s)", stage.as_str(), done, total);
+        }
+    }
+
+    fn finish(&self, stage: Stage) {
+        info!("Progress: {} done", stage.as_str());
+    }
+}
+
+#[cfg(feature = "progress")]
+mod bars {
+    use super::{ProgressObserver, Stage};
+    use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+    use std::io::Write;
+
+    /// Draws one live-updating bar per stage with `indicatif`, on stderr.
+    #[derive(Debug)]
+    pub(super) struct BarProgress {
+        multi: MultiProgress,
+        bars: Vec<ProgressBar>,
+    }
+
+    impl BarProgress {
+        pub(super) fn new() -> Self {
+            let multi = MultiProgress::new();
+            let style = ProgressStyle::with_template("{prefix:>6} [{bar:40}] {pos}/{len} files {msg}")
+                .expect("progress template is valid")
+                .progress_chars("=> ");
+            let bars = Stage::ALL
+                .iter()
+                .map(|stage| {
+                    let bar = multi.add(ProgressBar::new(0));
+                    bar.set_style(style.clone());
+                    bar.set_prefix(stage.as_str());
+                    bar
+                })
+                .collect();
+            Self { multi, bars }
+        }
+
+        /// Returns a writer to stderr that suspends the bars while writing.
+        pub(super) fn log_writer(&self) -> SuspendingWriter {
+            SuspendingWriter {
+                multi: self.multi.clone(),
+            }
+        }
+    }
+
+    /// Writes to stderr with the bars cleared, so each write appears above the redrawn bars.
+    pub(super) struct SuspendingWriter {
+        multi: MultiProgress,
+    }
+
+    impl Write for SuspendingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.multi.suspend(|| {
+                let mut stderr = std::io::stderr().lock();
+                stderr.write_all(buf)?;
+                stderr.flush()
+            })?;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl ProgressObserver for BarProgress {
+        fn update(&self, stage: Stage, done: u64, total: u64) {
+            let bar = &self.bars[stage.index()];
+            bar.set_length(total);
+            bar.set_position(done);
+        }
+
+        fn finish(&self, stage: Stage) {
+            self.bars[stage.index()].finish_with_message("done");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that output that is not a terminal always falls back to log lines, and that a terminal
+    /// only gets bars when they are compiled in.
+    #[test]
+    fn test_progress_display_falls_back_without_terminal() {
+        assert_eq!(ProgressDisplay::for_terminal(false), ProgressDisplay::LogLines);
+        let expected = if cfg!(feature = "progress") {
+            ProgressDisplay::Bars
+        } else {
+            ProgressDisplay::LogLines
+        };
+        assert_eq!(ProgressDisplay::for_terminal(true), expected);
+        if !std::io::stderr().is_terminal() {
+            assert_eq!(ProgressDisplay::detect(), ProgressDisplay::LogLines);
+        }
+    }
+
+    /// Tests that log lines are only written when a stage crosses a tenth of its files.
+    #[test]
+    fn test_log_progress_logs_in_tenths() {
+        let progress = LogProgress::default();
+        let logged = |stage: Stage| progress.logged_tenths[stage.index()].load(Ordering::Relaxed);
+
+        progress.update(Stage::Parse, 0, 200);
+        progress.update(Stage::Parse, 19, 200);
+        assert_eq!(logged(Stage::Parse), 1);
+        progress.update(Stage::Parse, 20, 200);
+        assert_eq!(logged(Stage::Parse), 2);
+        progress.update(Stage::Parse, 200, 200);
+        assert_eq!(logged(Stage::Parse), 11);
+        assert_eq!(logged(Stage::Fetch), 0);
+    }
+}
@@ -0,0 +1,876 @@
+use super::dead_letter::append_dead_letters;
+use super::progress::{ProgressObserver, Stage};
+use super::types::{DeadLetterEntry, DeadLetterStage, PipelineConfig};
+use crate::export::{ExportSummary, Exporter};
+use crate::fetch::{Collector, HttpFetcher};
+use crate::parse::{
+    format_warnings, parse_bridge_pool_files_best_effort, parse_bridge_pool_files_with_strategy, validate_assignments,
+    ParsedBridgePoolAssignment,
+};
+use crate::stats::{RunStats, StatsServer};
+use crate::utils::{datetime_from_millis, DigestCollisionCheck};
+use anyhow::{Context, Result as AnyhowResult};
+use futures::StreamExt;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often fetch progress is passed to the progress observer while files are being fetched.
+const FETCH_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs the fetch, parse, and export stages.
+///
+/// If `config.stats_addr` is set, a [`StatsServer`] exposing the live fetch statistics and the export
+/// summary is started before fetching and shut down once the run finishes, whether it succeeded or not.
+///
+/// # Arguments
+///
+/// * `config` - The pipeline configuration.
+/// * `fetcher` - The HTTP fetcher used to reach CollecTor.
+/// * `exporter` - The backend receiving the parsed assignments.
+///
+/// # Returns
+///
+/// * `Ok(ExportSummary)` - Summary of the rows written by the exporter.
+/// * `Err(anyhow::Error)` - An error if any stage fails.
+pub async fn run_pipeline(
+    config: &PipelineConfig,
+    fetcher: Arc<dyn HttpFetcher>,
+    exporter: &mut dyn Exporter,
+) -> AnyhowResult<ExportSummary> {
+    let collector = Arc::new(Collector::new(fetcher, &config.fetch));
+    let export_summary = Arc::new(Mutex::new(None));
+
+    let stats_server = match &config.stats_addr {
+        Some(addr) => {
+            let collector = Arc::clone(&collector);
+            let export_summary = Arc::clone(&export_summary);
+            let source = Arc::new(move || RunStats {
+                fetch: collector.stats(),
+                export: export_summary
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .clone(),
+            });
+            Some(StatsServer::start(addr, source).await?)
+        }
+        None => None,
+    };
+
+    let result = run_stages(config, &collector, exporter, &export_summary).await;
+
+    if let Some(server) = stats_server {
+        server.shutdown().await;
+    }
+    result
+}
+
+/// Runs the stages of the pipeline and records the export summary for the stats endpoint.
+async fn run_stages(
+    config: &PipelineConfig,
+    collector: &Collector,
+    exporter: &mut dyn Exporter,
+    export_summary: &Mutex<Option<ExportSummary>>,
+) -> AnyhowResult<ExportSummary> {
+    info!("Starting to fetch the files");
+    let dirs: Vec<&str> = config.dirs.iter().map(|s| s.as_str()).collect();
+    let fetch = collector.fetch_bridge_pool_files(&config.base_url, &dirs, config.min_last_modified);
+    let fetched = match &config.progress {
+        Some(progress) => with_fetch_progress(collector, progress.as_ref(), fetch).await,
+        None => fetch.await,
+    };
+    if let Some(dead_letter) = &config.dead_letter {
+        let entries: Vec<DeadLetterEntry> = collector.stats().failed_files.iter().map(Into::into).collect();
+        append_dead_letters(dead_letter, &entries)?;
+    }
+    let contents = fetched?;
+    report_finished(config, Stage::Fetch);
+    info!("Fetched {} file(s)", contents.len());
+    if contents.is_empty() {
+        info!("Nothing to do: no files newer than the last run");
+        let summary = ExportSummary::default();
+        *export_summary.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(summary.clone());
+        return Ok(summary);
+    }
+
+    info!("Starting to parse the files");
+    let mut warning_count = 0;
+    let best_effort = config.dead_letter.is_some() || config.warnings_as_errors;
+    let file_count = contents.len() as u64;
+    let mut parsed_data = Vec::new();
+    // Path of the file each parsed document came from, for dead-letter entries of the export
+    let mut parsed_paths = Vec::new();
+    let mut failures = Vec::new();
+    let mut collision_check = config.check_digest_collisions.then(DigestCollisionCheck::new);
+    // The collision check needs the bytes each document's digest was computed from
+    let keep_raw_content = config.keep_raw_content || collision_check.is_some();
+    let strategy = config.assignment_digest_strategy;
+    // File by file on blocking threads, in order, so progress can be reported in between
+    let mut parsing = futures::stream::iter(contents)
+        .map(|file| {
+            let path = file.path.clone();
+            let task = tokio::task::spawn_blocking(move || {
+                if best_effort {
+                    Ok(parse_bridge_pool_files_best_effort(vec![file], keep_raw_content, strategy))
+                } else {
+                    parse_bridge_pool_files_with_strategy(vec![file], keep_raw_content, strategy)
+                        .map(|parsed| (parsed, Vec::new()))
+                }
+            });
+            async move { (path, task.await) }
+        })
+        .buffered(config.parse_concurrency.max(1));
+    let mut parsed_count = 0;
+    while let Some((path, result)) = parsing.next().await {
+        let (mut file_parsed, file_failures) = result.context("Parsing task panicked")??;
+        failures.extend(file_failures);
+        if let Some(check) = &mut collision_check {
+            for parsed in &mut file_parsed {
+                if let Some(raw_content) = &parsed.raw_content {
+                    check.record(&parsed.file_digest, raw_content, &path)?;
+                }
+                if !config.keep_raw_content {
+                    parsed.raw_content = None;
+                }
+            }
+        }
+        parsed_paths.extend(std::iter::repeat_n(path, file_parsed.len()));
+        parsed_data.extend(file_parsed);
+        parsed_count += 1;
+        report_progress(config, Stage::Parse, parsed_count, file_count);
+    }
+    if let Some(check) = &collision_check {
+        info!("No digest collisions among {} distinct document(s)", check.len());
+    }
+    if best_effort {
+        if let Some(dead_letter) = &config.dead_letter {
+            if !failures.is_empty() {
+                warn!("Skipped {} unparsable file(s) or line(s)", failures.len());
+            }
+            let entries: Vec<DeadLetterEntry> = failures.iter().cloned().map(Into::into).collect();
+            append_dead_letters(dead_letter, &entries)?;
+        } else if let Some(failure) = failures.iter().find(|failure| failure.line.is_none()) {
+            anyhow::bail!("Failed to parse file {}: {}", failure.path, failure.error);
+        }
+        if config.warnings_as_errors {
+            for failure in &failures {
+                match &failure.line {
+                    Some(line) => warn!("Skipped line {:?} of {}: {}", line, failure.path, failure.error),
+                    None => warn!("Skipped file {}: {}", failure.path, failure.error),
+                }
+            }
+            warning_count += failures.len();
+        }
+    }
+    report_finished(config, Stage::Parse);
+    info!("Parsed {} bridge pool assignments", parsed_data.len());
+    for parsed in parsed_data.iter().filter(|parsed| !parsed.duplicate_fingerprints.is_empty()) {
+        warn!(
+            "File {} published at {} lists {} fingerprint(s) more than once; only the last line of each is kept",
+            parsed.file_digest,
+            parsed.published_millis,
+            parsed.duplicate_fingerprints.len()
+        );
+    }
+
+    if config.validate_assignments {
+        let warnings = validate_assignments(&parsed_data, config.assignment_parser.as_ref());
+        for warning in &warnings {
+            warn!(
+                "Inconsistent assignment of {} published at {}: {}",
+                warning.fingerprint, warning.published_millis, warning.rule
+            );
+        }
+        info!("Validation found {} inconsistent assignment(s)", warnings.len());
+        warning_count += warnings.len();
+    }
+
+    if config.warnings_as_errors {
+        let warnings = format_warnings(&parsed_data, config.assignment_parser.as_ref());
+        for warning in &warnings {
+            warn!(
+                "Malformed assignment of {} published at {}: {}",
+                warning.fingerprint, warning.published_millis, warning.rule
+            );
+        }
+        warning_count += warnings.len();
+        if warning_count > 0 {
+            anyhow::bail!("Parsing produced {} warning(s) and warnings are treated as errors", warning_count);
+        }
+    }
+
+    info!("Starting export");
+    let export_count = parsed_data.len() as u64;
+    report_progress(config, Stage::Export, 0, export_count);
+    let summary = match &config.dead_letter {
+        Some(dead_letter) => export_best_effort(config, exporter, parsed_data, parsed_paths, dead_letter).await?,
+        None => {
+            let summary = exporter.export(parsed_data).await.context("Failed to export")?;
+            report_progress(config, Stage::Export, export_count, export_count);
+            summary
+        }
+    };
+    report_finished(config, Stage::Export);
+    info!(
+        "Exported {} file(s) and {} assignment(s)",
+        summary.files_exported, summary.assignments_exported
+    );
+    for empty_file in &summary.empty_files {
+        let published = datetime_from_millis(empty_file.published_millis)
+            .map_or_else(|_| format!("{} ms", empty_file.published_millis), |published| published.to_string());
+        warn!(
+            "Exported file {} published {} has a header but no bridge entries",
+            empty_file.digest, published
+        );
+    }
+
+    *export_summary.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(summary.clone());
+    Ok(summary)
+}
+
+/// Exports the parsed documents one at a time, appending those that fail to the dead-letter file
+/// instead of failing the run.
+///
+/// A document that fails to export is retried one assignment at a time, so that only the failing rows
+/// are recorded, each with its raw line. If none of its rows can be exported, or it has none, the
+/// document is recorded as a whole with the error of the first attempt.
+///
+/// # Arguments
+///
+/// * `config` - The pipeline configuration, for progress reporting.
+/// * `exporter` - The backend receiving the parsed assignments.
+/// * `parsed_data` - The parsed documents.
+/// * `paths` - Path of the file each document was parsed from.
+/// * `dead_letter` - Path of the dead-letter file.
+///
+/// # Returns
+///
+/// * `Ok(ExportSummary)` - Summary of the rows written for all documents that could be exported.
+/// * `Err(anyhow::Error)` - No document could be exported at all, or the dead-letter file could not be
+///   written.
+async fn export_best_effort(
+    config: &PipelineConfig,
+    exporter: &mut dyn Exporter,
+    parsed_data: Vec<ParsedBridgePoolAssignment>,
+    paths: Vec<String>,
+    dead_letter: &Path,
+) -> AnyhowResult<ExportSummary> {
+    let total = parsed_data.len() as u64;
+    let mut summary = ExportSummary::default();
+    let mut failures = Vec::new();
+    let mut failed_documents = 0;
+    for (index, (parsed, path)) in parsed_data.into_iter().zip(paths).enumerate() {
+        let failure = |line: Option<String>, error: &anyhow::Error| DeadLetterEntry {
+            stage: DeadLetterStage::Export,
+            path: path.clone(),
+            digest: Some(parsed.file_digest.to_string()),
+            line,
+            error: format!("{:#}", error),
+        };
+        match exporter.export(vec![parsed.clone()]).await {
+            Ok(exported) => add_to_summary(&mut summary, exported),
+            Err(error) => {
+                let mut rows_exported = Vec::new();
+                let mut row_failures = Vec::new();
+                for (fingerprint, assignment_str) in &parsed.entries {
+                    let mut row = parsed.clone();
+                    row.entries = BTreeMap::from([(fingerprint.clone(), assignment_str.clone())]);
+                    match exporter.export(vec![row]).await {
+                        Ok(exported) => rows_exported.push(exported),
+                        Err(e) => row_failures.push(failure(Some(format!("{} {}", fingerprint, assignment_str)), &e)),
+                    }
+                }
+                if rows_exported.is_empty() {
+                    warn!("Failed to export {}: {:#}", path, error);
+                    failures.push(failure(None, &error));
+                    failed_documents += 1;
+                } else {
+                    warn!(
+                        "Failed to export {} of {} assignment(s) of {}",
+                        row_failures.len(),
+                        parsed.entries.len(),
+                        path
+                    );
+                    // Backends without unique file keys count the file once per row; it is one file
+                    summary.files_exported += rows_exported.iter().map(|row| row.files_exported).max().unwrap_or(0);
+                    summary.assignments_exported += rows_exported.iter().map(|row| row.assignments_exported).sum::<usize>();
+                    failures.extend(row_failures);
+                }
+            }
+        }
+        report_progress(config, Stage::Export, index as u64 + 1, total);
+    }
+    append_dead_letters(dead_letter, &failures)?;
+    if total > 0 && failed_documents as u64 == total {
+        anyhow::bail!("Failed to export any of {} document(s); see the dead-letter file", total);
+    }
+    Ok(summary)
+}
+
+/// Adds the rows written by one export to `summary`.
+fn add_to_summary(summary: &mut ExportSummary, exported: ExportSummary) {
+    summary.files_exported += exported.files_exported;
+    summary.assignments_exported += exported.assignments_exported;
+    summary.empty_files.extend(exported.empty_files);
+}
+
+/// Awaits `fetch`, passing the collector's fetch progress to `progress` every
+/// [`FETCH_PROGRESS_INTERVAL`] and once more when it completes.
+async fn with_fetch_progress<T>(
+    collector: &Collector,
+    progress: &dyn ProgressObserver,
+    fetch: impl Future<Output = T>,
+) -> T {
+    let report = || {
+        let stats = collector.stats();
+        progress.update(
+            Stage::Fetch,
+            (stats.files_fetched + stats.files_failed) as u64,
+            stats.files_listed as u64,
+        );
+    };
+    tokio::pin!(fetch);
+    let mut interval = tokio::time::interval(FETCH_PROGRESS_INTERVAL);
+    loop {
+        tokio::select! {
+            result = &mut fetch => {
+                report();
+                return result;
+            }
+            _ = interval.tick() => report(),
+        }
+    }
+}
+
+/// Passes the progress of `stage` to the configured progress observer, if any.
+fn report_progress(config: &PipelineConfig, stage: Stage, done: u64, total: u64) {
+    if let Some(progress) = &config.progress {
+        progress.update(stage, done, total);
+    }
+}
+
+/// Tells the configured progress observer, if any, that `stage` has completed.
+fn report_finished(config: &PipelineConfig, stage: Stage) {
+    if let Some(progress) = &config.progress {
+        progress.finish(stage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::MemoryExporter;
+    use crate::fetch::HttpResponse;
+    use crate::parse::{AssignmentParser, BridgeAssignment, DefaultAssignmentParser};
+    use futures::future::BoxFuture;
+    use tokio::sync::Notify;
+
+    const INDEX: &str = r#"{"directories": [{"path": "recent", "directories": [{
+        "path": "bridge-pool-assignments",
+        "files": [{"path": "2022-04-09-00-29-37", "last_modified": "2022-04-09 00:30"}]
+    }]}]}"#;
+
+    const FILE: &str = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+        005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4\n";
+
+    /// Serves a fixed index immediately, but holds back the file until `release` is notified.
+    struct GatedFetcher {
+        release: Arc<Notify>,
+    }
+
+    impl HttpFetcher for GatedFetcher {
+        fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+            Box::pin(async move {
+                let body = if url.ends_with("index/index.json") {
+                    INDEX
+                } else {
+                    self.release.notified().await;
+                    FILE
+                };
+                Ok(HttpResponse {
+                    status: 200,
+                    last_modified: None,
+                    body: body.as_bytes().to_vec(),
+                })
+            })
+        }
+    }
+
+    /// Counts export calls; any call stands in for DDL or DML reaching the database.
+    #[derive(Default)]
+    struct CountingExporter {
+        calls: usize,
+    }
+
+    impl Exporter for CountingExporter {
+        fn export(
+            &mut self,
+            _parsed_assignments: Vec<crate::parse::ParsedBridgePoolAssignment>,
+        ) -> BoxFuture<'_, AnyhowResult<ExportSummary>> {
+            self.calls += 1;
+            Box::pin(async { Ok(ExportSummary::default()) })
+        }
+    }
+
+    /// Tests that a run with no files newer than `min_last_modified` never reaches the exporter.
+    #[tokio::test]
+    async fn test_nothing_new_skips_export() {
+        let fetcher = Arc::new(GatedFetcher {
+            release: Arc::new(Notify::new()),
+        });
+        let config = PipelineConfig {
+            base_url: "https://collector.example".to_string(),
+            // One millisecond after the only file in `INDEX`.
+            min_last_modified: 1_649_464_200_001,
+            ..Default::default()
+        };
+        let mut exporter = CountingExporter::default();
+
+        let summary = run_pipeline(&config, fetcher, &mut exporter).await.unwrap();
+
+        assert_eq!(summary, ExportSummary::default());
+        assert_eq!(exporter.calls, 0);
+    }
+
+    /// Serves an index of three files: one missing, one that is not a bridge pool assignment, and one
+    /// with a malformed line among valid ones.
+    struct FaultyFetcher;
+
+    impl HttpFetcher for FaultyFetcher {
+        fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+            Box::pin(async move {
+                let (status, body) = match url.rsplit('/').next().unwrap() {
+                    "index.json" => (200, r#"{"directories": [{"path": "recent", "files": [
+                        {"path": "missing", "last_modified": "2022-04-09 00:30"},
+                        {"path": "garbage", "last_modified": "2022-04-09 00:30"},
+                        {"path": "partial", "last_modified": "2022-04-09 00:30"}
+                    ]}]}"#),
+                    "garbage" => (200, "<html>error</html>\n"),
+                    "partial" => (200, "bridge-pool-assignment 2022-04-09 00:29:37\n\
+                        005fd4d7decbb250055b861579e6fdc79ad17bee email\n\
+                        malformed\n"),
+                    _ => (404, ""),
+                };
+                Ok(HttpResponse {
+                    status,
+                    last_modified: None,
+                    body: body.as_bytes().to_vec(),
+                })
+            })
+        }
+    }
+
+    /// Serves two files: one concatenating two documents, and one repeating the first document alone.
+    struct ConcatenatedFetcher;
+
+    const FIRST_DOCUMENT: &str = "bridge-pool-assignment 2022-04-09 00:29:37\n\
+        005fd4d7decbb250055b861579e6fdc79ad17bee email\n";
+    const SECOND_DOCUMENT: &str = "bridge-pool-assignment 2022-04-09 12:00:00\n\
+        005fd4d7decbb250055b861579e6fdc79ad17bee moat\n";
+
+    impl HttpFetcher for ConcatenatedFetcher {
+        fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+            Box::pin(async move {
+                let body = match url.rsplit('/').next().unwrap() {
+                    "index.json" => r#"{"directories": [{"path": "recent", "files": [
+                        {"path": "concatenated", "last_modified": "2022-04-09 12:30"},
+                        {"path": "single", "last_modified": "2022-04-09 12:30"}
+                    ]}]}"#
+                        .to_string(),
+                    "concatenated" => format!("{}{}", FIRST_DOCUMENT, SECOND_DOCUMENT),
+                    _ => FIRST_DOCUMENT.to_string(),
+                };
+                Ok(HttpResponse {
+                    status: 200,
+                    last_modified: None,
+                    body: body.into_bytes(),
+                })
+            })
+        }
+    }
+
+    /// Tests that the digest collision check runs on each document's digest and bytes: a document
+    /// repeated as a file of its own is no collision, and raw content is not kept for the exporter.
+    #[tokio::test]
+    async fn test_digest_collision_check_per_document() {
+        let config = PipelineConfig {
+            base_url: "https://collector.example".to_string(),
+            dirs: vec!["recent".to_string()],
+            check_digest_collisions: true,
+            ..Default::default()
+        };
+        let mut exporter = RawContentExporter::default();
+
+        let summary = run_pipeline(&config, Arc::new(ConcatenatedFetcher), &mut exporter).await.unwrap();
+
+        assert_eq!(summary.files_exported, 3);
+        assert_eq!(exporter.raw_contents, [false, false, false]);
+    }
+
+    /// Records whether each exported file kept its raw content.
+    #[derive(Debug, Default)]
+    struct RawContentExporter {
+        raw_contents: Vec<bool>,
+    }
+
+    impl Exporter for RawContentExporter {
+        fn export(
+            &mut self,
+            parsed: Vec<crate::parse::ParsedBridgePoolAssignment>,
+        ) -> BoxFuture<'_, AnyhowResult<ExportSummary>> {
+            self.raw_contents = parsed.iter().map(|parsed| parsed.raw_content.is_some()).collect();
+            let summary = ExportSummary {
+                files_exported: parsed.len(),
+                ..Default::default()
+            };
+            Box::pin(async move { Ok(summary) })
+        }
+    }
+
+    /// Tests that failed files and lines end up in the dead-letter file, accumulating across runs,
+    /// while the rest is exported.
+    #[tokio::test]
+    async fn test_failures_written_to_dead_letter_file() {
+        let dead_letter = std::env::temp_dir().join(format!("bpa_dead_letter_{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&dead_letter);
+        let config = PipelineConfig {
+            base_url: "https://collector.example".to_string(),
+            dirs: vec!["recent".to_string()],
+            dead_letter: Some(dead_letter.clone()),
+            ..Default::default()
+        };
+
+        for _ in 0..2 {
+            let mut exporter = MemoryExporter::new();
+            let summary = run_pipeline(&config, Arc::new(FaultyFetcher), &mut exporter).await.unwrap();
+            assert_eq!(summary.assignments_exported, 1);
+        }
+
+        let entries: Vec<serde_json::Value> = std::fs::read_to_string(&dead_letter)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        std::fs::remove_file(&dead_letter).unwrap();
+        let summaries: Vec<(&str, &str, Option<&str>)> = entries
+            .iter()
+            .map(|entry| (entry["stage"].as_str().unwrap(), entry["path"].as_str().unwrap(), entry["line"].as_str()))
+            .collect();
+        let run = [
+            ("fetch", "recent/missing", None),
+            ("parse", "recent/garbage", None),
+            ("parse", "recent/partial", Some("malformed")),
+        ];
+        assert_eq!(summaries, [run, run].concat());
+        assert!(entries[0]["error"].as_str().unwrap().contains("404"));
+        assert!(entries[1]["digest"].is_string());
+    }
+
+    /// Serves two files: `mixed`, listing a bridge the [`RejectingExporter`] rejects among one it
+    /// accepts, and `rejected`, published at a time the exporter rejects as a whole.
+    struct ExportFailureFetcher;
+
+    const REJECTED_FINGERPRINT: &str = "01ea4fb2da2086e71e7ca84c683fcadd2aa9036b";
+    /// 2022-04-09 12:00:00 UTC.
+    const REJECTED_PUBLISHED_MILLIS: i64 = 1_649_505_600_000;
+
+    impl HttpFetcher for ExportFailureFetcher {
+        fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+            Box::pin(async move {
+                let body = match url.rsplit('/').next().unwrap() {
+                    "index.json" => r#"{"directories": [{"path": "recent", "files": [
+                        {"path": "mixed", "last_modified": "2022-04-09 12:30"},
+                        {"path": "rejected", "last_modified": "2022-04-09 12:30"}
+                    ]}]}"#,
+                    "mixed" => "bridge-pool-assignment 2022-04-09 00:29:37\n\
+                        005fd4d7decbb250055b861579e6fdc79ad17bee email\n\
+                        01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https blocklist=cn\n",
+                    _ => "bridge-pool-assignment 2022-04-09 12:00:00\n\
+                        005fd4d7decbb250055b861579e6fdc79ad17bee moat\n",
+                };
+                Ok(HttpResponse {
+                    status: 200,
+                    last_modified: None,
+                    body: body.as_bytes().to_vec(),
+                })
+            })
+        }
+    }
+
+    /// A [`MemoryExporter`] failing, without writing anything, every export that holds an assignment
+    /// of [`REJECTED_FINGERPRINT`] or a file published at [`REJECTED_PUBLISHED_MILLIS`].
+    #[derive(Debug, Default)]
+    struct RejectingExporter {
+        inner: MemoryExporter,
+    }
+
+    impl Exporter for RejectingExporter {
+        fn export(
+            &mut self,
+            parsed: Vec<crate::parse::ParsedBridgePoolAssignment>,
+        ) -> BoxFuture<'_, AnyhowResult<ExportSummary>> {
+            if parsed.iter().any(|parsed| parsed.entries.contains_key(REJECTED_FINGERPRINT)) {
+                return Box::pin(async { Err(anyhow::anyhow!("value too long for the blocklist column")) });
+            }
+            if parsed.iter().any(|parsed| parsed.published_millis == REJECTED_PUBLISHED_MILLIS) {
+                return Box::pin(async { Err(anyhow::anyhow!("published time out of range")) });
+            }
+            self.inner.export(parsed)
+        }
+    }
+
+    /// Tests that with a dead-letter file, a row or file failing to export is recorded there while the
+    /// rest is exported, and that without one the export fails as a whole.
+    #[tokio::test]
+    async fn test_export_failures_written_to_dead_letter_file() {
+        let dead_letter = std::env::temp_dir().join(format!("bpa_export_dead_letter_{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&dead_letter);
+        let mut config = PipelineConfig {
+            base_url: "https://collector.example".to_string(),
+            dirs: vec!["recent".to_string()],
+            ..Default::default()
+        };
+        let mut exporter = RejectingExporter::default();
+        run_pipeline(&config, Arc::new(ExportFailureFetcher), &mut exporter).await.unwrap_err();
+        assert!(exporter.inner.files.is_empty());
+
+        config.dead_letter = Some(dead_letter.clone());
+        let summary = run_pipeline(&config, Arc::new(ExportFailureFetcher), &mut exporter).await.unwrap();
+
+        assert_eq!((summary.files_exported, summary.assignments_exported), (1, 1));
+        let exported: Vec<&str> = exporter.inner.assignments.values().map(|row| row.fingerprint.as_str()).collect();
+        assert_eq!(exported, ["005fd4d7decbb250055b861579e6fdc79ad17bee"]);
+        let entries: Vec<serde_json::Value> = std::fs::read_to_string(&dead_letter)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        std::fs::remove_file(&dead_letter).unwrap();
+        let summaries: Vec<(&str, &str, Option<&str>, &str)> = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry["stage"].as_str().unwrap(),
+                    entry["path"].as_str().unwrap(),
+                    entry["line"].as_str(),
+                    entry["error"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summaries,
+            [
+                (
+                    "export",
+                    "recent/mixed",
+                    Some("01ea4fb2da2086e71e7ca84c683fcadd2aa9036b https blocklist=cn"),
+                    "value too long for the blocklist column"
+                ),
+                ("export", "recent/rejected", None, "published time out of range"),
+            ]
+        );
+        let mixed_digest = exporter.inner.files.keys().next().unwrap().to_string();
+        assert_eq!(entries[0]["digest"].as_str(), Some(mixed_digest.as_str()));
+        assert!(entries[1]["digest"].is_string());
+    }
+
+    /// Serves an index of a single file whose only assignment has an unparsable ratio.
+    struct BadRatioFetcher;
+
+    impl HttpFetcher for BadRatioFetcher {
+        fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+            Box::pin(async move {
+                let body = if url.ends_with("index.json") {
+                    r#"{"directories": [{"path": "recent", "files": [
+                        {"path": "bad-ratio", "last_modified": "2022-04-09 00:30"}
+                    ]}]}"#
+                } else {
+                    "bridge-pool-assignment 2022-04-09 00:29:37\n\
+                        005fd4d7decbb250055b861579e6fdc79ad17bee email ratio=half\n"
+                };
+                Ok(HttpResponse {
+                    status: 200,
+                    last_modified: None,
+                    body: body.as_bytes().to_vec(),
+                })
+            })
+        }
+    }
+
+    /// Serves an index of a single file that is not a bridge pool assignment at all.
+    struct UnparsableFetcher;
+
+    impl HttpFetcher for UnparsableFetcher {
+        fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, AnyhowResult<HttpResponse>> {
+            Box::pin(async move {
+                let body = if url.ends_with("index.json") {
+                    r#"{"directories": [{"path": "recent", "files": [
+                        {"path": "garbage", "last_modified": "2022-04-09 00:30"}
+                    ]}]}"#
+                } else {
+                    "<html>error</html>\n"
+                };
+                Ok(HttpResponse {
+                    status: 200,
+                    last_modified: None,
+                    body: body.as_bytes().to_vec(),
+                })
+            })
+        }
+    }
+
+    /// Tests that a file producing a parse warning is exported by default but fails the run, without
+    /// exporting anything, under `warnings_as_errors`, and that a file skipped into the dead-letter file
+    /// counts as a warning too.
+    #[tokio::test]
+    async fn test_warnings_as_errors() {
+        let mut config = PipelineConfig {
+            base_url: "https://collector.example".to_string(),
+            dirs: vec!["recent".to_string()],
+            ..Default::default()
+        };
+        let mut exporter = CountingExporter::default();
+        run_pipeline(&config, Arc::new(BadRatioFetcher), &mut exporter).await.unwrap();
+        assert_eq!(exporter.calls, 1);
+
+        config.warnings_as_errors = true;
+        let mut exporter = CountingExporter::default();
+        let err = run_pipeline(&config, Arc::new(BadRatioFetcher), &mut exporter).await.unwrap_err();
+
+        assert!(err.to_string().contains("1 warning(s)"), "{:#}", err);
+        assert_eq!(exporter.calls, 0);
+
+        let dead_letter = std::env::temp_dir().join(format!("bpa_strict_dead_letter_{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&dead_letter);
+        config.dead_letter = Some(dead_letter.clone());
+        let mut exporter = CountingExporter::default();
+        let err = run_pipeline(&config, Arc::new(UnparsableFetcher), &mut exporter).await.unwrap_err();
+        let dead_letters = std::fs::read_to_string(&dead_letter).unwrap();
+        std::fs::remove_file(&dead_letter).unwrap();
+
+        assert!(err.to_string().contains("1 warning(s)"), "{:#}", err);
+        assert_eq!(exporter.calls, 0);
+        assert_eq!(dead_letters.lines().count(), 1);
+    }
+
+    /// Reads `ratio=half` as 0.5, on top of the default parser.
+    #[derive(Debug)]
+    struct WordRatioParser;
+
+    impl AssignmentParser for WordRatioParser {
+        fn parse(&self, assignment_str: &str) -> BridgeAssignment {
+            let mut assignment = DefaultAssignmentParser.parse(assignment_str);
+            if assignment_str.split_whitespace().any(|part| part == "ratio=half") {
+                assignment.ratio = Some(0.5);
+            }
+            assignment
+        }
+    }
+
+    /// Tests that the warning checks use the configured assignment parser rather than the default one.
+    #[tokio::test]
+    async fn test_warnings_use_configured_parser() {
+        let config = PipelineConfig {
+            base_url: "https://collector.example".to_string(),
+            dirs: vec!["recent".to_string()],
+            validate_assignments: true,
+            warnings_as_errors: true,
+            assignment_parser: Arc::new(WordRatioParser),
+            ..Default::default()
+        };
+        let mut exporter = CountingExporter::default();
+
+        run_pipeline(&config, Arc::new(BadRatioFetcher), &mut exporter).await.unwrap();
+
+        assert_eq!(exporter.calls, 1);
+    }
+
+    /// Records the progress reported by a run.
+    #[derive(Debug, Default)]
+    struct RecordingProgress {
+        updates: Mutex<Vec<(Stage, u64, u64)>>,
+        finished: Mutex<Vec<Stage>>,
+    }
+
+    impl ProgressObserver for RecordingProgress {
+        fn update(&self, stage: Stage, done: u64, total: u64) {
+            self.updates.lock().unwrap().push((stage, done, total));
+        }
+
+        fn finish(&self, stage: Stage) {
+            self.finished.lock().unwrap().push(stage);
+        }
+    }
+
+    /// Tests that every stage reports its files as done and finishes, in order.
+    #[tokio::test]
+    async fn test_progress_reported_per_stage() {
+        let progress = Arc::new(RecordingProgress::default());
+        let config = PipelineConfig {
+            base_url: "https://collector.example".to_string(),
+            dirs: vec!["recent".to_string()],
+            progress: Some(progress.clone()),
+            ..Default::default()
+        };
+        let mut exporter = MemoryExporter::new();
+
+        run_pipeline(&config, Arc::new(BadRatioFetcher), &mut exporter).await.unwrap();
+
+        let updates = progress.updates.lock().unwrap();
+        for stage in Stage::ALL {
+            let last = updates.iter().rev().find(|(updated, _, _)| *updated == stage);
+            assert_eq!(last, Some(&(stage, 1, 1)), "{:?}", stage);
+        }
+        assert_eq!(*progress.finished.lock().unwrap(), Stage::ALL);
+    }
+
+    /// Tests that `/stats` serves live statistics during a run and goes away once the run finishes.
+    #[tokio::test]
+    async fn test_stats_endpoint_serves_live_stats() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let release = Arc::new(Notify::new());
+        let fetcher = Arc::new(GatedFetcher {
+            release: Arc::clone(&release),
+        });
+        let config = PipelineConfig {
+            base_url: "https://collector.example".to_string(),
+            stats_addr: Some(format!("127.0.0.1:{}", port)),
+            ..Default::default()
+        };
+        let run = tokio::spawn(async move {
+            let mut exporter = MemoryExporter::new();
+            let summary = run_pipeline(&config, fetcher, &mut exporter).await;
+            (summary, exporter)
+        });
+
+        let url = format!("http://127.0.0.1:{}/stats", port);
+        let mut stats = serde_json::Value::Null;
+        for _ in 0..200 {
+            if let Ok(resp) = reqwest::get(&url).await {
+                stats = resp.json().await.unwrap();
+                if stats["fetch"]["files_listed"] == 1 {
+                    break;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(stats["fetch"]["files_listed"], 1);
+        assert_eq!(stats["fetch"]["files_fetched"], 0);
+        assert!(stats["export"].is_null());
+
+        release.notify_one();
+        let (summary, exporter) = run.await.unwrap();
+
+        assert_eq!(summary.unwrap().assignments_exported, 1);
+        assert_eq!(exporter.assignments.len(), 1);
+        assert!(reqwest::get(&url).await.is_err());
+    }
+}
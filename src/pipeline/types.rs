@@ -0,0 +1,133 @@
+use super::progress::ProgressObserver;
+use crate::fetch::{available_parallelism, FetchOptions};
+use crate::parse::{AssignmentParser, DefaultAssignmentParser};
+use crate::utils::AssignmentDigestStrategy;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Configuration of a single pipeline run.
+///
+/// Export-related settings live with the selected `Exporter`, since they depend on the backend.
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    /// Base URL of the CollecTor instance (e.g., "https://collector.torproject.org").
+    pub base_url: String,
+    /// Directories to fetch bridge pool assignment files from (e.g., "recent/bridge-pool-assignments").
+    pub dirs: Vec<String>,
+    /// Minimum last-modified timestamp in milliseconds (0 includes all files).
+    pub min_last_modified: i64,
+    /// Fetch concurrency limits.
+    pub fetch: FetchOptions,
+    /// Maximum number of files parsed in parallel, on blocking threads. Defaults to
+    /// [`default_parse_concurrency`] of the available CPUs.
+    pub parse_concurrency: usize,
+    /// Address of the `/stats` endpoint served during the run (e.g., "127.0.0.1:9099"), if any.
+    pub stats_addr: Option<String>,
+    /// If `true`, parsed files keep their raw bytes for the exporter (needed for
+    /// `ExportOptions::store_raw`); otherwise only their digest is kept.
+    pub keep_raw_content: bool,
+    /// If `true`, parsed assignments are checked with
+    /// [`validate_assignments`](crate::parse::validate_assignments) and inconsistencies are logged as
+    /// warnings; the run continues either way.
+    pub validate_assignments: bool,
+    /// If set, files that fail to fetch, parse or export and lines that fail to parse or export are
+    /// appended to this NDJSON file as [`DeadLetterEntry`]s, and skipped instead of failing the run.
+    /// Files are then exported one at a time rather than in a single export, so exporters must not
+    /// clear existing data on each export (e.g. `ExportOptions::clear`).
+    pub dead_letter: Option<PathBuf>,
+    /// If `true`, the run fails before exporting if parsing produced any warning: a skipped line or,
+    /// with `dead_letter`, a skipped file, a malformed fingerprint or value (see [`format_warnings`](crate::parse::format_warnings)), or,
+    /// with `validate_assignments`, an inconsistent assignment.
+    pub warnings_as_errors: bool,
+    /// If `true`, the run fails before exporting if two parsed documents with different content share
+    /// a file digest (see [`DigestCollisionCheck`](crate::utils::DigestCollisionCheck)). Each digest is
+    /// checked against the bytes it was computed from: the whole file, or a single document of a file
+    /// concatenating several.
+    pub check_digest_collisions: bool,
+    /// How assignment digests are computed while parsing.
+    pub assignment_digest_strategy: AssignmentDigestStrategy,
+    /// Turns assignment strings into fields for `validate_assignments` and `warnings_as_errors`.
+    /// Should be the exporter's parser (`ExportOptions::assignment_parser`), so the checks see the
+    /// same values that are exported.
+    pub assignment_parser: Arc<dyn AssignmentParser>,
+    /// Receives the progress of each stage, e.g. to draw progress bars. `None` reports nothing beyond
+    /// the usual log lines.
+    pub progress: Option<Arc<dyn ProgressObserver>>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "https://collector.torproject.org".to_string(),
+            dirs: vec!["recent/bridge-pool-assignments".to_string()],
+            min_last_modified: 0,
+            fetch: FetchOptions::default(),
+            parse_concurrency: default_parse_concurrency(available_parallelism()),
+            stats_addr: None,
+            keep_raw_content: false,
+            validate_assignments: false,
+            dead_letter: None,
+            warnings_as_errors: false,
+            check_digest_collisions: false,
+            assignment_digest_strategy: AssignmentDigestStrategy::default(),
+            assignment_parser: Arc::new(DefaultAssignmentParser),
+            progress: None,
+        }
+    }
+}
+
+/// Returns the default number of files parsed in parallel on a machine with `parallelism` CPUs.
+///
+/// Parsing is CPU-bound, so one file per CPU is parsed at a time.
+///
+/// # Arguments
+///
+/// * `parallelism` - The number of CPUs available, e.g. from
+///   [`available_parallelism`](crate::fetch::available_parallelism).
+///
+/// # Returns
+///
+/// The default parse concurrency, at least 1.
+pub fn default_parse_concurrency(parallelism: usize) -> usize {
+    parallelism.max(1)
+}
+
+/// A failed item recorded in the dead-letter file, for a later targeted reprocessing run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeadLetterEntry {
+    /// The stage at which the item failed.
+    pub stage: DeadLetterStage,
+    /// Relative path of the file.
+    pub path: String,
+    /// Hex-encoded SHA-256 digest of the file, if it was fetched.
+    pub digest: Option<String>,
+    /// The raw line, if only a line of the file failed.
+    pub line: Option<String>,
+    /// The error, including its causes.
+    pub error: String,
+}
+
+/// Pipeline stages that can produce [`DeadLetterEntry`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterStage {
+    Fetch,
+    Parse,
+    Export,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the default parse concurrency for a range of CPU counts.
+    #[test]
+    fn test_default_parse_concurrency() {
+        assert_eq!(default_parse_concurrency(0), 1);
+        assert_eq!(default_parse_concurrency(1), 1);
+        assert_eq!(default_parse_concurrency(6), 6);
+        assert_eq!(default_parse_concurrency(128), 128);
+    }
+}
@@ -0,0 +1,22 @@
+//! # Live Run Statistics
+//!
+//! This module provides a tiny HTTP server exposing the statistics of the current (or last) pipeline
+//! run as JSON on a `/stats` endpoint. It is a lightweight alternative to a full metrics setup for
+//! long-running deployments.
+//!
+//! ## Usage
+//!
+//! Start a [`StatsServer`] with a [`StatsSource`] closure producing the current [`RunStats`], and call
+//! [`StatsServer::shutdown`] once the run has finished. The pipeline does this automatically when
+//! `PipelineConfig::stats_addr` is set.
+//!
+//! ## Submodules
+//!
+//! - **server**: Contains the HTTP server.
+//! - **types**: Defines the statistics served.
+
+mod server;
+mod types;
+
+pub use server::{StatsServer, StatsSource};
+pub use types::RunStats;
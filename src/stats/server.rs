@@ -0,0 +1,131 @@
+use super::types::RunStats;
+use anyhow::{Context, Result as AnyhowResult};
+use log::{info, warn};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Produces a snapshot of the current run statistics for each `/stats` request.
+pub type StatsSource = Arc<dyn Fn() -> RunStats + Send + Sync>;
+
+/// Maximum size of a request head read by the server; anything longer is rejected.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// A minimal HTTP server answering `GET /stats` with the JSON-encoded [`RunStats`].
+///
+/// The server runs on a background task until [`StatsServer::shutdown`] is called.
+pub struct StatsServer {
+    local_addr: SocketAddr,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl StatsServer {
+    /// Binds to `addr` and starts serving statistics from `source`.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - Address to listen on (e.g., "127.0.0.1:9099").
+    /// * `source` - Closure producing the statistics to serve.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(StatsServer)` - The running server.
+    /// * `Err(anyhow::Error)` - An error if the address cannot be bound.
+    pub async fn start(addr: &str, source: StatsSource) -> AnyhowResult<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .context(format!("Failed to bind stats server to {}", addr))?;
+        let local_addr = listener.local_addr().context("Failed to get stats server address")?;
+        let (shutdown, mut shutdown_rx) = oneshot::channel();
+
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = &mut shutdown_rx => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => {
+                            let source = Arc::clone(&source);
+                            tokio::spawn(async move {
+                                if let Err(e) = handle_connection(stream, source).await {
+                                    warn!("Failed to serve stats request: {:?}", e);
+                                }
+                            });
+                        }
+                        Err(e) => warn!("Failed to accept stats connection: {}", e),
+                    },
+                }
+            }
+        });
+
+        info!("Serving run statistics on http://{}/stats", local_addr);
+        Ok(Self {
+            local_addr,
+            shutdown,
+            task,
+        })
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops accepting connections and waits for the server task to finish.
+    pub async fn shutdown(self) {
+        let _ = self.shutdown.send(());
+        if let Err(e) = self.task.await {
+            warn!("Stats server task failed: {:?}", e);
+        }
+        info!("Stats server on {} shut down", self.local_addr);
+    }
+}
+
+/// Reads a single request from `stream` and writes the matching response.
+///
+/// # Arguments
+///
+/// * `stream` - The accepted connection.
+/// * `source` - Closure producing the statistics to serve.
+///
+/// # Returns
+///
+/// * `Ok(())` - A response was written.
+/// * `Err(anyhow::Error)` - An error if reading, serializing, or writing fails.
+async fn handle_connection(mut stream: TcpStream, source: StatsSource) -> AnyhowResult<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") && head.len() < MAX_REQUEST_HEAD {
+        let n = stream.read(&mut buf).await.context("Failed to read request")?;
+        if n == 0 {
+            break;
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&head);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/stats")) => (
+            "200 OK",
+            serde_json::to_string(&source()).context("Failed to serialize stats")?,
+        ),
+        _ => ("404 Not Found", r#"{"error":"not found"}"#.to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(response.as_bytes())
+        .await
+        .context("Failed to write response")?;
+    stream.shutdown().await.context("Failed to close connection")?;
+    Ok(())
+}
@@ -0,0 +1,13 @@
+use crate::export::ExportSummary;
+use crate::fetch::FetchStats;
+use serde::Serialize;
+use std::fmt::Debug;
+
+/// Statistics of a pipeline run, as served on the `/stats` endpoint.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunStats {
+    /// Fetch progress, updated while files are being fetched.
+    pub fetch: FetchStats,
+    /// Export summary, `None` until the export has completed.
+    pub export: Option<ExportSummary>,
+}
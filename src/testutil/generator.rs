@@ -0,0 +1,130 @@
+use std::fmt::Write;
+
+/// Distribution methods used in generated assignments.
+const DISTRIBUTION_METHODS: &[&str] = &["email", "https", "moat", "settings", "reserved", "telegram"];
+/// Pluggable transports used in generated assignments.
+const TRANSPORTS: &[&str] = &["obfs4", "meek", "snowflake", "webtunnel", "scramblesuit"];
+/// Countries used in generated blocklists.
+const COUNTRIES: &[&str] = &["by", "cn", "ir", "ru", "tm", "uz"];
+/// Bandwidth classes used in generated assignments.
+const BANDWIDTHS: &[&str] = &["sufficient", "insufficient"];
+
+/// Generates a valid bridge pool assignment document with `entries` pseudo-random entries.
+///
+/// Fingerprints and assignments are derived from `seed` only, so the same arguments always produce
+/// the same document. Assignments cover the fields understood by the parser (transport, ip,
+/// blocklist, distributed, state, bandwidth, ratio), each present with some probability.
+///
+/// # Arguments
+///
+/// * `entries` - Number of bridge entries in the document.
+/// * `seed` - Seed of the pseudo-random generator.
+///
+/// # Returns
+///
+/// The document text, starting with a `bridge-pool-assignment` header line.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// use bridge_pool_assignments::testutil::generate_bridge_pool_file;
+/// let content = generate_bridge_pool_file(1000, 42);
+/// assert_eq!(content.lines().count(), 1001);
+/// ```
+pub fn generate_bridge_pool_file(entries: usize, seed: u64) -> String {
+    let mut rng = SplitMix64(seed);
+    let mut content = String::from("bridge-pool-assignment 2022-04-09 00:29:37\n");
+
+    for _ in 0..entries {
+        let fingerprint = format!(
+            "{:016x}{:016x}{:08x}",
+            rng.next_u64(),
+            rng.next_u64(),
+            rng.next_u64() as u32
+        );
+        let _ = write!(content, "{} {}", fingerprint, rng.pick(DISTRIBUTION_METHODS));
+        if rng.chance(70) {
+            let _ = write!(content, " transport={}", rng.pick(TRANSPORTS));
+        }
+        if rng.chance(50) {
+            let _ = write!(content, " ip={}", if rng.chance(80) { 4 } else { 6 });
+        }
+        if rng.chance(20) {
+            let first = rng.pick(COUNTRIES);
+            let second = rng.pick(COUNTRIES);
+            if first == second {
+                let _ = write!(content, " blocklist={}", first);
+            } else {
+                let _ = write!(content, " blocklist={},{}", first, second);
+            }
+        }
+        if rng.chance(50) {
+            let _ = write!(content, " distributed={}", rng.chance(50));
+        }
+        if rng.chance(50) {
+            let _ = write!(content, " state=functional");
+        }
+        if rng.chance(50) {
+            let _ = write!(content, " bandwidth={}", rng.pick(BANDWIDTHS));
+        }
+        if rng.chance(30) {
+            let _ = write!(content, " ratio={:.3}", (rng.next_u64() % 1000) as f32 / 1000.0);
+        }
+        content.push('\n');
+    }
+
+    content
+}
+
+/// SplitMix64, a small and fast pseudo-random generator that is good enough for test data and keeps
+/// the crate free of an extra dependency.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    /// Returns the next pseudo-random value.
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns `true` with a probability of `percent`%.
+    fn chance(&mut self, percent: u64) -> bool {
+        self.next_u64() % 100 < percent
+    }
+
+    /// Returns a pseudo-randomly chosen element of `choices`.
+    fn pick<'a>(&mut self, choices: &[&'a str]) -> &'a str {
+        choices[(self.next_u64() % choices.len() as u64) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::BridgePoolFile;
+    use crate::parse::parse_bridge_pool_files;
+
+    /// Tests that generated documents parse to exactly the requested number of entries and are
+    /// reproducible for a fixed seed.
+    #[test]
+    fn test_generate_bridge_pool_file() {
+        let content = generate_bridge_pool_file(500, 7);
+
+        let parsed = parse_bridge_pool_files(vec![BridgePoolFile {
+            path: "generated".to_string(),
+            last_modified: 0,
+            content: content.clone(),
+            raw_content: content.as_bytes().to_vec(),
+        }])
+        .unwrap();
+
+        assert_eq!(parsed[0].entries.len(), 500);
+        assert!(parsed[0].entries.keys().all(|fingerprint| fingerprint.len() == 40));
+        assert_eq!(generate_bridge_pool_file(500, 7), content);
+        assert_ne!(generate_bridge_pool_file(500, 8), content);
+        assert_eq!(generate_bridge_pool_file(0, 7).lines().count(), 1);
+    }
+}
@@ -0,0 +1,20 @@
+//! # Synthetic Test Data
+//!
+//! This module generates realistic bridge pool assignment documents without network access or real
+//! data, for stress tests and benchmarks of the parse and export stages. Generation is deterministic:
+//! the same seed always yields the same document.
+//!
+//! The module is only compiled for the crate's own tests or with the `testutil` feature enabled.
+//!
+//! ## Usage
+//!
+//! Call `generate_bridge_pool_file` with the number of entries and a seed, and feed the result to the
+//! parser like any fetched file.
+//!
+//! ## Submodules
+//!
+//! - **generator**: Contains the document generator and its pseudo-random number generator.
+
+mod generator;
+
+pub use generator::generate_bridge_pool_file;
@@ -0,0 +1,64 @@
+use serde::Serialize;
+
+/// What a build of this crate supports, as reported by [`capabilities`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// Crate version (e.g., "1.0.0").
+    pub version: &'static str,
+    /// Export backends compiled into this build, by their `--backend` name.
+    pub backends: Vec<&'static str>,
+    /// Optional Cargo features enabled in this build.
+    pub features: Vec<&'static str>,
+}
+
+/// Describes the capabilities of the running build: its version, compiled-in export backends and
+/// enabled optional features.
+///
+/// # Returns
+///
+/// A `Capabilities` value determined at compile time.
+///
+/// # Examples
+///
+/// ```rust
+/// use bridge_pool_assignments::utils::capabilities;
+/// let capabilities = capabilities();
+/// assert!(capabilities.backends.contains(&"postgres"));
+/// ```
+pub fn capabilities() -> Capabilities {
+    let optional_backends = [("clickhouse", cfg!(feature = "clickhouse"))];
+    let optional_features = [
+        ("clickhouse", cfg!(feature = "clickhouse")),
+        ("progress", cfg!(feature = "progress")),
+        ("testutil", cfg!(feature = "testutil")),
+    ];
+    let enabled = |options: &[(&'static str, bool)]| -> Vec<&'static str> {
+        options.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect()
+    };
+
+    let mut backends = vec!["postgres", "memory", "ndjson"];
+    backends.extend(enabled(&optional_backends));
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        backends,
+        features: enabled(&optional_features),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the reported version and features match the build.
+    #[test]
+    fn test_capabilities_match_build() {
+        let capabilities = capabilities();
+
+        assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+        assert!(capabilities.backends.contains(&"postgres"));
+        assert_eq!(capabilities.backends.contains(&"clickhouse"), cfg!(feature = "clickhouse"));
+        assert_eq!(capabilities.features.contains(&"clickhouse"), cfg!(feature = "clickhouse"));
+        assert_eq!(capabilities.features.contains(&"progress"), cfg!(feature = "progress"));
+        assert_eq!(capabilities.features.contains(&"testutil"), cfg!(feature = "testutil"));
+    }
+}
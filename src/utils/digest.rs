@@ -1,4 +1,100 @@
-use sha2::{Digest, Sha256};
+use anyhow::Result as AnyhowResult;
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
+use std::fmt;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::str::FromStr;
+
+/// Length of a hex-encoded SHA-256 digest.
+const DIGEST_HEX_LEN: usize = 64;
+
+/// A SHA-256 digest as a validated, 64-character lowercase hex string.
+///
+/// Digests produced by this crate are always lowercase, but digests arriving from elsewhere (index
+/// metadata, database rows, user input) may be uppercase or padded. Constructing them through
+/// [`Digest::parse`] canonicalizes them, so equal digests always compare equal and map to the same
+/// primary key.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Digest(String);
+
+impl Digest {
+    /// Parses and canonicalizes a hex-encoded SHA-256 digest.
+    ///
+    /// Surrounding whitespace is trimmed and uppercase hex digits are lowercased.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The hex-encoded digest.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Digest)` - The canonical digest.
+    /// * `Err(anyhow::Error)` - An error if the value is not 64 hex characters.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use bridge_pool_assignments::utils::Digest;
+    /// let digest = Digest::parse(&"AB".repeat(32)).unwrap();
+    /// assert_eq!(digest.as_str(), "ab".repeat(32));
+    /// ```
+    pub fn parse(value: &str) -> AnyhowResult<Self> {
+        let value = value.trim();
+        if value.len() != DIGEST_HEX_LEN || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(anyhow::anyhow!(
+                "Invalid digest {:?}: expected {} hex characters",
+                value,
+                DIGEST_HEX_LEN
+            ));
+        }
+        Ok(Self(value.to_ascii_lowercase()))
+    }
+
+    /// Returns the digest as a lowercase hex string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Creates a digest from its 32 raw bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(hex::encode(bytes))
+    }
+
+    /// Returns the 32 raw bytes of the digest.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        hex::decode_to_slice(&self.0, &mut bytes).expect("digest is 64 hex characters by construction");
+        bytes
+    }
+
+    /// Wraps the output of a SHA-256 hasher, which is canonical by construction.
+    fn from_hasher(hasher: Sha256) -> Self {
+        Self(hex::encode(hasher.finalize()))
+    }
+}
+
+impl FromStr for Digest {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> AnyhowResult<Self> {
+        Self::parse(value)
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for Digest {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
 
 /// Computes a digest for a file using its raw content.
 ///
@@ -11,34 +107,137 @@ use sha2::{Digest, Sha256};
 ///
 /// # Returns
 ///
-/// A hexadecimal string representation of the SHA-256 digest.
-pub fn compute_file_digest(raw_content: &[u8]) -> String {
+/// The SHA-256 digest of the content.
+pub fn compute_file_digest(raw_content: &[u8]) -> Digest {
     let mut hasher = Sha256::new();
     hasher.update(raw_content);
-    let result = hasher.finalize();
-    hex::encode(result)
+    Digest::from_hasher(hasher)
+}
+
+/// Detects different contents sharing a file digest within a run.
+///
+/// SHA-256 collisions are infeasible, so two different contents with the same digest point at a bug
+/// in how digests are computed (e.g. hashing the wrong bytes). Left undetected, the second file would
+/// silently be dropped by the exporters' `ON CONFLICT (digest)` handling. Each recorded digest is
+/// remembered with an independent 64-bit hash of its content, so the check costs one extra pass over
+/// each file but keeps no content.
+#[derive(Debug, Default)]
+pub struct DigestCollisionCheck {
+    seen: HashMap<Digest, (u64, String)>,
+}
+
+impl DigestCollisionCheck {
+    /// Creates a check that has seen no digests yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the digest computed for a document's content.
+    ///
+    /// # Arguments
+    ///
+    /// * `digest` - The digest computed for `content`.
+    /// * `content` - The raw bytes the digest was computed from.
+    /// * `path` - Path of the file holding the document, for the error message.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - The digest is new, or was seen before for the same content.
+    /// * `Err(anyhow::Error)` - The digest was seen before for different content.
+    pub fn record(&mut self, digest: &Digest, content: &[u8], path: &str) -> AnyhowResult<()> {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(content);
+        let content_hash = hasher.finish();
+        match self.seen.get(digest) {
+            Some((seen_hash, seen_path)) if *seen_hash != content_hash => Err(anyhow::anyhow!(
+                "Digest collision: {} and {} have different content but the same digest {}",
+                seen_path,
+                path,
+                digest
+            )),
+            Some(_) => Ok(()),
+            None => {
+                self.seen.insert(digest.clone(), (content_hash, path.to_string()));
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the number of distinct digests recorded.
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Returns `true` if no digest has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
+
+/// How the raw line and the file digest are combined into an assignment digest.
+///
+/// Every variant hashes both with SHA-256, so digests stay unique across files; they differ in order
+/// and in whether the file digest enters as its 64-character lowercase hex string or as its 32 raw
+/// bytes. Changing the strategy changes every assignment digest, so rows exported under another
+/// strategy are not recognized as already present.
+///
+/// Compatibility with assignment digests computed by any other implementation has not been verified
+/// against that implementation's source; the variants exist so a deployment can match whichever
+/// construction its existing rows were written with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AssignmentDigestStrategy {
+    /// `SHA256(raw_line || hex(file_digest))`. The construction used by earlier versions of this
+    /// crate, so it keeps their stored digests valid.
+    #[default]
+    LineThenHexFileDigest,
+    /// `SHA256(raw_line || file_digest_bytes)`.
+    LineThenFileDigestBytes,
+    /// `SHA256(file_digest_bytes || raw_line)`.
+    FileDigestBytesThenLine,
+    /// `SHA256(hex(file_digest) || raw_line)`.
+    HexFileDigestThenLine,
 }
 
 /// Computes a digest for an individual assignment using its raw line bytes and file digest.
 ///
 /// Following the maintainer's recommendation and the original implementation,
 /// this calculates a SHA-256 hash of the raw line bytes combined with the file digest
-/// to ensure uniqueness across files.
+/// to ensure uniqueness across files. How the two are combined is chosen by `strategy`.
 ///
 /// # Arguments
 ///
 /// * `raw_line` - The raw bytes of the assignment line.
 /// * `file_digest` - The digest of the file this assignment belongs to.
+/// * `strategy` - How the line and the file digest are combined.
 ///
 /// # Returns
 ///
-/// A hexadecimal string representation of the SHA-256 digest.
-pub fn compute_assignment_digest(raw_line: &[u8], file_digest: &str) -> String {
+/// The SHA-256 digest of the assignment.
+pub fn compute_assignment_digest(
+    raw_line: &[u8],
+    file_digest: &Digest,
+    strategy: AssignmentDigestStrategy,
+) -> Digest {
     let mut hasher = Sha256::new();
-    hasher.update(raw_line);
-    hasher.update(file_digest.as_bytes()); // Include file digest to ensure uniqueness
-    let result = hasher.finalize();
-    hex::encode(result)
+    match strategy {
+        AssignmentDigestStrategy::LineThenHexFileDigest => {
+            hasher.update(raw_line);
+            hasher.update(file_digest.as_str().as_bytes());
+        }
+        AssignmentDigestStrategy::LineThenFileDigestBytes => {
+            hasher.update(raw_line);
+            hasher.update(file_digest.to_bytes());
+        }
+        AssignmentDigestStrategy::FileDigestBytesThenLine => {
+            hasher.update(file_digest.to_bytes());
+            hasher.update(raw_line);
+        }
+        AssignmentDigestStrategy::HexFileDigestThenLine => {
+            hasher.update(file_digest.as_str().as_bytes());
+            hasher.update(raw_line);
+        }
+    }
+    Digest::from_hasher(hasher)
 }
 
 #[cfg(test)]
@@ -49,29 +248,104 @@ mod tests {
     fn test_compute_file_digest() {
         let content = b"bridge-pool-assignment 2022-04-09 00:29:37";
         let digest = compute_file_digest(content);
-        assert!(!digest.is_empty());
-        assert_eq!(digest.len(), 64); // SHA-256 produces a 32-byte (64 hex char) digest
+        assert_eq!(digest.as_str().len(), 64); // SHA-256 produces a 32-byte (64 hex char) digest
     }
 
     #[test]
     fn test_compute_assignment_digest() {
         let line = b"005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4";
-        let file_digest = "abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890";
-        let digest = compute_assignment_digest(line, file_digest);
-        assert!(!digest.is_empty());
-        assert_eq!(digest.len(), 64);
+        let file_digest = Digest::parse("abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890").unwrap();
+        let digest = compute_assignment_digest(line, &file_digest, AssignmentDigestStrategy::default());
+        assert_eq!(digest.as_str().len(), 64);
+    }
+
+    /// Tests each strategy against a reference vector computed independently with Python's `hashlib`,
+    /// e.g. `sha256(line + file_digest_hex.encode())` for the default strategy and
+    /// `sha256(bytes.fromhex(file_digest_hex) + line)` for `FileDigestBytesThenLine`.
+    #[test]
+    fn test_assignment_digest_strategies() {
+        let line = b"005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4";
+        let file_digest = Digest::parse("45df2d36098298d289de00a6891614e09284a4be5466026f9e033965a0a73b41").unwrap();
+        let cases = [
+            (
+                AssignmentDigestStrategy::LineThenHexFileDigest,
+                "d896a7021ec058ee3d835c7d7cec30677dea44c5e8b28de8a0f8f8b9b480448e",
+            ),
+            (
+                AssignmentDigestStrategy::LineThenFileDigestBytes,
+                "e4da1533043299aaf6b0f33bfb709faf72756b882de61715a0e1736eabd1df74",
+            ),
+            (
+                AssignmentDigestStrategy::FileDigestBytesThenLine,
+                "72669228840445c782e45b1c00c2527f095a417321847b91d9d2228512cbc9cc",
+            ),
+            (
+                AssignmentDigestStrategy::HexFileDigestThenLine,
+                "703037a72cce3659a3af1be9d74fbf015ff20b593ae7bc50617fbeb2b9a8df33",
+            ),
+        ];
+
+        for (strategy, expected) in cases {
+            assert_eq!(compute_assignment_digest(line, &file_digest, strategy).as_str(), expected, "{:?}", strategy);
+        }
+        assert_eq!(AssignmentDigestStrategy::default(), AssignmentDigestStrategy::LineThenHexFileDigest);
     }
 
     #[test]
     fn test_assignment_digests_are_unique_with_same_line() {
         let line = b"005fd4d7decbb250055b861579e6fdc79ad17bee email transport=obfs4";
-        let file_digest1 = "abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890";
-        let file_digest2 = "fedcba0987654321fedcba0987654321fedcba0987654321fedcba0987654321";
+        let file_digest1 = Digest::parse("abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890").unwrap();
+        let file_digest2 = Digest::parse("fedcba0987654321fedcba0987654321fedcba0987654321fedcba0987654321").unwrap();
         
-        let digest1 = compute_assignment_digest(line, file_digest1);
-        let digest2 = compute_assignment_digest(line, file_digest2);
+        let digest1 = compute_assignment_digest(line, &file_digest1, AssignmentDigestStrategy::default());
+        let digest2 = compute_assignment_digest(line, &file_digest2, AssignmentDigestStrategy::default());
         
         // Same line but different file digests should produce different assignment digests
         assert_ne!(digest1, digest2);
     }
+
+    /// Tests that uppercase or padded input is canonicalized to the same digest as lowercase input.
+    #[test]
+    fn test_digest_parse_normalizes_uppercase() {
+        let lower = "abcdef1234567890abcdef1234567890abcdef1234567890abcdef1234567890";
+        let upper = format!("  {}\n", lower.to_uppercase());
+
+        let digest = Digest::parse(&upper).unwrap();
+
+        assert_eq!(digest.as_str(), lower);
+        assert_eq!(digest, Digest::parse(lower).unwrap());
+
+        let computed = compute_file_digest(b"bridge-pool-assignment 2022-04-09 00:29:37");
+        assert_eq!(computed.as_str().to_uppercase().parse::<Digest>().unwrap(), computed);
+    }
+
+    /// Tests that values that are not 64 hex characters are rejected.
+    #[test]
+    fn test_digest_parse_rejects_invalid() {
+        assert!(Digest::parse("").is_err());
+        assert!(Digest::parse("abcdef").is_err());
+        assert!(Digest::parse(&"a".repeat(65)).is_err());
+        assert!(Digest::parse(&"g".repeat(64)).is_err());
+        assert!(Digest::parse(&format!("{}-", "a".repeat(63))).is_err());
+    }
+
+    /// Tests that different contents reported with the same digest, as a broken hasher would produce,
+    /// are rejected, while the same content seen twice is accepted.
+    #[test]
+    fn test_digest_collision_check() {
+        // Stands in for a hasher that ignores its input
+        let stub_digest = |_content: &[u8]| Digest::parse(&"ab".repeat(32)).unwrap();
+        let mut check = DigestCollisionCheck::new();
+
+        check.record(&stub_digest(b"first"), b"first", "recent/a").unwrap();
+        check.record(&stub_digest(b"first"), b"first", "archive/a").unwrap();
+        let err = check.record(&stub_digest(b"second"), b"second", "recent/b").unwrap_err();
+
+        assert!(err.to_string().contains("recent/a and recent/b"), "{}", err);
+        assert_eq!(check.len(), 1);
+        check
+            .record(&compute_file_digest(b"second"), b"second", "recent/b")
+            .unwrap();
+        assert_eq!(check.len(), 2);
+    }
 } 
\ No newline at end of file
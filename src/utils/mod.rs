@@ -5,8 +5,21 @@
 //!
 //! ## Submodules
 //!
-//! - **digest**: Contains functions for calculating SHA-256 digests for files and assignments.
+//! - **capabilities**: Reports the crate version and the backends and features compiled into a build.
+//! - **digest**: Contains functions for calculating SHA-256 digests for files and assignments
+//!   (combined as chosen by an `AssignmentDigestStrategy`), the `Digest` type holding a canonical
+//!   (lowercase hex) digest, and an in-run check for colliding file digests.
+//! - **timestamp**: Contains range-checked conversions between dates and milliseconds since the epoch,
+//!   and the parsing of relative ages such as "30d".
 
+mod capabilities;
 mod digest;
+mod timestamp;
 
-pub use digest::{compute_file_digest, compute_assignment_digest}; 
\ No newline at end of file
+pub use capabilities::{capabilities, Capabilities};
+pub use digest::{
+    compute_file_digest, compute_assignment_digest, AssignmentDigestStrategy, Digest, DigestCollisionCheck,
+};
+pub use timestamp::{
+    datetime_from_millis, millis_from_naive_utc, min_last_modified_for_max_age, parse_max_age,
+}; 
\ No newline at end of file
@@ -0,0 +1,187 @@
+use anyhow::{Context, Result as AnyhowResult};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::time::Duration;
+
+/// Earliest supported timestamp (0001-01-01 00:00:00 UTC) in milliseconds since the Unix epoch.
+const MIN_TIMESTAMP_MILLIS: i64 = -62_135_596_800_000;
+/// Latest supported timestamp (9999-12-31 23:59:59.999 UTC) in milliseconds since the Unix epoch.
+const MAX_TIMESTAMP_MILLIS: i64 = 253_402_300_799_999;
+
+/// Converts a UTC date and time into milliseconds since the Unix epoch.
+///
+/// Only years 1 to 9999 are supported; that comfortably covers any real document while keeping every
+/// timestamp representable in PostgreSQL and in four-digit `%Y` formatting.
+///
+/// # Arguments
+///
+/// * `datetime` - The date and time, interpreted as UTC.
+///
+/// # Returns
+///
+/// * `Ok(i64)` - Milliseconds since the Unix epoch.
+/// * `Err(anyhow::Error)` - An error if the timestamp is outside the supported range.
+pub fn millis_from_naive_utc(datetime: NaiveDateTime) -> AnyhowResult<i64> {
+    let millis = datetime
+        .and_utc()
+        .timestamp()
+        .checked_mul(1000)
+        .and_then(|millis| millis.checked_add(i64::from(datetime.and_utc().timestamp_subsec_millis())))
+        .with_context(|| format!("Timestamp {} overflows milliseconds since the epoch", datetime))?;
+    check_range(millis).with_context(|| format!("Timestamp {} is out of range", datetime))?;
+    Ok(millis)
+}
+
+/// Converts milliseconds since the Unix epoch into a UTC date and time.
+///
+/// # Arguments
+///
+/// * `millis` - Milliseconds since the Unix epoch.
+///
+/// # Returns
+///
+/// * `Ok(DateTime<Utc>)` - The corresponding date and time.
+/// * `Err(anyhow::Error)` - An error if the timestamp is outside the supported range (years 1 to 9999).
+pub fn datetime_from_millis(millis: i64) -> AnyhowResult<DateTime<Utc>> {
+    check_range(millis)?;
+    DateTime::<Utc>::from_timestamp_millis(millis)
+        .with_context(|| format!("Timestamp {} ms cannot be represented as a date", millis))
+}
+
+/// Parses a relative age such as "30d", "12h" or "90m" (days, hours or minutes).
+///
+/// # Arguments
+///
+/// * `value` - A non-negative whole number followed by `d`, `h` or `m`.
+///
+/// # Returns
+///
+/// * `Ok(Duration)` - The age as a duration.
+/// * `Err(anyhow::Error)` - An error if the number or the suffix is missing or invalid.
+///
+/// # Examples
+///
+/// ```rust
+/// use bridge_pool_assignments::utils::parse_max_age;
+/// use std::time::Duration;
+/// assert_eq!(parse_max_age("2h").unwrap(), Duration::from_secs(2 * 60 * 60));
+/// ```
+pub fn parse_max_age(value: &str) -> AnyhowResult<Duration> {
+    let split = value.len() - value.chars().last().map_or(0, char::len_utf8);
+    let (number, suffix) = value.split_at(split);
+    let unit_secs: u64 = match suffix {
+        "d" => 24 * 60 * 60,
+        "h" => 60 * 60,
+        "m" => 60,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Invalid age {:?}: expected a number followed by d, h or m (e.g. 30d)",
+                value
+            ))
+        }
+    };
+    let count: u64 = number
+        .parse()
+        .with_context(|| format!("Invalid age {:?}: {:?} is not a whole number", value, number))?;
+    count
+        .checked_mul(unit_secs)
+        .map(Duration::from_secs)
+        .with_context(|| format!("Age {:?} is too large", value))
+}
+
+/// Computes the earliest last-modified time still within `max_age` of `now_millis`.
+///
+/// # Arguments
+///
+/// * `now_millis` - The current time in milliseconds since the Unix epoch.
+/// * `max_age` - The maximum age of files to keep.
+///
+/// # Returns
+///
+/// * `Ok(i64)` - `now_millis - max_age` in milliseconds since the Unix epoch.
+/// * `Err(anyhow::Error)` - An error if the result is outside the supported range (years 1 to 9999).
+pub fn min_last_modified_for_max_age(now_millis: i64, max_age: Duration) -> AnyhowResult<i64> {
+    let min_last_modified = i64::try_from(max_age.as_millis())
+        .ok()
+        .and_then(|age_millis| now_millis.checked_sub(age_millis))
+        .with_context(|| format!("Maximum age {:?} reaches before the epoch range", max_age))?;
+    check_range(min_last_modified)
+        .with_context(|| format!("Maximum age {:?} reaches too far into the past", max_age))?;
+    Ok(min_last_modified)
+}
+
+/// Ensures `millis` lies within the supported range of years 1 to 9999.
+fn check_range(millis: i64) -> AnyhowResult<()> {
+    if !(MIN_TIMESTAMP_MILLIS..=MAX_TIMESTAMP_MILLIS).contains(&millis) {
+        return Err(anyhow::anyhow!(
+            "Timestamp {} ms is outside the supported range (years 1 to 9999)",
+            millis
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// Returns the first instant of `date` in milliseconds since the Unix epoch.
+    fn date_millis(date: NaiveDate) -> i64 {
+        date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis()
+    }
+
+    /// Tests that the range constants match the first and last supported instants.
+    #[test]
+    fn test_range_bounds() {
+        assert_eq!(MIN_TIMESTAMP_MILLIS, date_millis(NaiveDate::from_ymd_opt(1, 1, 1).unwrap()));
+        assert_eq!(
+            MAX_TIMESTAMP_MILLIS + 1,
+            date_millis(NaiveDate::from_ymd_opt(10000, 1, 1).unwrap())
+        );
+    }
+
+    /// Tests converting timestamps at and beyond the supported range in both directions.
+    #[test]
+    fn test_extreme_timestamps() {
+        assert!(datetime_from_millis(MIN_TIMESTAMP_MILLIS).is_ok());
+        assert!(datetime_from_millis(MAX_TIMESTAMP_MILLIS).is_ok());
+        for millis in [i64::MIN, MIN_TIMESTAMP_MILLIS - 1, MAX_TIMESTAMP_MILLIS + 1, i64::MAX] {
+            let err = datetime_from_millis(millis).unwrap_err();
+            assert!(err.to_string().contains("outside the supported range"), "{}", err);
+        }
+
+        let far_future = NaiveDate::from_ymd_opt(200_000, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let far_past = NaiveDate::from_ymd_opt(-200_000, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        for datetime in [far_future, far_past, NaiveDateTime::MAX, NaiveDateTime::MIN] {
+            let err = millis_from_naive_utc(datetime).unwrap_err();
+            assert!(format!("{:#}", err).contains("outside the supported range"), "{:#}", err);
+        }
+        let published = NaiveDate::from_ymd_opt(2022, 4, 9).unwrap().and_hms_opt(0, 29, 37).unwrap();
+        assert_eq!(millis_from_naive_utc(published).unwrap(), 1649464177000);
+    }
+
+    /// Tests parsing ages with each supported suffix and rejecting malformed ones.
+    #[test]
+    fn test_parse_max_age() {
+        assert_eq!(parse_max_age("30d").unwrap(), Duration::from_secs(30 * 24 * 60 * 60));
+        assert_eq!(parse_max_age("12h").unwrap(), Duration::from_secs(12 * 60 * 60));
+        assert_eq!(parse_max_age("90m").unwrap(), Duration::from_secs(90 * 60));
+        assert_eq!(parse_max_age("0d").unwrap(), Duration::ZERO);
+        for invalid in ["", "d", "30", "30s", "-1d", "1.5h", "30 d", "x30d"] {
+            assert!(parse_max_age(invalid).is_err(), "{:?}", invalid);
+        }
+        assert!(parse_max_age(&format!("{}d", u64::MAX)).is_err());
+    }
+
+    /// Tests computing the minimum last-modified time relative to a fixed "now".
+    #[test]
+    fn test_min_last_modified_for_max_age() {
+        let now = 1_649_464_177_000;
+        assert_eq!(
+            min_last_modified_for_max_age(now, parse_max_age("30d").unwrap()).unwrap(),
+            now - 30 * 24 * 60 * 60 * 1000
+        );
+        assert_eq!(min_last_modified_for_max_age(now, Duration::ZERO).unwrap(), now);
+        assert!(min_last_modified_for_max_age(now, parse_max_age("1000000000d").unwrap()).is_err());
+    }
+}